@@ -1,10 +1,32 @@
 use anyhow::Context as _;
 use pps_engine::{
-    apis::compile::{CompileRequest, CompileUpdate},
-    operation::Outcome,
+    apis::compile::{CompileRequest, CompileUpdate, GitSource},
+    operation::{JsonLinesWriter, Outcome},
 };
 use std::path::PathBuf;
 
+/// If this environment variable is set, progress updates are additionally
+/// written as JSON Lines to the file it points to (`-` means stdout).
+const PROGRESS_JSON_ENV: &str = "PPS_PROGRESS_JSON";
+
+/// Returns JSON progress writer, and whether it writes to stdout
+fn open_progress_json() -> anyhow::Result<Option<(JsonLinesWriter<Box<dyn std::io::Write>>, bool)>>
+{
+    let path = match std::env::var_os(PROGRESS_JSON_ENV) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if path == "-" {
+        return Ok(Some((
+            JsonLinesWriter::new(Box::new(std::io::stdout())),
+            true,
+        )));
+    }
+    let file = std::fs::File::create(&path)
+        .with_context(|| format!("failed to create {}", PathBuf::from(&path).display()))?;
+    Ok(Some((JsonLinesWriter::new(Box::new(file)), false)))
+}
+
 #[derive(Debug, clap::Clap)]
 pub struct CompileArgs {
     /// Path to problem package root
@@ -16,6 +38,27 @@ pub struct CompileArgs {
     /// Rewrite dir
     #[clap(long, short = 'F')]
     pub force: bool,
+    /// Only regenerate tests that changed since previous build
+    #[clap(long, short = 'I')]
+    pub incremental: bool,
+    /// Only regenerate given test of already built package (can be repeated)
+    #[clap(long = "test", short = 'T')]
+    pub tests: Vec<usize>,
+    /// Only regenerate correct answers of already built package (of --test
+    /// tests, or of all tests), rebuilding answer solutions
+    #[clap(long)]
+    pub answers_only: bool,
+    /// Additionally pack built package into zip archive (if given, must be
+    /// repeated for each --out)
+    #[clap(long = "zip")]
+    pub zip_path: Vec<PathBuf>,
+    /// Clone problem sources from this git repository. --pkg paths are
+    /// then relative to repository root.
+    #[clap(long = "git")]
+    pub git_url: Option<String>,
+    /// Together with --git, branch, tag or commit to build
+    #[clap(long)]
+    pub git_ref: Option<String>,
 }
 
 #[tracing::instrument(skip(compile_args))]
@@ -23,18 +66,52 @@ pub async fn exec(compile_args: CompileArgs) -> anyhow::Result<()> {
     if compile_args.out_path.len() != compile_args.pkg_path.len() {
         anyhow::bail!("count(--pkg) != count(--out)");
     }
+    if !compile_args.zip_path.is_empty()
+        && compile_args.zip_path.len() != compile_args.out_path.len()
+    {
+        anyhow::bail!("count(--zip) != count(--out)");
+    }
+    if compile_args.git_ref.is_some() && compile_args.git_url.is_none() {
+        anyhow::bail!("--git-ref requires --git");
+    }
     let jjs_path = std::env::var_os("JJS_PATH").context("JJS_PATH environment variable missing")?;
-    for (out_path, pkg_path) in compile_args.out_path.iter().zip(&compile_args.pkg_path) {
+    let mut progress_json = open_progress_json()?;
+    for (i, (out_path, pkg_path)) in compile_args
+        .out_path
+        .iter()
+        .zip(&compile_args.pkg_path)
+        .enumerate()
+    {
         let req = CompileRequest {
             out_path: out_path.clone(),
             problem_path: pkg_path.clone(),
             force: compile_args.force,
+            incremental: compile_args.incremental,
+            tests: compile_args.tests.clone(),
+            answers_only: compile_args.answers_only,
+            zip_path: compile_args.zip_path.get(i).cloned(),
+            git_source: compile_args.git_url.clone().map(|url| GitSource {
+                url,
+                git_ref: compile_args.git_ref.clone(),
+            }),
             jjs_path: jjs_path.clone().into(),
         };
         let mut op = pps_engine::apis::compile::exec(req);
         let mut notifier = None;
         while let Some(upd) = op.next_update().await {
+            if let Some((writer, to_stdout)) = &mut progress_json {
+                writer
+                    .write(&upd)
+                    .context("failed to write progress update")?;
+                if *to_stdout {
+                    // human-readable output would break JSON stream
+                    continue;
+                }
+            }
             match upd {
+                CompileUpdate::CloneRepository { url } => {
+                    println!("Cloning {}", url);
+                }
                 CompileUpdate::Warnings(warnings) => {
                     if !warnings.is_empty() {
                         eprintln!("{} warnings", warnings.len());
@@ -46,36 +123,86 @@ pub async fn exec(compile_args: CompileArgs) -> anyhow::Result<()> {
                 CompileUpdate::BuildSolution(solution_name) => {
                     println!("Building solution {}", &solution_name);
                 }
+                CompileUpdate::SkipSolution(solution_name) => {
+                    println!("Skipping solution {}", solution_name);
+                }
                 CompileUpdate::BuildTestgen(testgen_name) => {
                     println!("Building generator {}", testgen_name);
                 }
+                CompileUpdate::SkipTestgen(testgen_name) => {
+                    println!("Generator {} is up to date", testgen_name);
+                }
                 CompileUpdate::BuildChecker => {
                     println!("Building checker");
                 }
+                CompileUpdate::BuildOutputValidator => {
+                    println!("Building output validator");
+                }
                 CompileUpdate::GenerateTests { count } => {
                     notifier = Some(crate::progress_notifier::Notifier::new(count));
                 }
-                CompileUpdate::GenerateTest { test_id } => {
+                CompileUpdate::GenerateTest { test_id } => match notifier.as_mut() {
+                    Some(notifier) => notifier.maybe_notify(test_id),
+                    // separate test is regenerated
+                    None => println!("Generating test {}", test_id),
+                },
+                CompileUpdate::SkipTest { test_id } => {
                     notifier
                         .as_mut()
-                        .expect("GenerateTest received before GenerateTests")
+                        .expect("SkipTest received before GenerateTests")
                         .maybe_notify(test_id);
                 }
+                CompileUpdate::TestLimits { test_id, limits } => {
+                    tracing::debug!(test_id, limits = ?limits, "effective test limits");
+                }
+                CompileUpdate::GenerateAnswer { test_id } => {
+                    println!("Regenerating answer for test {}", test_id);
+                }
+                CompileUpdate::DuplicateTests { count } => {
+                    println!("Removed {} duplicate tests", count);
+                }
+                CompileUpdate::CheckerSelfTest { test_id } => {
+                    println!("Testing checker on test {}", test_id);
+                }
+                CompileUpdate::VerifyAnswer { test_id } => {
+                    println!("Verifying answer of test {}", test_id);
+                }
+                CompileUpdate::ValidateAnswer { test_id } => {
+                    println!("Validating answer format of test {}", test_id);
+                }
+                CompileUpdate::CheckExpectedVerdict { solution, test_id } => {
+                    println!(
+                        "Checking verdict of solution {} on test {}",
+                        solution, test_id
+                    );
+                }
                 CompileUpdate::CopyValuerConfig => {
                     println!("Valuer config");
                 }
+                CompileUpdate::RunPostBuild => {
+                    println!("Running post-build hook");
+                }
+                CompileUpdate::WriteArchive => {
+                    println!("Writing zip archive");
+                }
+                CompileUpdate::Progress { progress } => {
+                    tracing::debug!(progress, "build progress");
+                }
             }
         }
-        match op.outcome() {
-            Outcome::Finish => {
-                println!("Problem compiled successfully");
-            }
-            Outcome::Error(err) => {
-                println!("Compilation failed: {:#}", err,);
-            }
-            Outcome::Cancelled => {
-                println!("Operation was cancelled");
-            }
+        let outcome = op.outcome();
+        let message = match &outcome {
+            Outcome::Finish => "Problem compiled successfully".to_string(),
+            Outcome::Error(err) => format!("Compilation failed: {:#}", err),
+            Outcome::Cancelled => "Operation was cancelled".to_string(),
+        };
+        if matches!(progress_json, Some((_, true))) {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+        if let Outcome::Cancelled = outcome {
+            anyhow::bail!("build of {} was interrupted", pkg_path.display());
         }
     }
     Ok(())
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+#[derive(clap::Clap, Debug)]
+pub struct DiffArgs {
+    /// Path to old built package
+    #[clap(long = "old", short = 'A')]
+    pub old_path: PathBuf,
+    /// Path to new built package
+    #[clap(long = "new", short = 'B')]
+    pub new_path: PathBuf,
+}
+
+fn format_limit(value: Option<u64>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "unlimited".to_string(),
+    }
+}
+
+fn describe_limits_change(change: &pps_engine::Change<pom::Limits>) -> Vec<String> {
+    let (old, new) = (&change.old, &change.new);
+    let fields = [
+        ("time", old.time, new.time),
+        ("cpu time", old.cpu_time, new.cpu_time),
+        ("memory", old.memory, new.memory),
+        ("processes", old.process_count, new.process_count),
+        ("work dir size", old.work_dir_size, new.work_dir_size),
+    ];
+    fields
+        .iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(name, old, new)| {
+            format!("{} {} -> {}", name, format_limit(*old), format_limit(*new))
+        })
+        .collect()
+}
+
+fn format_ids(ids: &[usize]) -> String {
+    ids.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub async fn exec(args: DiffArgs) -> anyhow::Result<()> {
+    let diff = pps_engine::diff_packages(&args.old_path, &args.new_path).await?;
+    if diff.is_empty() {
+        println!("Packages are identical");
+        return Ok(());
+    }
+    if diff.checker_changed {
+        println!("Checker changed");
+    }
+    if diff.valuer_changed {
+        println!("Valuer changed");
+    }
+    if diff.groups_changed {
+        println!("Group descriptions changed");
+    }
+    if !diff.added_tests.is_empty() {
+        println!("Added tests: {}", format_ids(&diff.added_tests));
+    }
+    if !diff.removed_tests.is_empty() {
+        println!("Removed tests: {}", format_ids(&diff.removed_tests));
+    }
+    for test in &diff.changed_tests {
+        let mut changes = Vec::new();
+        if test.input_changed {
+            changes.push("input changed".to_string());
+        }
+        if test.answers_changed {
+            changes.push("answer changed".to_string());
+        }
+        if let Some(group) = &test.group {
+            changes.push(format!("group {} -> {}", group.old, group.new));
+        }
+        if let Some(limits) = &test.limits {
+            changes.extend(describe_limits_change(limits));
+        }
+        println!("test {}: {}", test.test_id, changes.join(", "));
+    }
+    Ok(())
+}
@@ -1,6 +1,8 @@
 mod compile;
+mod diff;
 mod import;
 mod progress_notifier;
+mod summary;
 
 use anyhow::Context as _;
 use clap::Clap;
@@ -11,6 +13,10 @@ use std::path::Path;
 pub enum Args {
     Compile(compile::CompileArgs),
     Import(import::ImportArgs),
+    /// Print groups and limits of tests in built package
+    Summary(summary::SummaryArgs),
+    /// Print differences between two built packages
+    Diff(diff::DiffArgs),
 }
 
 fn check_dir(path: &Path, allow_nonempty: bool) -> anyhow::Result<()> {
@@ -42,5 +48,7 @@ async fn process_args(args: Args) -> anyhow::Result<()> {
     match args {
         Args::Compile(compile_args) => compile::exec(compile_args).await,
         Args::Import(import_args) => import::exec(import_args).await,
+        Args::Summary(summary_args) => summary::exec(summary_args).await,
+        Args::Diff(diff_args) => diff::exec(diff_args).await,
     }
 }
@@ -0,0 +1,36 @@
+use anyhow::Context as _;
+use std::path::PathBuf;
+
+#[derive(clap::Clap, Debug)]
+pub struct SummaryArgs {
+    /// Path to built problem package
+    #[clap(long = "pkg", short = 'P')]
+    pub pkg_path: PathBuf,
+}
+
+fn format_limit(value: Option<u64>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{}{}", value, unit),
+        None => "unlimited".to_string(),
+    }
+}
+
+pub async fn exec(args: SummaryArgs) -> anyhow::Result<()> {
+    let manifest_path = args.pkg_path.join("manifest.json");
+    let manifest = tokio::fs::read(&manifest_path)
+        .await
+        .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+    let problem: pom::Problem = serde_json::from_slice(&manifest).context("manifest is invalid")?;
+    for test in problem.test_summary() {
+        println!(
+            "test {}: group {}, time {}, cpu time {}, memory {}, processes {}",
+            test.test_id.get(),
+            test.group,
+            format_limit(test.limits.time_opt(), " ms"),
+            format_limit(test.limits.cpu_time_opt(), " ms"),
+            format_limit(test.limits.memory_opt(), " bytes"),
+            format_limit(test.limits.process_count_opt(), ""),
+        );
+    }
+    Ok(())
+}
@@ -1,17 +1,27 @@
 //! This module implements compiling source package into invoker package
 pub(crate) mod build;
 mod builder;
+mod checker;
+mod error;
+mod git;
+mod incremental;
+
+pub use error::BuildError;
+pub use git::GitSource;
 
 use crate::operation::{Operation, ProgressWriter};
 use anyhow::Context as _;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeSet,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 #[derive(Serialize, Deserialize)]
 pub struct CompileRequest {
-    /// Path to problem source directory
+    /// Path to problem source directory. If `git_source` is set,
+    /// it is relative to repository root.
     pub problem_path: PathBuf,
     /// Where to put compiled package
     pub out_path: PathBuf,
@@ -19,44 +29,280 @@ pub struct CompileRequest {
     pub force: bool,
     /// Path to directory containing JJS binaries (such as svaluer)
     pub jjs_path: PathBuf,
+    /// Reuse up-to-date tests from previous build in out_path.
+    /// Has no effect if `force` is set.
+    #[serde(default)]
+    pub incremental: bool,
+    /// If not empty, only these tests of previously built package in
+    /// out_path are regenerated
+    #[serde(default)]
+    pub tests: Vec<usize>,
+    /// Only regenerate correct answers of `tests` (or of all tests, if
+    /// `tests` is empty), keeping their inputs
+    #[serde(default)]
+    pub answers_only: bool,
+    /// If set, built package is additionally packed into zip archive
+    /// at this path
+    #[serde(default)]
+    pub zip_path: Option<PathBuf>,
+    /// If set, problem sources are cloned from this repository into
+    /// temporary directory, which is removed after build
+    #[serde(default)]
+    pub git_source: Option<GitSource>,
 }
 
+/// Progress update of compile operation.
+/// Serialized form is `{"kind": <variant name in kebab-case>, "data": <fields>}`,
+/// e.g. `{"kind":"generate-test","data":{"test_id":3}}`.
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
 pub enum CompileUpdate {
+    /// Problem repository is being cloned. Appears before all other
+    /// updates, and only if `git_source` is set.
+    CloneRepository { url: String },
     /// Contains some warnings that should be displayed to used.
-    /// Appears at most once.
+    /// Manifest warnings appear at most once, before build starts.
+    /// Warnings found during build are sent as separate updates.
     Warnings(Vec<String>),
     /// Solution with given name is being built
     BuildSolution(String),
+    /// Solution with given name is not built, because it is excluded
+    /// by `JJS_SOLUTIONS` filter
+    SkipSolution(String),
     /// Test generator with given name is being built
     BuildTestgen(String),
+    /// Test generator with given name is up to date, so it was not rebuilt.
+    /// Appears instead of `BuildTestgen` in incremental builds.
+    SkipTestgen(String),
     /// Checker building started
     BuildChecker,
+    /// Output validator building started
+    BuildOutputValidator,
     /// Test generation started. `count` tests will be processed.
-    /// Appears at most once before `GenerateTest` updates.
+    /// Appears at most once before `GenerateTest` updates. Does not
+    /// appear when only separate tests are regenerated.
     GenerateTests { count: usize },
     /// Test `test_id` is being generated. Total test count is `count`.
     /// `test_id`s are in range 1..=`count`. It is gu
     GenerateTest { test_id: usize },
+    /// Test `test_id` is up to date, so it was not regenerated.
+    /// Appears instead of `GenerateTest` in incremental builds.
+    SkipTest { test_id: usize },
+    /// Effective limits of test `test_id` (after merging problem and test
+    /// limits and applying time scale). Appears after `GenerateTest` or
+    /// `SkipTest` for the same test.
+    TestLimits { test_id: usize, limits: pom::Limits },
+    /// Test `test_id` input is up to date, but its correct answer is
+    /// regenerated (e.g. because primary solution changed).
+    GenerateAnswer { test_id: usize },
+    /// `count` generated tests duplicated other tests of their groups
+    /// and were removed
+    DuplicateTests { count: usize },
+    /// Checker is being tested on test `test_id`
+    CheckerSelfTest { test_id: usize },
+    /// Checker is being run on correct answer of test `test_id`
+    VerifyAnswer { test_id: usize },
+    /// Output validator is being run on correct answer of test `test_id`
+    ValidateAnswer { test_id: usize },
+    /// Solution `solution` is being run on test `test_id` to check its
+    /// expected verdict
+    CheckExpectedVerdict { solution: String, test_id: usize },
     /// Valuer config is being copied
     CopyValuerConfig,
+    /// Post-build hook is being executed
+    RunPostBuild,
+    /// Package is being packed into zip archive
+    WriteArchive,
+    /// Estimated overall progress of full build, from 0.0 to 1.0.
+    /// Sent after each build stage and after each test.
+    Progress { progress: f32 },
+}
+
+/// Prefix of builtin checker binary names
+const BUILTIN_CHECKER_PREFIX: &str = "builtin-checker-";
+
+/// Returns names of builtin checkers found in given directory, sorted
+fn scan_builtin_checkers(dir: &Path) -> Vec<String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix(BUILTIN_CHECKER_PREFIX))
+                .map(ToString::to_string)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Lists builtin checkers, provided by build environment
+/// (i.e. `bin/builtin-checker-*` files).
+pub fn list_builtin_checkers(build_env: &Path) -> Vec<String> {
+    scan_builtin_checkers(&build_env.join("bin"))
+}
+
+/// Reads positive number from environment variable `var`.
+/// Returns `default` if variable is not set.
+fn positive_usize_env(var: &str, default: usize) -> anyhow::Result<usize> {
+    match std::env::var(var) {
+        Ok(value) => {
+            let value: usize = value
+                .parse()
+                .with_context(|| format!("{} is not a number", var))?;
+            if value == 0 {
+                anyhow::bail!("{} must be positive", var);
+            }
+            Ok(value)
+        }
+        Err(_) => Ok(default),
+    }
+}
+
+/// Default value for build tasks concurrency limit
+const DEFAULT_BUILD_JOBS: usize = 4;
+
+/// Returns how many build tasks can be executed concurrently.
+/// Can be overriden with `JJS_BUILD_JOBS` environment variable.
+fn build_jobs() -> anyhow::Result<usize> {
+    positive_usize_env("JJS_BUILD_JOBS", DEFAULT_BUILD_JOBS)
+}
+
+/// Default value for answer generation concurrency limit. Answers are
+/// generated sequentially, so that runs do not compete for CPU.
+const DEFAULT_ANSWER_JOBS: usize = 1;
+
+/// Returns how many answer generation runs can be executed concurrently.
+/// Can be overriden with `JJS_ANSWER_JOBS` environment variable.
+fn answer_jobs() -> anyhow::Result<usize> {
+    positive_usize_env("JJS_ANSWER_JOBS", DEFAULT_ANSWER_JOBS)
+}
+
+/// Default build task timeout, in seconds
+const DEFAULT_BUILD_TIMEOUT: u64 = 600;
+
+/// Returns how long single build task can run.
+/// Can be overriden with `JJS_BUILD_TIMEOUT` environment variable (in seconds).
+fn build_timeout() -> anyhow::Result<Duration> {
+    match std::env::var("JJS_BUILD_TIMEOUT") {
+        Ok(timeout) => {
+            let timeout: u64 = timeout
+                .parse()
+                .context("JJS_BUILD_TIMEOUT is not a number")?;
+            if timeout == 0 {
+                anyhow::bail!("JJS_BUILD_TIMEOUT must be positive");
+            }
+            Ok(Duration::from_secs(timeout))
+        }
+        Err(_) => Ok(Duration::from_secs(DEFAULT_BUILD_TIMEOUT)),
+    }
+}
+
+/// Returns true if `JJS_STRICT_BUILD` environment variable is set to
+/// non-empty value, i.e. compiler warnings must fail the build
+fn strict_build() -> bool {
+    match std::env::var_os("JJS_STRICT_BUILD") {
+        Some(value) => !value.is_empty(),
+        None => false,
+    }
+}
+
+/// Parses `JJS_SOLUTIONS` environment variable. It contains
+/// comma-separated list of solutions that should be built.
+/// If it is not set, all solutions are built.
+fn solutions_filter() -> Option<BTreeSet<String>> {
+    let solutions = std::env::var("JJS_SOLUTIONS").ok()?;
+    Some(
+        solutions
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+/// Returns factor, which is applied to time limits. `JJS_TIME_SCALE`
+/// environment variable overrides value from manifest, because
+/// calibration is specific to the machine.
+fn time_scale(manifest_value: f64) -> anyhow::Result<f64> {
+    match std::env::var("JJS_TIME_SCALE") {
+        Ok(scale) => {
+            let scale: f64 = scale.parse().context("JJS_TIME_SCALE is not a number")?;
+            if !(scale.is_finite() && scale > 0.0) {
+                anyhow::bail!("JJS_TIME_SCALE must be positive");
+            }
+            Ok(scale)
+        }
+        Err(_) => Ok(manifest_value),
+    }
+}
+
+/// Returns Docker image that should be used for building sources.
+/// If `JJS_BUILD_IMAGE` is not set, sources are built on the host.
+fn build_image() -> Option<String> {
+    std::env::var("JJS_BUILD_IMAGE").ok()
+}
+
+/// Parses `JJS_BUILD_MOUNTS` environment variable. It contains
+/// comma-separated list of `host_path:container_path` pairs.
+fn build_extra_mounts() -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let mounts = match std::env::var("JJS_BUILD_MOUNTS") {
+        Ok(mounts) => mounts,
+        Err(_) => return Ok(Vec::new()),
+    };
+    mounts
+        .split(',')
+        .filter(|mount| !mount.is_empty())
+        .map(|mount| match mount.split_once(':') {
+            Some((host, container)) => Ok((PathBuf::from(host), PathBuf::from(container))),
+            None => anyhow::bail!("JJS_BUILD_MOUNTS: invalid mount `{}`", mount),
+        })
+        .collect()
 }
 
 async fn do_exec(
     req: CompileRequest,
     pw: &mut ProgressWriter<CompileUpdate>,
 ) -> anyhow::Result<()> {
-    if req.force {
+    if !req.tests.is_empty() || req.answers_only {
+        crate::check_dir(&req.out_path, true).await?;
+    } else if req.force {
         tokio::fs::remove_dir_all(&req.out_path).await.ok();
         tokio::fs::create_dir_all(&req.out_path).await?;
+    } else if req.incremental {
+        tokio::fs::create_dir_all(&req.out_path).await?;
     } else {
         crate::check_dir(&req.out_path, false /* TODO */).await?;
     }
-    let toplevel_manifest = req.problem_path.join("problem.toml");
-    let toplevel_manifest = tokio::fs::read_to_string(toplevel_manifest).await?;
+    let checkout = match &req.git_source {
+        Some(source) => {
+            pw.send(CompileUpdate::CloneRepository {
+                url: source.url.clone(),
+            })
+            .await;
+            Some(git::clone(source).await?)
+        }
+        None => None,
+    };
+    let problem_path = match &checkout {
+        Some(checkout) => checkout.path().join(&req.problem_path),
+        None => req.problem_path.clone(),
+    };
+    let (manifest_path, manifest_format) = crate::manifest::find_manifest(&problem_path)?;
+    let toplevel_manifest = tokio::fs::read_to_string(&manifest_path)
+        .await
+        .with_context(|| format!("failed to read {}", manifest_path.display()))?;
 
-    let raw_problem_cfg: crate::manifest::RawProblem =
-        toml::from_str(&toplevel_manifest).context("problem.toml parse error")?;
+    let raw_problem_cfg = manifest_format
+        .parse(&toplevel_manifest)
+        .with_context(|| format!("{} parse error", manifest_path.display()))?;
     let (problem_cfg, warnings) = raw_problem_cfg.postprocess()?;
 
     pw.send(CompileUpdate::Warnings(warnings)).await;
@@ -64,30 +310,90 @@ async fn do_exec(
     let out_dir = tokio::fs::canonicalize(&req.out_path)
         .await
         .context("resolve out dir")?;
-    let problem_dir = tokio::fs::canonicalize(&req.problem_path)
+    let problem_dir = tokio::fs::canonicalize(&problem_path)
         .await
         .context("resolve problem dir")?;
 
+    let jjs_dir = Path::new(&req.jjs_path);
+    let build_backend: Box<dyn build::BuildBackend + '_> = match build_image() {
+        Some(image) => Box::new(build::DockerBuildBackend {
+            jjs_dir,
+            image,
+            extra_mounts: build_extra_mounts()?,
+        }),
+        None => Box::new(build::Pibs { jjs_dir }),
+    };
+
     let mut builder = builder::ProblemBuilder {
         cfg: &problem_cfg,
         problem_dir: &problem_dir,
         out_dir: &out_dir,
         build_env: &req.jjs_path,
-        build_backend: &build::Pibs {
-            jjs_dir: Path::new(&req.jjs_path),
-        },
+        build_backend: &*build_backend,
         pw,
+        jobs: build_jobs()?,
+        build_timeout: build_timeout()?,
+        answer_jobs: answer_jobs()?,
+        incremental: req.incremental && !req.force,
+        strict_build: problem_cfg.strict_build || strict_build(),
+        solutions_filter: solutions_filter(),
+        time_scale: time_scale(problem_cfg.time_scale)?,
+        progress: Default::default(),
     };
-    builder.build().await?;
+    if req.answers_only {
+        builder.regenerate_answers(&req.tests).await?;
+    } else if req.tests.is_empty() {
+        builder.build().await?;
+    } else {
+        for &test_id in &req.tests {
+            builder.build_single_test(test_id).await?;
+        }
+    }
+    if let Some(zip_path) = &req.zip_path {
+        pw.send(CompileUpdate::WriteArchive).await;
+        crate::zip::write_package_zip(&out_dir, zip_path).await?;
+    }
     Ok(())
 }
 
-/// Executes CompileRequest
+/// Resolves when process receives SIGINT or SIGTERM.
+/// If signal handlers can not be installed, never resolves.
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let signals = signal(SignalKind::interrupt())
+        .and_then(|sigint| Ok((sigint, signal(SignalKind::terminate())?)));
+    match signals {
+        Ok((mut sigint, mut sigterm)) => {
+            tokio::select! {
+                _ = sigint.recv() => (),
+                _ = sigterm.recv() => (),
+            }
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to install signal handlers");
+            futures::future::pending().await
+        }
+    }
+}
+
+/// Executes CompileRequest.
+/// On SIGINT or SIGTERM the build is dropped, which kills its child
+/// processes and removes temporary build dirs. Output dir is left as is,
+/// with `build.incomplete` marker in it, and operation is cancelled.
 pub fn exec(req: CompileRequest) -> Operation<CompileUpdate> {
     let (op, mut pw) = crate::operation::start();
     tokio::task::spawn(async move {
-        let res = do_exec(req, &mut pw).await;
-        pw.finish(res).await;
+        let res = tokio::select! {
+            res = do_exec(req, &mut pw) => Some(res),
+            _ = shutdown_signal() => None,
+        };
+        match res {
+            Some(res) => pw.finish(res).await,
+            None => {
+                tracing::info!("build interrupted by signal");
+                pw.cancel().await
+            }
+        }
     });
 
     op
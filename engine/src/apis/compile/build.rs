@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 #[derive(Debug, Clone)]
@@ -13,6 +16,10 @@ pub(crate) struct Task {
 
 pub(crate) struct TaskSuccess {
     pub(crate) command: crate::command::Command,
+    /// Stderr of all commands executed during build (e.g. compiler warnings)
+    pub(crate) stderr: Vec<u8>,
+    /// Argv of all commands executed during build, in order
+    pub(crate) build_commands: Vec<Vec<String>>,
 }
 
 #[derive(Debug, Error)]
@@ -36,17 +43,31 @@ impl Task {
 
 #[async_trait::async_trait]
 trait CommandExt {
-    async fn run(&mut self) -> Result<(), TaskError>;
+    /// Runs command and returns its stderr. Command argv is appended
+    /// to `log`.
+    async fn run(&mut self, log: &mut Vec<Vec<String>>) -> Result<Vec<u8>, TaskError>;
 }
 
 #[async_trait::async_trait]
 impl CommandExt for tokio::process::Command {
-    async fn run(&mut self) -> Result<(), TaskError> {
-        let out = self.output().await?;
+    async fn run(&mut self, log: &mut Vec<Vec<String>>) -> Result<Vec<u8>, TaskError> {
+        let std_cmd = self.as_std();
+        log.push(
+            std::iter::once(std_cmd.get_program())
+                .chain(std_cmd.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        );
+        // if build task times out, future is dropped and child must be killed
+        let out = self.kill_on_drop(true).output().await?;
         if out.status.success() {
-            Ok(())
+            Ok(out.stderr)
         } else {
-            Err(TaskError::ExitCodeNonZero(format!("{:?}", self), out))
+            let env = std_cmd.get_envs().filter_map(|(k, v)| v.map(|v| (k, v)));
+            let argv = std::iter::once(std_cmd.get_program()).chain(std_cmd.get_args());
+            let cmd =
+                crate::command::shell_command_line(std_cmd.get_current_dir(), false, env, argv);
+            Err(TaskError::ExitCodeNonZero(cmd, out))
         }
     }
 }
@@ -54,6 +75,34 @@ impl CommandExt for tokio::process::Command {
 #[async_trait::async_trait]
 pub(crate) trait BuildBackend: Send + Sync {
     async fn process_task(&self, task: Task) -> Result<TaskSuccess, TaskError>;
+
+    /// Environment variables that must be set for every built command
+    fn extra_env(&self) -> Vec<(OsString, OsString)> {
+        Vec::new()
+    }
+
+    /// String, identifying backend configuration. Build results are
+    /// reused only if it did not change.
+    fn cache_key(&self) -> String;
+
+    /// Returns true if output of successful build contains warnings
+    fn has_warnings(&self, stderr: &[u8]) -> bool {
+        String::from_utf8_lossy(stderr).contains("warning")
+    }
+}
+
+/// Creates commands which are executed during build
+trait Launcher: Send + Sync {
+    fn command(&self, program: &str, task: &Task) -> tokio::process::Command;
+}
+
+/// Runs commands on the host
+struct LocalLauncher;
+
+impl Launcher for LocalLauncher {
+    fn command(&self, program: &str, _task: &Task) -> tokio::process::Command {
+        tokio::process::Command::new(program)
+    }
 }
 
 /// Ppc-integrated build system
@@ -62,35 +111,49 @@ pub(crate) struct Pibs<'a> {
 }
 
 impl<'a> Pibs<'a> {
-    async fn process_cmake_task(&self, task: Task) -> Result<TaskSuccess, TaskError> {
-        tokio::process::Command::new("cmake")
+    async fn process_cmake_task(
+        &self,
+        task: Task,
+        launcher: &dyn Launcher,
+    ) -> Result<TaskSuccess, TaskError> {
+        let mut build_commands = Vec::new();
+        let mut stderr = launcher
+            .command("cmake", &task)
             .arg("-S")
             .arg(&task.src)
             .arg("-B")
             .arg(&task.tmp)
-            .run()
+            .run(&mut build_commands)
             .await?;
 
-        tokio::process::Command::new("cmake")
-            .arg("--build")
-            .arg(&task.tmp)
-            .run()
-            .await?;
+        stderr.extend(
+            launcher
+                .command("cmake", &task)
+                .arg("--build")
+                .arg(&task.tmp)
+                .run(&mut build_commands)
+                .await?,
+        );
 
         let dst = task.dest.join("bin");
         tokio::fs::copy(task.tmp.join("Out"), &dst).await?;
         let run_cmd = crate::command::Command::new(dst);
-        Ok(TaskSuccess { command: run_cmd })
+        Ok(TaskSuccess {
+            command: run_cmd,
+            stderr,
+            build_commands,
+        })
     }
-}
 
-#[async_trait::async_trait]
-impl<'a> BuildBackend for Pibs<'a> {
-    async fn process_task(&self, task: Task) -> Result<TaskSuccess, TaskError> {
+    async fn process_task_with(
+        &self,
+        task: Task,
+        launcher: &dyn Launcher,
+    ) -> Result<TaskSuccess, TaskError> {
         if task.multi_file() {
             let cmake_lists_path = task.src.join("CMakeLists.txt");
             if cmake_lists_path.exists() {
-                return self.process_cmake_task(task).await;
+                return self.process_cmake_task(task, launcher).await;
             }
             let python_path = task.src.join("main.py");
             if python_path.exists() {
@@ -98,7 +161,11 @@ impl<'a> BuildBackend for Pibs<'a> {
                 std::fs::copy(&python_path, &out_path)?;
                 let mut command = crate::command::Command::new("python3");
                 command.arg(&out_path);
-                return Ok(TaskSuccess { command });
+                return Ok(TaskSuccess {
+                    command,
+                    stderr: Vec::new(),
+                    build_commands: Vec::new(),
+                });
             }
             return Err(TaskError::FeatureNotSupported {
                 feature: "multi-file sources",
@@ -109,21 +176,105 @@ impl<'a> BuildBackend for Pibs<'a> {
         let link_arg = format!("-L{}/lib", self.jjs_dir.display());
 
         let dest_file = task.dest.join("bin");
-        tokio::process::Command::new("g++")
+        let mut build_commands = Vec::new();
+        let stderr = launcher
+            .command("g++", &task)
             .arg("-std=c++17")
             .arg(incl_arg)
             .arg(link_arg)
             .arg("-DPPC=1")
-            .arg(task.src)
+            .arg(&task.src)
             .arg("-o")
             .arg(&dest_file)
             .arg("-ljtl")
             .arg("-lpthread")
             .arg("-ldl")
-            .run()
+            .run(&mut build_commands)
             .await?;
 
         let command = crate::command::Command::new(&dest_file);
-        Ok(TaskSuccess { command })
+        Ok(TaskSuccess {
+            command,
+            stderr,
+            build_commands,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> BuildBackend for Pibs<'a> {
+    async fn process_task(&self, task: Task) -> Result<TaskSuccess, TaskError> {
+        self.process_task_with(task, &LocalLauncher).await
+    }
+
+    fn cache_key(&self) -> String {
+        format!("pibs:{}", self.jjs_dir.display())
+    }
+}
+
+/// Builds sources the same way as `Pibs`, but runs all compilers inside
+/// a Docker container. Task directories and JJS dir are mounted into
+/// container on the same paths, so produced artifacts are usable on the host.
+pub(crate) struct DockerBuildBackend<'a> {
+    pub(crate) jjs_dir: &'a Path,
+    /// Image used to create build containers
+    pub(crate) image: String,
+    /// Additional mounts as (host path, container path) pairs
+    pub(crate) extra_mounts: Vec<(PathBuf, PathBuf)>,
+}
+
+fn mount_arg(host: &Path, container: &Path, read_only: bool) -> String {
+    let mut arg = format!(
+        "type=bind,source={},target={}",
+        host.display(),
+        container.display()
+    );
+    if read_only {
+        arg.push_str(",readonly");
+    }
+    arg
+}
+
+impl<'a> Launcher for DockerBuildBackend<'a> {
+    fn command(&self, program: &str, task: &Task) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new("docker");
+        cmd.arg("run").arg("--rm").arg("--network=none");
+        // make sure artifacts are owned by current user
+        // SAFETY: getuid and getgid are always successful
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        cmd.arg(format!("--user={}:{}", uid, gid));
+        cmd.arg("--mount")
+            .arg(mount_arg(&task.src, &task.src, true));
+        cmd.arg("--mount")
+            .arg(mount_arg(&task.dest, &task.dest, false));
+        cmd.arg("--mount")
+            .arg(mount_arg(&task.tmp, &task.tmp, false));
+        cmd.arg("--mount")
+            .arg(mount_arg(self.jjs_dir, self.jjs_dir, true));
+        for (host, container) in &self.extra_mounts {
+            cmd.arg("--mount").arg(mount_arg(host, container, true));
+        }
+        cmd.arg(&self.image).arg(program);
+        cmd
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> BuildBackend for DockerBuildBackend<'a> {
+    async fn process_task(&self, task: Task) -> Result<TaskSuccess, TaskError> {
+        Pibs {
+            jjs_dir: self.jjs_dir,
+        }
+        .process_task_with(task, self)
+        .await
+    }
+
+    fn cache_key(&self) -> String {
+        format!(
+            "docker:{}:{}:{:?}",
+            self.image,
+            self.jjs_dir.display(),
+            self.extra_mounts
+        )
     }
 }
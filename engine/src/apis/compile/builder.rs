@@ -1,21 +1,84 @@
 use crate::{
     apis::compile::{
-        build::{BuildBackend, Task, TaskError},
+        build::{BuildBackend, Task, TaskError, TaskSuccess},
+        checker,
+        error::BuildError,
+        incremental::{
+            BuiltCommands, Fingerprinter, Fingerprints, TestFingerprint, TestgenCache,
+            TestgenCacheEntry,
+        },
         CompileUpdate,
     },
-    command::Command,
+    command::{ChildInput, Command},
+    manifest::LimitsPatch,
     operation::ProgressWriter,
 };
 use anyhow::Context as _;
+use futures::stream::{StreamExt as _, TryStreamExt as _};
 use pom::{FileRef, FileRefRoot, Limits};
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     fmt::Write,
-    os::unix::io::IntoRawFd,
     path::{Path, PathBuf},
-    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
+/// Correct answer, which is not generated yet. Answers are generated
+/// after all test inputs, so that they can be generated concurrently.
+struct PendingAnswer {
+    tid: usize,
+    work_dir: Option<PathBuf>,
+    limits: Limits,
+}
+
+/// Programs, used to generate tests and their answers
+struct TestContext<'c> {
+    testgens: &'c BTreeMap<String, Command>,
+    answer_solutions: Vec<(&'c str, &'c Command)>,
+    /// Solutions, generating alternate answers
+    alternate_solutions: Vec<(&'c str, &'c Command)>,
+    /// Fingerprint of all answer solutions
+    answers_fingerprint: String,
+}
+
+impl<'c> TestContext<'c> {
+    async fn new(
+        testgens: &'c BTreeMap<String, Command>,
+        answer_solutions: Vec<(&'c str, &'c Command)>,
+        alternate_solutions: Vec<(&'c str, &'c Command)>,
+    ) -> anyhow::Result<TestContext<'c>> {
+        let mut fingerprinter = Fingerprinter::new();
+        for (name, cmd) in &answer_solutions {
+            fingerprinter.add(name);
+            fingerprinter.add_command(cmd).await?;
+        }
+        for (name, cmd) in &alternate_solutions {
+            fingerprinter.add("alternate");
+            fingerprinter.add(name);
+            fingerprinter.add_command(cmd).await?;
+        }
+        Ok(TestContext {
+            testgens,
+            answer_solutions,
+            alternate_solutions,
+            answers_fingerprint: fingerprinter.finish(),
+        })
+    }
+
+    /// Returns fingerprint of the test correct answer
+    fn answer_fingerprint(
+        &self,
+        input_fingerprint: &str,
+        test_spec: &crate::manifest::ExpandedTestSpec,
+    ) -> String {
+        Fingerprinter::new()
+            .add(input_fingerprint)
+            .add(&self.answers_fingerprint)
+            .add(&test_spec.work_dir)
+            .finish()
+    }
+}
+
 /// ProblemBuilder is struct, responsible for building single problem.
 /// Its instances are managed by CompilerService.
 pub(crate) struct ProblemBuilder<'a> {
@@ -31,10 +94,80 @@ pub(crate) struct ProblemBuilder<'a> {
     pub(crate) build_backend: &'a dyn BuildBackend,
     /// Used to return live building progress
     pub(crate) pw: &'a mut ProgressWriter<CompileUpdate>,
+    /// Maximal count of build tasks executed concurrently
+    pub(crate) jobs: usize,
+    /// Build task is aborted if it runs longer
+    pub(crate) build_timeout: std::time::Duration,
+    /// Maximal count of answer generation runs executed concurrently
+    pub(crate) answer_jobs: usize,
+    /// Reuse up-to-date tests from previous build
+    pub(crate) incremental: bool,
+    /// Fail build if compiler reported warnings or some sources are unused
+    pub(crate) strict_build: bool,
+    /// If set, only solutions with these names are built
+    pub(crate) solutions_filter: Option<std::collections::BTreeSet<String>>,
+    /// Factor, applied to time limits of tests
+    pub(crate) time_scale: f64,
+    /// Filled when full build starts
+    pub(crate) progress: ProgressEstimate,
+}
+
+/// Returns solution with given name, or error listing defined solutions
+fn find_solution<'s>(
+    solutions: &'s BTreeMap<String, Command>,
+    name: &str,
+) -> anyhow::Result<&'s Command> {
+    match solutions.get(name) {
+        Some(cmd) => Ok(cmd),
+        None => {
+            let defined: Vec<&str> = solutions.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "Unknown solution {} (defined solutions: [{}])",
+                name,
+                defined.join(", ")
+            )
+        }
+    }
+}
+
+/// Runs `valuer --version` and extracts version from its output
+/// (which looks like `svaluer 0.1.0`).
+async fn query_valuer_version(valuer_exe: &Path) -> anyhow::Result<String> {
+    let out = Command::new(valuer_exe)
+        .arg("--version")
+        .run_quiet()
+        .await?;
+    let out = String::from_utf8(out.stdout).context("version is not utf8")?;
+    match out.split_whitespace().last() {
+        Some(version) => Ok(version.to_string()),
+        None => anyhow::bail!("valuer printed empty version"),
+    }
+}
+
+/// Strips leading UTF-8 BOM and replaces CRLF line endings with LF
+fn normalize_line_endings(data: Vec<u8>) -> Vec<u8> {
+    const BOM: &[u8] = b"\xEF\xBB\xBF";
+    let data = data.strip_prefix(BOM).unwrap_or(&data);
+    let mut out = Vec::with_capacity(data.len());
+    let mut iter = data.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        if byte == b'\r' && iter.peek() == Some(&&b'\n') {
+            continue;
+        }
+        out.push(byte);
+    }
+    out
+}
+
+/// Generates fresh random seed for test generator
+fn random_seed() -> String {
+    let mut entropy_buf = [0; crate::manifest::RANDOM_SEED_LENGTH];
+    get_entropy_hex(&mut entropy_buf);
+    String::from_utf8(entropy_buf.to_vec()).unwrap() // only ASCII can be here
 }
 
 /// Fills given buffer with random hex string
-fn get_entropy_hex(buf: &mut [u8]) {
+pub(super) fn get_entropy_hex(buf: &mut [u8]) {
     getrandom::getrandom(buf).expect("get entropy failed");
     for i in buf.iter_mut() {
         *i %= 16;
@@ -46,51 +179,325 @@ fn get_entropy_hex(buf: &mut [u8]) {
     }
 }
 
-/// Applies merge patch `other` to a `place`:
-/// If `other` is None, does nothing.
-/// If `other` is Some, stores `other` inner value into `place`.
-fn merge_option<T: Copy>(place: &mut Option<T>, other: Option<T>) {
-    if let Some(x) = other {
-        place.replace(x);
+/// Runs test filter on generated `test_data`. Returns error if test is
+/// rejected or filter fails.
+async fn run_filter(filter_cmd: &Command, test_data: &[u8], limits: &Limits) -> anyhow::Result<()> {
+    let out = filter_cmd
+        .run_with_limits_redirected(limits, ChildInput::Data(test_data), None)
+        .await
+        .with_context(|| format!("failed to run filter `{}`", filter_cmd))?;
+    match out.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => anyhow::bail!("test was rejected by filter"),
+        _ => filter_cmd.check_success(out).map(drop),
     }
 }
 
-/// Merges several `Limits` object. Last element of slice will have maximal proirity.
-fn merge_limits(limits_set: &[Limits]) -> Limits {
+/// Merges several limits patches on top of default limits.
+/// Last element of slice will have maximal priority. Precedence is:
+/// test spec (tests block, or group of tests tree) overrides problem-wide
+/// limits, which override defaults. Patch can also clear limit, making
+/// it unlimited (see `LimitPatch::Unlimited`).
+fn merge_limits(limits_set: &[LimitsPatch]) -> Limits {
     let mut res = Limits::default();
     for lim in limits_set {
-        merge_option(&mut res.memory, lim.memory);
-        merge_option(&mut res.process_count, lim.process_count);
-        merge_option(&mut res.time, lim.time);
+        lim.apply(&mut res);
     }
     res
 }
 
+/// Multiplies time limit by calibration factor, rounding up
+fn scale_time_limit(limits: &mut Limits, time_scale: f64) {
+    limits.time = limits
+        .time
+        .map(|time| (time as f64 * time_scale).ceil() as u64);
+}
+
+/// Returns limits for build-time helpers (generators, filters and answer
+/// solutions). Only time limits of the test are kept: memory and process
+/// count rlimits break multithreaded helpers (e.g. JVM), and
+/// `RLIMIT_NPROC` counts all processes of the build user.
+fn helper_limits(test_limits: &Limits) -> Limits {
+    Limits {
+        time: test_limits.time,
+        cpu_time: test_limits.cpu_time,
+        memory: None,
+        process_count: None,
+        work_dir_size: None,
+    }
+}
+
+/// Returns groups, whose tests do not occupy contiguous range of test ids.
+/// `groups` contains group of each test, in test id order.
+fn non_contiguous_groups<'g>(groups: impl Iterator<Item = &'g str>) -> Vec<&'g str> {
+    let mut finished = std::collections::BTreeSet::new();
+    let mut bad = Vec::new();
+    let mut current = None;
+    for group in groups {
+        if current == Some(group) {
+            continue;
+        }
+        if let Some(prev) = current {
+            finished.insert(prev);
+        }
+        if finished.contains(group) && !bad.contains(&group) {
+            bad.push(group);
+        }
+        current = Some(group);
+    }
+    bad
+}
+
+/// Returns total size of files in directory `root`, except for
+/// files in `exclude` directory
+async fn dir_size(root: &Path, exclude: &Path) -> anyhow::Result<u64> {
+    let mut total = 0;
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("failed to read dir {}", dir.display()))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let metadata = tokio::fs::symlink_metadata(&path).await?;
+            if metadata.is_dir() {
+                if path != exclude {
+                    stack.push(path);
+                }
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+    Ok(total)
+}
+
+/// Returns total size of given package files
+async fn files_size(
+    files: impl Iterator<Item = &pom::FileRef>,
+    out_dir: &Path,
+) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for file in files {
+        size += tokio::fs::metadata(file.resolve(out_dir)).await?.len();
+    }
+    Ok(size)
+}
+
+/// Weight of building one program (solution, generator etc.) in progress
+/// estimation. Each test weights 1.
+const PROGRAM_PROGRESS_WEIGHT: usize = 3;
+/// Weight of steps, performed after tests are generated
+const FINISH_PROGRESS_WEIGHT: usize = 1;
+
+/// Estimation of full build progress, see `CompileUpdate::Progress`
+#[derive(Default)]
+pub(crate) struct ProgressEstimate {
+    /// Planned amount of work, in weight units
+    total: usize,
+    done: usize,
+}
+
+impl ProgressEstimate {
+    fn fraction(&self) -> f32 {
+        (self.done as f32 / self.total as f32).min(1.0)
+    }
+}
+
+/// Name of the file, which is present in output dir while build is in
+/// progress. If it exists before build, previous build did not finish.
+const INCOMPLETE_MARKER: &str = "build.incomplete";
+
+/// Removes temporary build dir when build task finishes or is cancelled
+pub(super) struct BuildDirGuard(pub(super) PathBuf);
+
+impl Drop for BuildDirGuard {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.0) {
+            tracing::warn!(dir = %self.0.display(), error = %err, "failed to remove build dir");
+        }
+    }
+}
+
+/// Returns paths of directory entries, sorted by name. Hidden entries
+/// are skipped.
+async fn sorted_dir_entries(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("failed to read dir {}", dir.display()))?;
+    let mut out = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.file_name().to_string_lossy().starts_with('.') {
+            out.push(entry.path());
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Removes input and correct answers of the test. Answers, which
+/// were not generated yet, are skipped.
+async fn remove_test_files(test: &pom::Test, out_dir: &Path) -> anyhow::Result<()> {
+    for file in std::iter::once(&test.path).chain(test.correct_answers()) {
+        let path = file.resolve(out_dir);
+        match tokio::fs::remove_file(&path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            res => res.with_context(|| format!("failed to remove {}", path.display()))?,
+        }
+    }
+    Ok(())
+}
+
+/// Returns output directory name of module with given path (relative to
+/// modules directory), e.g. `module-foo-main.cpp` for `foo/main.cpp`.
+/// Path components are joined with `-`, other unusual characters are
+/// replaced with `_`.
+fn module_output_name(rel_path: &Path) -> anyhow::Result<String> {
+    let mut name = "module".to_string();
+    for component in rel_path.components() {
+        let component = match component {
+            std::path::Component::Normal(c) => c
+                .to_str()
+                .with_context(|| format!("module path {} is not utf8", rel_path.display()))?,
+            _ => anyhow::bail!("unexpected module path {}", rel_path.display()),
+        };
+        name.push('-');
+        name.extend(component.chars().map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        }));
+    }
+    Ok(name)
+}
+
+/// Stable-sorts test specs so that tests of each group are contiguous.
+/// Groups are ordered by their first test.
+fn reorder_by_group(specs: &mut [crate::manifest::ExpandedTestSpec]) {
+    let mut group_order = std::collections::HashMap::new();
+    for spec in specs.iter() {
+        let next = group_order.len();
+        group_order.entry(spec.group.clone()).or_insert(next);
+    }
+    specs.sort_by_key(|spec| group_order[&spec.group]);
+}
+
+/// Deterministically shuffles tests of each group among positions, occupied
+/// by this group, so contiguous groups stay contiguous. Returns original
+/// ids of tests in new order.
+fn shuffle_within_groups(tests: &mut Vec<pom::Test>, seed: u64) -> Vec<usize> {
+    // splitmix64: permutation must not change when dependencies are updated
+    let mut state = seed;
+    let mut next_random = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let mut group_positions: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, test) in tests.iter().enumerate() {
+        group_positions
+            .entry(test.group.clone())
+            .or_default()
+            .push(i);
+    }
+    let mut order: Vec<usize> = (0..tests.len()).collect();
+    for positions in group_positions.values() {
+        let mut members = positions.clone();
+        for i in (1..members.len()).rev() {
+            let j = (next_random() % (i as u64 + 1)) as usize;
+            members.swap(i, j);
+        }
+        for (&pos, &member) in positions.iter().zip(&members) {
+            order[pos] = member;
+        }
+    }
+    let mut old: Vec<Option<pom::Test>> = tests.drain(..).map(Some).collect();
+    tests.extend(
+        order
+            .iter()
+            .map(|&i| old[i].take().expect("order is permutation")),
+    );
+    order.into_iter().map(|i| i + 1).collect()
+}
+
 // TODO: remove duplicated code
 impl<'a> ProblemBuilder<'a> {
+    /// Checks if warnings of given source are allowed in strict build
+    fn is_strict_build_exempt(&self, src: &Path) -> bool {
+        let rel_path = src.strip_prefix(self.problem_dir).unwrap_or(src);
+        self.cfg
+            .strict_build_exempt
+            .iter()
+            .any(|exempt| Path::new(exempt.trim_start_matches('/')) == rel_path)
+    }
+
     /// Higher-level wrapper for `self.build_backend`
     async fn do_build(&self, src: &Path, dest: &Path) -> anyhow::Result<Command> {
+        self.do_build_task(src, dest)
+            .await
+            .map(|success| success.command)
+    }
+
+    /// Same as `do_build`, but also returns build details
+    async fn do_build_task(&self, src: &Path, dest: &Path) -> anyhow::Result<TaskSuccess> {
         tokio::fs::create_dir_all(dest)
             .await
             .context("failed to create dir")?;
 
-        let build_id = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_micros()
-            .to_string();
+        // several tasks can be started in the same microsecond, so
+        // timestamp is not enough to make build dir unique
+        let mut build_id_suffix = [0; 8];
+        get_entropy_hex(&mut build_id_suffix);
+        let build_id = format!(
+            "{}-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_micros(),
+            String::from_utf8_lossy(&build_id_suffix)
+        );
         let build_dir = format!("/tmp/pps-build-{}", &build_id);
         tokio::fs::create_dir(&build_dir)
             .await
-            .expect("couldn't create build dir");
+            .with_context(|| format!("failed to create build dir {}", build_dir))?;
+        let _build_dir_guard = BuildDirGuard(PathBuf::from(&build_dir));
 
         let task = Task {
             src: src.to_path_buf(),
             dest: dest.to_path_buf(),
             tmp: Path::new(&build_dir).to_path_buf(),
         };
-        match self.build_backend.process_task(task.clone()).await {
-            Ok(cmd) => Ok(cmd.command),
+        let res = tokio::time::timeout(
+            self.build_timeout,
+            self.build_backend.process_task(task.clone()),
+        )
+        .await;
+        let res = match res {
+            Ok(res) => res,
+            Err(_) => anyhow::bail!(
+                "build of {} timed out after {} seconds",
+                src.display(),
+                self.build_timeout.as_secs()
+            ),
+        };
+        match res {
+            Ok(success) => {
+                if self.strict_build
+                    && self.build_backend.has_warnings(&success.stderr)
+                    && !self.is_strict_build_exempt(src)
+                {
+                    anyhow::bail!(
+                        "strict build: {} compiled with warnings:\n{}",
+                        src.display(),
+                        String::from_utf8_lossy(&success.stderr)
+                    );
+                }
+                Ok(success)
+            }
             Err(err) => {
                 let mut description = String::new();
                 writeln!(
@@ -120,11 +527,60 @@ impl<'a> ProblemBuilder<'a> {
         }
     }
 
+    /// Resolves path, specified in problem manifest, and checks it exists
+    fn resolve_override(&self, path: &str, what: &str) -> anyhow::Result<PathBuf> {
+        let path = self.problem_dir.join(path);
+        if !path.exists() {
+            anyhow::bail!("{} override {} does not exist", what, path.display());
+        }
+        Ok(path)
+    }
+
+    /// Returns directory that contains builtin checker binaries
+    fn builtin_checkers_dir(&self) -> anyhow::Result<PathBuf> {
+        match &self.cfg.builtin_checker_dir_override {
+            Some(dir) => self.resolve_override(dir, "builtin checker dir"),
+            None => Ok(self.build_env.join("bin")),
+        }
+    }
+
+    /// Returns path to the valuer binary
+    fn valuer_exe_path(&self) -> anyhow::Result<PathBuf> {
+        match &self.cfg.valuer_exe_override {
+            Some(path) => self.resolve_override(path, "valuer"),
+            None => Ok(self.build_env.join("bin/svaluer")),
+        }
+    }
+
+    /// Checks that binaries, which are copied from build environment
+    /// (valuer and builtin checker), exist, so that build fails before
+    /// anything expensive is done
+    fn preflight(&self) -> anyhow::Result<()> {
+        let mut required = vec![("valuer binary", self.valuer_exe_path()?)];
+        if let crate::manifest::Check::Builtin(bc) = &self.cfg.check {
+            let checkers_dir = self.builtin_checkers_dir()?;
+            let checker =
+                checkers_dir.join(format!("{}{}", super::BUILTIN_CHECKER_PREFIX, bc.name));
+            required.push(("builtin checker", checker));
+        }
+        for (what, path) in required {
+            if !path.is_file() {
+                anyhow::bail!(
+                    "{} {} not found (build environment: {})",
+                    what,
+                    path.display(),
+                    self.build_env.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// async wrapper for `glob::glob`
     async fn glob(&self, suffix: &str) -> anyhow::Result<Vec<PathBuf>> {
         let pattern = format!("{}/{}", self.problem_dir.display(), suffix);
         tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<PathBuf>> {
-            let paths = glob::glob(&pattern)
+            let mut paths = glob::glob(&pattern)
                 .context("blob pattern error")?
                 .map(|x| match x {
                     Ok(p) => Ok(p),
@@ -133,6 +589,8 @@ impl<'a> ProblemBuilder<'a> {
                     }
                 })
                 .collect::<anyhow::Result<Vec<PathBuf>>>()?;
+            // sort paths to make build reproducible
+            paths.sort();
             Ok(paths)
         })
         .await
@@ -147,6 +605,7 @@ impl<'a> ProblemBuilder<'a> {
             .to_str()
             .context("path is not utf8")?
             .to_owned();
+        crate::manifest::check_name("solution", &sol_id)?;
         self.pw
             .send(CompileUpdate::BuildSolution(sol_id.clone()))
             .await;
@@ -159,307 +618,1750 @@ impl<'a> ProblemBuilder<'a> {
     }
 
     /// Builds all solutions
-    async fn build_solutions(&mut self) -> anyhow::Result<HashMap<String, Command>> {
-        let mut out = HashMap::new();
-        for solution_path in self.glob("solutions/*").await? {
+    async fn build_solutions(&mut self) -> anyhow::Result<BTreeMap<String, Command>> {
+        if let Some(filter) = &self.solutions_filter {
+            let required = self
+                .cfg
+                .primary_solution
+                .iter()
+                .chain(self.cfg.answer_solutions.iter())
+                .chain(self.cfg.alternate_answer_solutions.iter());
+            for name in required {
+                if !filter.contains(name) {
+                    anyhow::bail!(
+                        "solution {} is used for answer generation, so it must be included into solutions filter",
+                        name
+                    );
+                }
+            }
+        }
+        let solution_paths = self.glob("solutions/*").await?;
+        let solution_name = |path: &PathBuf| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        if let Some(filter) = &self.solutions_filter {
+            let found: std::collections::BTreeSet<String> =
+                solution_paths.iter().map(solution_name).collect();
+            let unknown: Vec<&str> = filter
+                .iter()
+                .filter(|name| !found.contains(*name))
+                .map(String::as_str)
+                .collect();
+            if !unknown.is_empty() {
+                anyhow::bail!(
+                    "solutions filter contains unknown solutions: {}",
+                    unknown.join(", ")
+                );
+            }
+        }
+        let mut out = BTreeMap::new();
+        for solution_path in solution_paths {
+            if let Some(filter) = &self.solutions_filter {
+                let name = solution_name(&solution_path);
+                if !filter.contains(&name) {
+                    self.pw.send(CompileUpdate::SkipSolution(name)).await;
+                    continue;
+                }
+            }
             let (sol_id, cmd) = self.build_solution(solution_path).await?;
             out.insert(sol_id, cmd);
         }
         Ok(out)
     }
 
-    /// Builds single testgen
+    /// Builds single testgen, or reuses generator from previous build
+    /// if its sources did not change
     async fn build_testgen(
         &mut self,
         testgen_path: &Path,
         testgen_name: &str,
+        cache: &mut TestgenCache,
     ) -> anyhow::Result<Command> {
+        let mut fingerprinter = Fingerprinter::new();
+        fingerprinter.add(self.build_backend.cache_key());
+        fingerprinter.add_tree(testgen_path).await?;
+        let source = fingerprinter.finish();
+        if self.incremental {
+            if let Some(cmd) = cache.lookup(testgen_name, &source) {
+                self.pw
+                    .send(CompileUpdate::SkipTestgen(testgen_name.to_string()))
+                    .await;
+                return Ok(cmd.clone());
+            }
+        }
         self.pw
             .send(CompileUpdate::BuildTestgen(testgen_name.to_string()))
             .await;
+        crate::manifest::check_name("generator", testgen_name)?;
         let out_path = format!("{}/assets/testgen-{}", self.out_dir.display(), testgen_name);
-        self.do_build(testgen_path, &Path::new(&out_path)).await
+        let command = self.do_build(testgen_path, &Path::new(&out_path)).await?;
+        cache.testgens.insert(
+            testgen_name.to_string(),
+            TestgenCacheEntry {
+                source,
+                command: command.clone(),
+            },
+        );
+        Ok(command)
     }
 
     /// Builds all testgens
-    async fn build_testgens(&mut self) -> anyhow::Result<HashMap<String, Command>> {
-        let mut out = HashMap::new();
+    async fn build_testgens(&mut self) -> anyhow::Result<BTreeMap<String, Command>> {
+        let mut cache = TestgenCache::load(self.out_dir).await;
+        let mut out = BTreeMap::new();
         for testgen in self.glob("generators/*").await? {
             let testgen_name = testgen
                 .file_stem()
                 .unwrap()
                 .to_str()
                 .context("utf8 error")?;
-            let testgen_launch_cmd = self.build_testgen(&testgen, testgen_name).await?;
+            let testgen_launch_cmd = self
+                .build_testgen(&testgen, testgen_name, &mut cache)
+                .await?;
             out.insert(testgen_name.to_string(), testgen_launch_cmd);
         }
+        cache.testgens.retain(|name, _| out.contains_key(name));
+        cache.store(self.out_dir).await?;
         Ok(out)
     }
 
-    /// Adds common modifications to a child process builder
+    /// Adds common modifications to a child process builder.
+    /// Working directory defaults to `problem_dir` unless already set.
+    /// If `clean-env` is enabled, only allowlisted variables are inherited.
+    /// Environment required by build backend is always added.
     fn configure_command(&self, cmd: &mut Command) {
-        cmd.current_dir(self.problem_dir);
+        if cmd.get_current_dir().is_none() {
+            cmd.current_dir(self.problem_dir);
+        }
+        if self.cfg.clean_env {
+            let mut clean_cmd = Command::with_clean_env(cmd.get_exe());
+            for arg in cmd.get_argv() {
+                clean_cmd.arg(arg);
+            }
+            if let Some(dir) = cmd.get_current_dir() {
+                clean_cmd.current_dir(dir);
+            }
+            for var in &self.cfg.env_allowlist {
+                if let Some(value) = std::env::var_os(var) {
+                    clean_cmd.env(var, value);
+                }
+            }
+            // explicitly set variables are kept and take precedence
+            for (key, value) in cmd.get_env() {
+                clean_cmd.env(key, value);
+            }
+            *cmd = clean_cmd;
+        }
+        for (key, value) in self.build_backend.extra_env() {
+            cmd.env(key, value);
+        }
         cmd.env("JJS_PROBLEM_SRC", &self.problem_dir);
         cmd.env("JJS_PROBLEM_DEST", &self.out_dir);
+        cmd.env("JJS_ATTACHMENTS", self.attachments_dir());
     }
 
-    /// Builds all tests
-    async fn build_tests(
-        &mut self,
-        testgens: &HashMap<String, Command>,
-        gen_answers: Option<&Command>,
-    ) -> anyhow::Result<Vec<pom::Test>> {
-        let tests_path = format!("{}/assets/tests", self.out_dir.display());
-        std::fs::create_dir_all(&tests_path).expect("couldn't create tests output dir");
-        self.pw
-            .send(CompileUpdate::GenerateTests {
-                count: self.cfg.tests.len(),
-            })
-            .await;
-        let mut out = vec![];
-        for (i, test_spec) in self.cfg.tests.iter().enumerate() {
-            let tid = i + 1;
-            self.pw
-                .send(CompileUpdate::GenerateTest { test_id: tid })
-                .await;
-
-            let out_file_path = format!("{}/{}-in.txt", &tests_path, tid);
-            match &test_spec.gen {
-                crate::manifest::TestGenSpec::Generate { testgen, args } => {
-                    let testgen_cmd = testgens
-                        .get(testgen)
-                        .with_context(|| format!("error: unknown testgen {}", testgen))?;
-
-                    let mut entropy_buf = [0; crate::manifest::RANDOM_SEED_LENGTH];
-                    get_entropy_hex(&mut entropy_buf);
-                    let entropy = String::from_utf8(entropy_buf.to_vec()).unwrap(); // only ASCII can be here
+    fn attachments_dir(&self) -> PathBuf {
+        self.out_dir.join("assets/attachments")
+    }
 
-                    let mut cmd = testgen_cmd.clone();
-                    for a in args {
-                        cmd.arg(a);
-                    }
-                    cmd.env("JJS_TEST_ID", &tid.to_string());
-                    cmd.env("JJS_RANDOM_SEED", &entropy);
-                    self.configure_command(&mut cmd);
-                    let gen_out = cmd.run_quiet().await?;
-                    tokio::fs::write(&out_file_path, gen_out.stdout)
-                        .await
-                        .context("failed to write test")?;
-                }
-                crate::manifest::TestGenSpec::File { path } => {
-                    let src_path = self.problem_dir.join("tests").join(path);
-                    if let Err(e) = tokio::fs::copy(&src_path, &out_file_path).await {
-                        anyhow::bail!(
-                            "Couldn't copy test data from {} to {}: {}",
-                            src_path.display(),
-                            out_file_path,
-                            e,
-                        );
-                    }
-                }
+    /// Copies files matching `attachments` patterns into package,
+    /// preserving their paths relative to problem dir
+    async fn copy_attachments(&self) -> anyhow::Result<Vec<FileRef>> {
+        let mut paths = std::collections::BTreeSet::new();
+        for pattern in &self.cfg.attachments {
+            let matched: Vec<PathBuf> = self
+                .glob(pattern)
+                .await?
+                .into_iter()
+                .filter(|path| path.is_file())
+                .collect();
+            if matched.is_empty() {
+                anyhow::bail!("attachment pattern {} does not match any file", pattern);
             }
-            let mut test_info = pom::Test {
-                path: FileRef {
-                    path: format!("tests/{}-in.txt", tid),
-                    root: FileRefRoot::Problem,
-                },
-                correct: None,
-                limits: merge_limits(&[self.cfg.limits, test_spec.limits]),
-                group: test_spec.group.clone(),
+            paths.extend(matched);
+        }
+        let mut out = Vec::new();
+        for src in paths {
+            let rel_path = src
+                .strip_prefix(self.problem_dir)
+                .context("attachment is outside of problem dir")?;
+            let rel_path = rel_path.to_str().context("utf8 error")?;
+            crate::manifest::check_relative_path("attachment", rel_path)?;
+            let attachment = FileRef {
+                root: FileRefRoot::Attachments,
+                path: rel_path.to_string(),
             };
-            if let Some(cmd) = gen_answers {
-                let test_data = tokio::fs::File::open(&out_file_path).await?;
-
-                let correct_file_path = format!("{}/{}-out.txt", &tests_path, tid);
-
-                let answer_data = tokio::fs::File::create(&correct_file_path).await?;
-
-                let mut cmd = cmd.clone();
-                self.configure_command(&mut cmd);
-                let mut cmd = cmd.to_tokio_command();
-                let mut close_handles = vec![];
-                unsafe {
-                    let test_data_fd = test_data.into_std().await.into_raw_fd();
-                    close_handles.push(test_data_fd);
-                    let test_data_fd = libc::dup(test_data_fd);
-                    close_handles.push(test_data_fd);
-
-                    let ans_data_fd = answer_data.into_std().await.into_raw_fd();
-                    close_handles.push(ans_data_fd);
-                    let ans_data_fd = libc::dup(ans_data_fd);
-                    close_handles.push(ans_data_fd);
-                    cmd.pre_exec(move || {
-                        if libc::dup2(test_data_fd, 0) == -1 {
-                            return Err(std::io::Error::last_os_error());
-                        }
-                        if libc::dup2(ans_data_fd, 1) == -1 {
-                            return Err(std::io::Error::last_os_error());
-                        }
-                        Ok(())
-                    });
-                }
-                let output = cmd
-                    .stdin(Stdio::piped())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .output()
-                    .await
-                    .context("launch main solution error: {}")?;
-                if !output.status.success() {
-                    anyhow::bail!(
-                        "Error while generating correct answer for test {}: main solution failed: {}",
-                        tid,
-                        String::from_utf8_lossy(&output.stderr)
-                    );
-                }
-                let short_file_path = format!("tests/{}-out.txt", tid);
-                test_info.correct.replace(FileRef {
-                    path: short_file_path,
-                    root: FileRefRoot::Problem,
-                });
-                for handle in close_handles {
-                    unsafe {
-                        libc::close(handle);
-                    }
-                }
+            let dest = attachment.resolve(self.out_dir);
+            if let Some(parent) = dest.parent() {
+                tokio::fs::create_dir_all(parent).await?;
             }
-            out.push(test_info);
+            tokio::fs::copy(&src, &dest)
+                .await
+                .with_context(|| format!("failed to copy attachment {}", src.display()))?;
+            out.push(attachment);
         }
         Ok(out)
     }
 
-    /// Builds all checkers (currently only one is supported)
-    async fn build_checkers(&mut self) -> anyhow::Result<FileRef> {
-        // TODO: support multi-file checkers
-        let checker_path = format!("{}/checkers/main.cpp", self.problem_dir.display());
-        self.build_checker(&checker_path).await
-    }
-
-    /// Builds single checker
-    async fn build_checker(&mut self, checker_path: &str) -> anyhow::Result<FileRef> {
-        let out_path = self.out_dir.join("assets/checker");
-        self.pw.send(CompileUpdate::BuildChecker).await;
-        match &self.cfg.check {
-            crate::manifest::Check::Custom(_) => {
-                self.do_build(Path::new(checker_path), Path::new(&out_path))
-                    .await?;
-                Ok(FileRef {
-                    path: "checker/bin".to_string(),
-                    root: FileRefRoot::Problem,
-                })
+    /// Copies statements into `assets/statements/{lang}/`
+    async fn copy_statements(&self) -> anyhow::Result<Vec<pom::Statement>> {
+        let mut out = Vec::new();
+        for statement in &self.cfg.statements {
+            let src = self.problem_dir.join(&statement.path);
+            let file_name = src
+                .file_name()
+                .and_then(|name| name.to_str())
+                .with_context(|| format!("statement path {} is invalid", statement.path))?;
+            let file = FileRef {
+                root: FileRefRoot::Problem,
+                path: format!("statements/{}/{}", statement.lang, file_name),
+            };
+            if out
+                .iter()
+                .any(|s: &pom::Statement| s.file.path == file.path)
+            {
+                anyhow::bail!(
+                    "several {} statements are named {}",
+                    statement.lang,
+                    file_name
+                );
             }
-            crate::manifest::Check::Builtin(bc) => {
-                let src_path = self
-                    .build_env
-                    .join(format!("bin/builtin-checker-{}", bc.name));
-                tokio::fs::create_dir(&out_path)
-                    .await
-                    .context("failed to create out directory")?;
-                tokio::fs::copy(&src_path, &out_path.join("bin"))
-                    .await
-                    .context("failed to copy checker binary")?;
-                Ok(FileRef {
-                    path: "checker/bin".to_string(),
-                    root: FileRefRoot::Problem,
-                })
+            let dest = file.resolve(self.out_dir);
+            if let Some(parent) = dest.parent() {
+                tokio::fs::create_dir_all(parent).await?;
             }
+            tokio::fs::copy(&src, &dest)
+                .await
+                .with_context(|| format!("failed to copy statement {}", src.display()))?;
+            out.push(pom::Statement {
+                lang: statement.lang.clone(),
+                file,
+            });
         }
+        Ok(out)
     }
 
-    /// Builds all modules
-    ///
-    /// Module is user-defined program. PPC only builds module and places
-    /// binaries into compiled problem assets.
-    async fn build_modules(&self) -> anyhow::Result<()> {
-        for module in self.glob("modules/*").await? {
-            let module_name = module.file_name().unwrap().to_str().expect("utf8 error");
-            let output_path = self
-                .out_dir
-                .join("assets")
-                .join(format!("module-{}", module_name));
-            self.do_build(&module, Path::new(&output_path)).await?;
+    /// Runs test generator once under given limits, with given random seed.
+    /// If `stdin_data` is set, it is written to generator stdin.
+    /// Testlib generators additionally get seed as last argument.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_testgen(
+        &self,
+        testgen_name: &str,
+        testgen_cmd: &Command,
+        args: &[String],
+        stdin_data: Option<&[u8]>,
+        tid: usize,
+        entropy: &str,
+        limits: &Limits,
+    ) -> anyhow::Result<std::process::Output> {
+        let mut cmd = testgen_cmd.clone();
+        for a in args {
+            cmd.arg(a);
         }
-        Ok(())
+        if self
+            .cfg
+            .testlib_generators
+            .iter()
+            .any(|name| name == testgen_name)
+        {
+            cmd.arg(entropy);
+        }
+        cmd.env("JJS_TEST_ID", &tid.to_string());
+        cmd.env("JJS_RANDOM_SEED", entropy);
+        self.configure_command(&mut cmd);
+        let stdin = match stdin_data {
+            Some(data) => ChildInput::Data(data),
+            None => ChildInput::Null,
+        };
+        let output = cmd.run_with_limits_redirected(limits, stdin, None).await;
+        output
+            .and_then(|out| cmd.check_success(out))
+            .with_context(|| {
+                format!(
+                    "generator `{}` failed for test {} with args [{}]",
+                    testgen_name,
+                    tid,
+                    args.join(", ")
+                )
+            })
     }
 
-    /// Copies files that should just be copied as is.
-    /// Currently, only such file is valuer config
-    async fn copy_raw(&mut self) -> anyhow::Result<()> {
-        let valuer_cfg_dir = self.out_dir.join("assets/valuer-cfg");
-        if let Some(valuer_cfg) = &self.cfg.valuer_cfg {
-            self.pw.send(CompileUpdate::CopyValuerConfig).await;
+    /// Runs primary solution on test input to get correct answer.
+    /// Solution is run under given limits.
+    async fn generate_answer(
+        &self,
+        cmd: &Command,
+        tid: usize,
+        input_path: &Path,
+        answer_path: &Path,
+        work_dir: Option<&Path>,
+        limits: &Limits,
+    ) -> anyhow::Result<()> {
+        // each run opens its own input, so that runs can be concurrent
+        let stdin = ChildInput::File(tokio::fs::File::open(input_path).await?.into_std().await);
+        let answer_data = tokio::fs::File::create(answer_path).await?.into_std().await;
 
-            let src = self.problem_dir.join(valuer_cfg.trim_start_matches('/'));
-            let dest = valuer_cfg_dir.join("cfg.yaml");
-            tokio::fs::create_dir(&valuer_cfg_dir).await?;
-            if src.is_file() {
-                tokio::fs::copy(&src, &dest).await?;
-            } else {
-                // TODO
-                anyhow::bail!("Multi-file valuer config is TODO");
+        let mut cmd = cmd.clone();
+        if let Some(dir) = work_dir {
+            cmd.current_dir(dir);
+        }
+        self.configure_command(&mut cmd);
+        let output = cmd
+            .run_with_limits_redirected(limits, stdin, Some(answer_data))
+            .await
+            .map_err(|err| BuildError::AnswerGenerationFailed {
+                test_id: tid,
+                source: err.into(),
+            })?;
+        if !output.status.success() {
+            return Err(BuildError::AnswerGenerationFailed {
+                test_id: tid,
+                source: format!(
+                    "main solution failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                )
+                .into(),
             }
+            .into());
         }
         Ok(())
     }
 
-    /// Main method, which actually builds the problem into
-    /// redistributable package.
-    pub async fn build(&mut self) -> anyhow::Result<()> {
-        self.build_modules().await?;
-        let solutions = self.build_solutions().await?;
-        let testgen_launch_info = self.build_testgens().await?;
-
-        let checker_ref = self
-            .build_checkers()
-            .await
-            .context("failed to build checker")?;
+    /// Returns working directory for commands of the test, creating it
+    async fn test_work_dir(
+        &self,
+        test_spec: &crate::manifest::ExpandedTestSpec,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        match &test_spec.work_dir {
+            Some(dir) => {
+                let dir = self.problem_dir.join(dir);
+                tokio::fs::create_dir_all(&dir)
+                    .await
+                    .with_context(|| format!("failed to create work dir {}", dir.display()))?;
+                Ok(Some(dir))
+            }
+            None => Ok(None),
+        }
+    }
 
-        let checker_cmd = self.cfg.check_options.args.clone();
+    /// Returns test specs in test id order
+    async fn test_specs(&self) -> anyhow::Result<Vec<crate::manifest::ExpandedTestSpec>> {
+        let mut test_specs = self.expand_test_specs().await?;
+        if self.cfg.reorder_groups {
+            reorder_by_group(&mut test_specs);
+        }
+        Ok(test_specs)
+    }
 
-        let tests = {
-            let gen_answers = match &self.cfg.check {
-                crate::manifest::Check::Custom(cs) => cs.pass_correct,
-                crate::manifest::Check::Builtin(_) => true,
-            };
-            let gen_answers = if gen_answers {
-                let primary_solution_name = self.cfg.primary_solution.as_ref().context(
-                    "primary-solution must be specified in order to generate tests correct answers",
-                )?;
-                let sol_data = match solutions.get(primary_solution_name.as_str()) {
-                    Some(d) => d,
-                    None => {
-                        eprint!("Following solutions are defined: ");
-                        for sol_name in solutions.keys() {
-                            eprint!("{} ", sol_name);
-                        }
-                        anyhow::bail!("Unknown solution {}", primary_solution_name)
-                    }
-                };
-                Some(sol_data)
-            } else {
-                None
-            };
-            self.build_tests(&testgen_launch_info, gen_answers).await?
+    /// Builds all tests.
+    /// If `answer_solutions` is not empty, correct answers are generated
+    /// by each of them, and all solutions must produce the same output.
+    async fn build_tests(&mut self, ctx: &TestContext<'_>) -> anyhow::Result<Vec<pom::Test>> {
+        let tests_path = self.out_dir.join("assets/tests");
+        // created once before tests are generated, so that test builds
+        // never race on directory creation
+        tokio::fs::create_dir_all(&tests_path)
+            .await
+            .with_context(|| {
+                format!("couldn't create tests output dir {}", tests_path.display())
+            })?;
+        let test_specs = self.test_specs().await?;
+        self.pw
+            .send(CompileUpdate::GenerateTests {
+                count: test_specs.len(),
+            })
+            .await;
+        // files of shuffled tests are renamed after build, so they do not
+        // correspond to fingerprints of previous build
+        let old_fingerprints = if self.incremental && self.cfg.shuffle_tests.is_none() {
+            Fingerprints::load(self.out_dir).await
+        } else {
+            Fingerprints::default()
         };
-        self.copy_raw().await?;
-
-        let valuer_exe = {
-            let src = self.build_env.join("bin/svaluer");
-            let dest = self.out_dir.join("assets/valuer");
-            tokio::fs::copy(&src, &dest)
-                .await
-                .context("failed to copy valuer binary")?;
-            FileRef {
-                root: FileRefRoot::Problem,
-                path: "valuer".to_string(),
-            }
+        let mut fingerprints = Fingerprints::default();
+        // everything except tests is already in package
+        let mut package_size = match self.cfg.max_package_bytes {
+            Some(_) => dir_size(self.out_dir, &tests_path).await?,
+            None => 0,
         };
-
+        let mut out = vec![];
+        let mut pending_answers = Vec::new();
+        // fingerprints of (group, input) pairs of generated tests
+        let mut seen_inputs = std::collections::BTreeSet::new();
+        let mut duplicates = 0;
+        for test_spec in &test_specs {
+            // removed duplicates do not take ids, so numbering stays contiguous
+            let tid = out.len() + 1;
+            let (test_info, fingerprint, pending_answer) = self
+                .build_test(ctx, tid, test_spec, old_fingerprints.tests.get(&tid))
+                .await?;
+            self.advance_progress(1).await;
+            let generated = !matches!(test_spec.gen, crate::manifest::ExpandedGenSpec::File { .. });
+            if self.cfg.dedup && generated {
+                let mut input_fingerprint = Fingerprinter::new();
+                input_fingerprint.add(&test_spec.group);
+                input_fingerprint
+                    .add_file(&test_info.path.resolve(self.out_dir))
+                    .await?;
+                if !seen_inputs.insert(input_fingerprint.finish()) {
+                    tracing::info!(test_id = tid, "removing duplicate test");
+                    // pending answer of duplicate is dropped
+                    remove_test_files(&test_info, self.out_dir).await?;
+                    duplicates += 1;
+                    continue;
+                }
+            }
+            if self.cfg.max_package_bytes.is_some() {
+                // pending answers are counted after they are generated
+                package_size += files_size(std::iter::once(&test_info.path), self.out_dir).await?;
+                if pending_answer.is_none() {
+                    package_size += files_size(test_info.correct_answers(), self.out_dir).await?;
+                }
+                self.check_package_size(package_size, format_args!("test {}", tid))?;
+            }
+            fingerprints.tests.insert(tid, fingerprint);
+            out.push(test_info);
+            pending_answers.extend(pending_answer);
+        }
+        if duplicates != 0 {
+            self.pw
+                .send(CompileUpdate::DuplicateTests { count: duplicates })
+                .await;
+        }
+        let this = &*self;
+        let tests = &out;
+        let package_size = &AtomicU64::new(package_size);
+        futures::stream::iter(pending_answers)
+            .map(|pending| async move {
+                let test_info = &tests[pending.tid - 1];
+                this.generate_pending_answer(ctx, &pending, test_info, Some(package_size))
+                    .await
+            })
+            .buffer_unordered(self.answer_jobs)
+            .try_for_each(|_| async { Ok(()) })
+            .await?;
+        fingerprints.store(self.out_dir).await?;
+        Ok(out)
+    }
+
+    /// Fails if `package_size` exceeds `max-package-bytes`.
+    /// `after` describes last added file, e.g. `test 5`.
+    fn check_package_size(
+        &self,
+        package_size: u64,
+        after: std::fmt::Arguments<'_>,
+    ) -> anyhow::Result<()> {
+        match self.cfg.max_package_bytes {
+            Some(max_package_bytes) if package_size > max_package_bytes => anyhow::bail!(
+                "package size exceeds max-package-bytes of {} bytes after {} ({} bytes)",
+                max_package_bytes,
+                after,
+                package_size
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Builds single test. Its input (and answer) is reused if it
+    /// matches `old_fingerprint`. If answer must be generated, it is
+    /// returned as pending, and `correct` already refers to it.
+    async fn build_test(
+        &mut self,
+        ctx: &TestContext<'_>,
+        tid: usize,
+        test_spec: &crate::manifest::ExpandedTestSpec,
+        old_fingerprint: Option<&TestFingerprint>,
+    ) -> anyhow::Result<(pom::Test, TestFingerprint, Option<PendingAnswer>)> {
+        let input_ref = self.input_ref(tid);
+        let out_file_path = input_ref.resolve(self.out_dir);
+        let input_fingerprint = self.test_input_fingerprint(test_spec, ctx.testgens).await?;
+        let reuse_input = old_fingerprint.map_or(false, |old| old.input == input_fingerprint)
+            && out_file_path.is_file();
+        if reuse_input {
+            self.pw.send(CompileUpdate::SkipTest { test_id: tid }).await;
+        } else {
+            self.pw
+                .send(CompileUpdate::GenerateTest { test_id: tid })
+                .await;
+        }
+        let work_dir = self.test_work_dir(test_spec).await?;
+        let mut limits = merge_limits(&[self.cfg.limits, test_spec.limits]);
+        let raw_time = limits.time;
+        scale_time_limit(&mut limits, self.time_scale);
+        tracing::debug!(test_id = tid, limits = ?limits, "effective test limits");
+        self.pw
+            .send(CompileUpdate::TestLimits {
+                test_id: tid,
+                limits,
+            })
+            .await;
+        let provenance = match &test_spec.gen {
+            _ if reuse_input => old_fingerprint.and_then(|old| old.provenance.clone()),
+            crate::manifest::ExpandedGenSpec::Generate {
+                testgen,
+                args,
+                retries,
+                stdin_data,
+                filter,
+            } => {
+                let stdin_data = match stdin_data {
+                    Some(stdin_data) => Some(self.read_testgen_stdin(stdin_data).await?),
+                    None => None,
+                };
+                let mut testgen_cmd = ctx
+                    .testgens
+                    .get(testgen)
+                    .with_context(|| format!("error: unknown testgen {}", testgen))?
+                    .clone();
+                if let Some(dir) = &work_dir {
+                    testgen_cmd.current_dir(dir);
+                }
+                let filter_cmd = match filter {
+                    Some(filter) => {
+                        let mut cmd = ctx
+                            .testgens
+                            .get(&filter[0])
+                            .with_context(|| format!("error: unknown filter {}", filter[0]))?
+                            .clone();
+                        for arg in &filter[1..] {
+                            cmd.arg(arg);
+                        }
+                        if let Some(dir) = &work_dir {
+                            cmd.current_dir(dir);
+                        }
+                        self.configure_command(&mut cmd);
+                        Some(cmd)
+                    }
+                    None => None,
+                };
+
+                let retries = retries.unwrap_or(self.cfg.testgen_retries);
+                let gen_limits = helper_limits(&limits);
+                let mut attempt = 0;
+                let (test_data, seed) = loop {
+                    attempt += 1;
+                    let seed = random_seed();
+                    let res = self
+                        .run_testgen(
+                            testgen,
+                            &testgen_cmd,
+                            args,
+                            stdin_data.as_deref(),
+                            tid,
+                            &seed,
+                            &gen_limits,
+                        )
+                        .await;
+                    let res = match res {
+                        Ok(out) => {
+                            let test_data = if self.cfg.normalize_line_endings {
+                                normalize_line_endings(out.stdout)
+                            } else {
+                                out.stdout
+                            };
+                            match &filter_cmd {
+                                Some(filter_cmd) => run_filter(filter_cmd, &test_data, &gen_limits)
+                                    .await
+                                    .map(|()| test_data),
+                                None => Ok(test_data),
+                            }
+                        }
+                        Err(err) => Err(err),
+                    };
+                    match res {
+                        Ok(test_data) => break (test_data, seed),
+                        Err(err) if attempt <= retries => {
+                            tracing::warn!(
+                                test_id = tid,
+                                attempt,
+                                error = %format!("{:#}", err),
+                                "test generator failed, retrying"
+                            );
+                        }
+                        Err(err) => {
+                            return Err(BuildError::GeneratorFailed {
+                                name: testgen.clone(),
+                                test_id: tid,
+                                attempts: attempt,
+                                source: err.into(),
+                            }
+                            .into());
+                        }
+                    }
+                };
+                tokio::fs::write(&out_file_path, &test_data)
+                    .await
+                    .context("failed to write test")?;
+                Some(pom::TestProvenance {
+                    generator: testgen.clone(),
+                    args: args.clone(),
+                    seed,
+                })
+            }
+            crate::manifest::ExpandedGenSpec::File { path } => {
+                let src_path = self.problem_dir.join("tests").join(path);
+                let res = if self.cfg.normalize_line_endings {
+                    match tokio::fs::read(&src_path).await {
+                        Ok(data) => {
+                            tokio::fs::write(&out_file_path, normalize_line_endings(data)).await
+                        }
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    tokio::fs::copy(&src_path, &out_file_path).await.map(drop)
+                };
+                if let Err(e) = res {
+                    anyhow::bail!(
+                        "Couldn't copy test data from {} to {}: {}",
+                        src_path.display(),
+                        out_file_path.display(),
+                        e,
+                    );
+                }
+                None
+            }
+        };
+        let mut test_info = pom::Test {
+            path: input_ref,
+            correct: None,
+            alternate_correct: Vec::new(),
+            limits,
+            group: test_spec.group.clone(),
+            provenance: provenance.clone(),
+            raw_time,
+        };
+        let mut fingerprint = TestFingerprint {
+            input: input_fingerprint,
+            answer: None,
+            provenance,
+        };
+        let mut pending_answer = None;
+        if !ctx.answer_solutions.is_empty() {
+            let correct_ref = self.answer_ref(tid);
+            let answer_fingerprint = ctx.answer_fingerprint(&fingerprint.input, test_spec);
+            let alternate_refs: Vec<FileRef> = ctx
+                .alternate_solutions
+                .iter()
+                .map(|(name, _)| self.alternate_answer_ref(tid, name))
+                .collect();
+            let reuse_answer = reuse_input
+                && old_fingerprint.and_then(|old| old.answer.as_deref())
+                    == Some(answer_fingerprint.as_str())
+                && std::iter::once(&correct_ref)
+                    .chain(&alternate_refs)
+                    .all(|answer| answer.resolve(self.out_dir).is_file());
+            if !reuse_answer {
+                if reuse_input {
+                    self.pw
+                        .send(CompileUpdate::GenerateAnswer { test_id: tid })
+                        .await;
+                }
+                pending_answer = Some(PendingAnswer {
+                    tid,
+                    work_dir,
+                    limits: test_info.limits,
+                });
+            }
+            test_info.correct.replace(correct_ref);
+            test_info.alternate_correct = alternate_refs;
+            fingerprint.answer = Some(answer_fingerprint);
+        }
+        Ok((test_info, fingerprint, pending_answer))
+    }
+
+    /// Generates answer, postponed by `build_test`. If `package_size` is
+    /// given, answer size is added to it and checked against
+    /// `max-package-bytes`.
+    async fn generate_pending_answer(
+        &self,
+        ctx: &TestContext<'_>,
+        pending: &PendingAnswer,
+        test_info: &pom::Test,
+        package_size: Option<&AtomicU64>,
+    ) -> anyhow::Result<()> {
+        let input_path = self.input_ref(pending.tid).resolve(self.out_dir);
+        self.generate_answers(
+            ctx,
+            pending.tid,
+            &input_path,
+            pending.work_dir.as_deref(),
+            &pending.limits,
+        )
+        .await?;
+        if let (Some(package_size), Some(_)) = (package_size, self.cfg.max_package_bytes) {
+            let answers_size = files_size(test_info.correct_answers(), self.out_dir).await?;
+            let size = package_size.fetch_add(answers_size, Ordering::SeqCst) + answers_size;
+            self.check_package_size(size, format_args!("answer of test {}", pending.tid))?;
+        }
+        Ok(())
+    }
+
+    /// Returns input file of the test `tid`
+    fn input_ref(&self, tid: usize) -> FileRef {
+        FileRef {
+            path: format!("tests/{}", self.cfg.test_naming.input_name(tid)),
+            root: FileRefRoot::Problem,
+        }
+    }
+
+    /// Returns correct answer file of the test `tid`
+    fn answer_ref(&self, tid: usize) -> FileRef {
+        FileRef {
+            path: format!("tests/{}", self.cfg.test_naming.answer_name(tid)),
+            root: FileRefRoot::Problem,
+        }
+    }
+
+    /// Returns alternate answer file of the test `tid`, generated by
+    /// solution `solution`
+    fn alternate_answer_ref(&self, tid: usize, solution: &str) -> FileRef {
+        FileRef {
+            path: format!(
+                "tests/{}",
+                self.cfg.test_naming.alternate_answer_name(tid, solution)
+            ),
+            root: FileRefRoot::Problem,
+        }
+    }
+
+    /// Generates correct answer of the test using first answer solution,
+    /// and checks that other answer solutions agree with it. Then
+    /// alternate answers are generated.
+    /// Solutions are run with `answer_gen_time_limit` and without other
+    /// limits (see `helper_limits`).
+    async fn generate_answers(
+        &self,
+        ctx: &TestContext<'_>,
+        tid: usize,
+        input_path: &Path,
+        work_dir: Option<&Path>,
+        test_limits: &Limits,
+    ) -> anyhow::Result<()> {
+        let ((main_name, main_cmd), other_solutions) = match ctx.answer_solutions.split_first() {
+            Some(solutions) => solutions,
+            None => return Ok(()),
+        };
+        let limits = &Limits {
+            time: Some(self.cfg.answer_gen_time_limit),
+            cpu_time: None,
+            ..helper_limits(test_limits)
+        };
+        let correct_file_path = self.answer_ref(tid).resolve(self.out_dir);
+        self.generate_answer(
+            main_cmd,
+            tid,
+            input_path,
+            &correct_file_path,
+            work_dir,
+            limits,
+        )
+        .await?;
+        if !other_solutions.is_empty() {
+            let expected = tokio::fs::read(&correct_file_path).await?;
+            // outputs of other solutions never get into package, so they
+            // are written to scratch dir, removed even if build aborts
+            let mut suffix = [0; 8];
+            get_entropy_hex(&mut suffix);
+            let scratch_dir = std::env::temp_dir()
+                .join(format!("pps-answers-{}", String::from_utf8_lossy(&suffix)));
+            tokio::fs::create_dir(&scratch_dir)
+                .await
+                .with_context(|| format!("failed to create {}", scratch_dir.display()))?;
+            let _scratch_dir_guard = BuildDirGuard(scratch_dir.clone());
+            for (name, cmd) in other_solutions {
+                let alt_file_path = scratch_dir.join(format!("{}.txt", name));
+                self.generate_answer(cmd, tid, input_path, &alt_file_path, work_dir, limits)
+                    .await?;
+                let actual = tokio::fs::read(&alt_file_path).await?;
+                if actual != expected {
+                    anyhow::bail!(
+                        "answer solutions {} and {} produced different answers for test {}",
+                        main_name,
+                        name,
+                        tid
+                    );
+                }
+            }
+        }
+        for (name, cmd) in &ctx.alternate_solutions {
+            let alt_file_path = self.alternate_answer_ref(tid, name).resolve(self.out_dir);
+            self.generate_answer(cmd, tid, input_path, &alt_file_path, work_dir, limits)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Replaces each `GenerateFromFile` spec with `Generate` specs, one
+    /// per line of arguments file. Empty lines and lines starting with `#`
+    /// are skipped. Arguments are separated by whitespace or commas, there
+    /// is no quoting.
+    async fn expand_test_specs(&self) -> anyhow::Result<Vec<crate::manifest::ExpandedTestSpec>> {
+        let mut out = Vec::new();
+        for test_spec in &self.cfg.tests {
+            let (testgen, args, args_file, retries, stdin_data, filter) = match &test_spec.gen {
+                crate::manifest::TestGenSpec::GenerateFromFile {
+                    testgen,
+                    args,
+                    args_file,
+                    retries,
+                    stdin_data,
+                    filter,
+                } => (testgen, args, args_file, retries, stdin_data, filter),
+                _ => {
+                    out.extend(test_spec.expanded());
+                    continue;
+                }
+            };
+            let args_file_path = self.problem_dir.join(args_file);
+            let content = tokio::fs::read_to_string(&args_file_path)
+                .await
+                .with_context(|| format!("failed to read {}", args_file_path.display()))?;
+            for line in content.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut test_args = args.clone();
+                test_args.extend(
+                    line.split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|arg| !arg.is_empty())
+                        .map(ToString::to_string),
+                );
+                out.push(
+                    test_spec.with_gen(crate::manifest::ExpandedGenSpec::Generate {
+                        testgen: testgen.clone(),
+                        args: test_args,
+                        retries: *retries,
+                        stdin_data: stdin_data.clone(),
+                        filter: filter.clone(),
+                    }),
+                );
+            }
+        }
+        if let Some(tree) = &self.cfg.tests_tree {
+            out.extend(self.tests_tree_specs(tree).await?);
+        }
+        Ok(out)
+    }
+
+    /// Returns test specs for all files in group subdirectories of tests
+    /// tree. Groups and tests in each group are ordered by file name.
+    async fn tests_tree_specs(
+        &self,
+        tree: &crate::manifest::TestsTree,
+    ) -> anyhow::Result<Vec<crate::manifest::ExpandedTestSpec>> {
+        let tests_dir = self.problem_dir.join("tests");
+        let mut out = Vec::new();
+        let mut found_groups = std::collections::BTreeSet::new();
+        for group_dir in sorted_dir_entries(&tests_dir.join(&tree.dir)).await? {
+            if !group_dir.is_dir() {
+                continue;
+            }
+            let group = group_dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .with_context(|| format!("group dir {} is not utf8", group_dir.display()))?
+                .to_string();
+            for file in sorted_dir_entries(&group_dir).await? {
+                if !file.is_file() {
+                    continue;
+                }
+                let path = file
+                    .strip_prefix(&tests_dir)
+                    .expect("file is inside tests dir")
+                    .to_str()
+                    .with_context(|| format!("test path {} is not utf8", file.display()))?
+                    .to_string();
+                out.push(crate::manifest::ExpandedTestSpec {
+                    gen: crate::manifest::ExpandedGenSpec::File { path },
+                    limits: tree.limits.get(&group).copied().unwrap_or_default(),
+                    group: group.clone(),
+                    work_dir: None,
+                });
+            }
+            found_groups.insert(group);
+        }
+        for group in tree.limits.keys() {
+            if !found_groups.contains(group) {
+                anyhow::bail!("tests tree has limits for missing group {}", group);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns data, which should be written to generator stdin
+    async fn read_testgen_stdin(
+        &self,
+        stdin_data: &crate::manifest::TestgenStdin,
+    ) -> anyhow::Result<Vec<u8>> {
+        match stdin_data {
+            crate::manifest::TestgenStdin::Data(data) => Ok(data.clone().into_bytes()),
+            crate::manifest::TestgenStdin::File(path) => {
+                let path = self.problem_dir.join(path);
+                tokio::fs::read(&path)
+                    .await
+                    .with_context(|| format!("failed to read {}", path.display()))
+            }
+        }
+    }
+
+    /// Computes fingerprint of test input. It changes when test generator,
+    /// its arguments, seed policy or source test file change.
+    async fn test_input_fingerprint(
+        &self,
+        test_spec: &crate::manifest::ExpandedTestSpec,
+        testgens: &BTreeMap<String, Command>,
+    ) -> anyhow::Result<String> {
+        let mut fingerprinter = Fingerprinter::new();
+        fingerprinter
+            .add(&test_spec.work_dir)
+            .add(self.cfg.normalize_line_endings);
+        match &test_spec.gen {
+            crate::manifest::ExpandedGenSpec::Generate {
+                testgen,
+                args,
+                stdin_data,
+                filter,
+                ..
+            } => {
+                fingerprinter
+                    .add("generate")
+                    .add(testgen)
+                    .add(args)
+                    .add(stdin_data)
+                    // testlib generators get random seed as an argument
+                    .add(self.cfg.testlib_generators.contains(testgen));
+                if let Some(testgen_cmd) = testgens.get(testgen) {
+                    fingerprinter.add_command(testgen_cmd).await?;
+                }
+                // tests without filter keep fingerprints of previous versions
+                if let Some(filter) = filter {
+                    fingerprinter.add("filter").add(filter);
+                    if let Some(filter_cmd) = testgens.get(&filter[0]) {
+                        fingerprinter.add_command(filter_cmd).await?;
+                    }
+                }
+                if let Some(crate::manifest::TestgenStdin::File(path)) = stdin_data {
+                    fingerprinter.add_file(&self.problem_dir.join(path)).await?;
+                }
+            }
+            crate::manifest::ExpandedGenSpec::File { path } => {
+                fingerprinter.add("file").add(path);
+                fingerprinter
+                    .add_file(&self.problem_dir.join("tests").join(path))
+                    .await?;
+            }
+        }
+        Ok(fingerprinter.finish())
+    }
+
+    /// Builds all checkers (currently only one is supported).
+    /// Returns checker and commands used to compile it
+    async fn build_checkers(&mut self) -> anyhow::Result<(FileRef, Vec<Vec<String>>)> {
+        // TODO: support multi-file checkers
+        let checker_path = format!("{}/checkers/main.cpp", self.problem_dir.display());
+        self.build_checker(&checker_path).await
+    }
+
+    /// Builds single checker
+    async fn build_checker(
+        &mut self,
+        checker_path: &str,
+    ) -> anyhow::Result<(FileRef, Vec<Vec<String>>)> {
+        let out_path = self.out_dir.join("assets/checker");
+        self.pw.send(CompileUpdate::BuildChecker).await;
+        let checker_ref = FileRef {
+            path: "checker/bin".to_string(),
+            root: FileRefRoot::Problem,
+        };
+        match &self.cfg.check {
+            crate::manifest::Check::Custom(_) => {
+                let success = self
+                    .do_build_task(Path::new(checker_path), Path::new(&out_path))
+                    .await?;
+                Ok((checker_ref, success.build_commands))
+            }
+            crate::manifest::Check::Builtin(bc) => {
+                let checkers_dir = self.builtin_checkers_dir()?;
+                let available = super::scan_builtin_checkers(&checkers_dir);
+                if !available.contains(&bc.name) {
+                    anyhow::bail!(
+                        "unknown builtin checker {} (available: [{}])",
+                        bc.name,
+                        available.join(", ")
+                    );
+                }
+                let src_path =
+                    checkers_dir.join(format!("{}{}", super::BUILTIN_CHECKER_PREFIX, bc.name));
+                tokio::fs::create_dir_all(&out_path)
+                    .await
+                    .context("failed to create out directory")?;
+                tokio::fs::copy(&src_path, &out_path.join("bin"))
+                    .await
+                    .context("failed to copy checker binary")?;
+                Ok((checker_ref, Vec::new()))
+            }
+            crate::manifest::Check::Tokens => {
+                // invoker compares outputs itself, so only marker is emitted
+                tokio::fs::create_dir_all(&out_path)
+                    .await
+                    .context("failed to create out directory")?;
+                tokio::fs::write(out_path.join("builtin"), "tokens\n")
+                    .await
+                    .context("failed to write checker marker")?;
+                Ok((
+                    FileRef {
+                        path: "checker/builtin".to_string(),
+                        root: FileRefRoot::Problem,
+                    },
+                    Vec::new(),
+                ))
+            }
+            crate::manifest::Check::Prebuilt(pc) => {
+                use std::os::unix::fs::PermissionsExt;
+                let src_path = self.problem_dir.join(pc.path.trim_start_matches('/'));
+                let meta = tokio::fs::metadata(&src_path).await.with_context(|| {
+                    format!("prebuilt checker {} not found", src_path.display())
+                })?;
+                if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+                    anyhow::bail!(
+                        "prebuilt checker {} is not executable file",
+                        src_path.display()
+                    );
+                }
+                tokio::fs::create_dir_all(&out_path)
+                    .await
+                    .context("failed to create out directory")?;
+                tokio::fs::copy(&src_path, &out_path.join("bin"))
+                    .await
+                    .context("failed to copy checker binary")?;
+                Ok((checker_ref, Vec::new()))
+            }
+        }
+    }
+
+    /// Builds all modules
+    ///
+    /// Module is user-defined program. PPC only builds module and places
+    /// binaries into compiled problem assets.
+    async fn build_modules(&self) -> anyhow::Result<()> {
+        let modules_dir = self.problem_dir.join("modules");
+        let mut outputs: BTreeMap<String, PathBuf> = BTreeMap::new();
+        for module in self.glob("modules/*").await? {
+            let rel_path = module
+                .strip_prefix(&modules_dir)
+                .context("module is outside of modules directory")?
+                .to_path_buf();
+            let output_name = module_output_name(&rel_path)?;
+            if let Some(prev) = outputs.insert(output_name.clone(), rel_path) {
+                anyhow::bail!(
+                    "modules {} and {} have same output name {}",
+                    prev.display(),
+                    outputs[&output_name].display(),
+                    output_name
+                );
+            }
+        }
+        let modules_dir = &modules_dir;
+        futures::stream::iter(outputs)
+            .map(|(output_name, rel_path)| async move {
+                let output_path = self.out_dir.join("assets").join(&output_name);
+                self.do_build(&modules_dir.join(&rel_path), &output_path)
+                    .await
+                    .with_context(|| format!("failed to build module {}", rel_path.display()))
+            })
+            .buffer_unordered(self.jobs)
+            .try_for_each(|_| async { Ok(()) })
+            .await
+    }
+
+    /// Copies files that should just be copied as is.
+    /// Currently, only such file is valuer config
+    async fn copy_raw(&mut self) -> anyhow::Result<()> {
+        let valuer_cfg_dir = self.out_dir.join("assets/valuer-cfg");
+        if let Some(valuer_cfg) = &self.cfg.valuer_cfg {
+            self.pw.send(CompileUpdate::CopyValuerConfig).await;
+
+            let src = self.problem_dir.join(valuer_cfg.trim_start_matches('/'));
+            let dest = valuer_cfg_dir.join("cfg.yaml");
+            tokio::fs::create_dir_all(&valuer_cfg_dir).await?;
+            if src.is_file() {
+                tokio::fs::copy(&src, &dest).await?;
+            } else {
+                // TODO
+                anyhow::bail!("Multi-file valuer config is TODO");
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns command, launching built checker
+    fn checker_command(&self, checker_ref: &FileRef, checker_cmd: &[String]) -> Command {
+        let mut cmd = Command::new(checker_ref.resolve(self.out_dir));
+        for arg in checker_cmd {
+            cmd.arg(arg);
+        }
+        cmd.env("JJS_ATTACHMENTS", self.attachments_dir());
+        cmd
+    }
+
+    /// Returns limits of checker: defaults, patched by manifest
+    fn checker_limits(&self) -> Limits {
+        let mut limits = Limits::checker_default();
+        self.cfg.checker_limits.apply(&mut limits);
+        limits
+    }
+
+    /// Builds output validator, if it is specified
+    async fn build_output_validator(&mut self) -> anyhow::Result<Option<FileRef>> {
+        let src = match &self.cfg.output_validator {
+            Some(src) => self.problem_dir.join(src.trim_start_matches('/')),
+            None => return Ok(None),
+        };
+        self.pw.send(CompileUpdate::BuildOutputValidator).await;
+        self.do_build_task(&src, &self.out_dir.join("assets/output-validator"))
+            .await
+            .context("failed to build output validator")?;
+        Ok(Some(FileRef {
+            path: "output-validator/bin".to_string(),
+            root: FileRefRoot::Problem,
+        }))
+    }
+
+    /// Runs output validator on each correct answer (including alternate
+    /// ones), passing that answer as solution output, so that bugs in
+    /// answer format are found at build time.
+    async fn validate_answers(
+        &mut self,
+        tests: &[pom::Test],
+        validator_ref: &FileRef,
+    ) -> anyhow::Result<()> {
+        let cmd = self.checker_command(validator_ref, &[]);
+        let limits = self.checker_limits();
+        let validator = checker::CheckerRun {
+            cmd: &cmd,
+            limits: &limits,
+            exit_codes: &self.cfg.checker_exit_codes,
+        };
+        let work_dir = self.out_dir.join("validate-answers");
+        tokio::fs::create_dir_all(&work_dir).await?;
+        for (i, test) in tests.iter().enumerate() {
+            if test.correct.is_none() {
+                continue;
+            }
+            let test_id = i + 1;
+            self.pw
+                .send(CompileUpdate::ValidateAnswer { test_id })
+                .await;
+            let input_path = test.path.resolve(self.out_dir);
+            for correct in test.correct_answers() {
+                let correct_path = correct.resolve(self.out_dir);
+                let res = checker::run_checker(
+                    &validator,
+                    &input_path,
+                    &correct_path,
+                    &correct_path,
+                    &work_dir,
+                )
+                .await
+                .with_context(|| format!("failed to run output validator on test {}", test_id))?;
+                if res.outcome != checker::OUTCOME_OK {
+                    anyhow::bail!(
+                        "output validator rejected correct answer {} of test {}: {} ({})",
+                        correct.path,
+                        test_id,
+                        res.outcome,
+                        res.comment.trim()
+                    );
+                }
+            }
+        }
+        tokio::fs::remove_dir_all(&work_dir).await?;
+        Ok(())
+    }
+
+    /// Runs checker on each test with correct answer, passing each of its
+    /// correct answers as solution output. Checker must accept all of
+    /// them (each against any of correct answers).
+    async fn verify_answers(
+        &mut self,
+        tests: &[pom::Test],
+        checker_ref: &FileRef,
+        checker_cmd: &[String],
+    ) -> anyhow::Result<()> {
+        let cmd = self.checker_command(checker_ref, checker_cmd);
+        let limits = self.checker_limits();
+        let checker = checker::CheckerRun {
+            cmd: &cmd,
+            limits: &limits,
+            exit_codes: &self.cfg.checker_exit_codes,
+        };
+        let work_dir = self.out_dir.join("verify-answers");
+        tokio::fs::create_dir_all(&work_dir).await?;
+        for (i, test) in tests.iter().enumerate() {
+            let corrects: Vec<PathBuf> = test
+                .correct_answers()
+                .map(|correct| correct.resolve(self.out_dir))
+                .collect();
+            if corrects.is_empty() {
+                continue;
+            }
+            let test_id = i + 1;
+            self.pw.send(CompileUpdate::VerifyAnswer { test_id }).await;
+            let input_path = test.path.resolve(self.out_dir);
+            for answer_path in &corrects {
+                let res = checker::run_checker_any(
+                    &checker,
+                    &input_path,
+                    &corrects,
+                    answer_path,
+                    &work_dir,
+                )
+                .await
+                .with_context(|| format!("failed to run checker on test {}", test_id))?;
+                if res.outcome != checker::OUTCOME_OK {
+                    anyhow::bail!(
+                        "checker rejected correct answer {} of test {}: {} ({})",
+                        answer_path.display(),
+                        test_id,
+                        res.outcome,
+                        res.comment.trim()
+                    );
+                }
+            }
+        }
+        tokio::fs::remove_dir_all(&work_dir).await?;
+        Ok(())
+    }
+
+    /// Runs solution on test and judges its output, like invoker does
+    async fn judge_solution(
+        &self,
+        solution: &Command,
+        test: &pom::Test,
+        checker: &checker::CheckerRun<'_>,
+        builtin_comparison: Option<pom::BuiltinComparison>,
+        work_dir: &Path,
+    ) -> anyhow::Result<crate::manifest::Verdict> {
+        use crate::manifest::Verdict;
+        let input_path = test.path.resolve(self.out_dir);
+        let output_path = work_dir.join("output.txt");
+        let mut corrects: Vec<PathBuf> = test
+            .correct_answers()
+            .map(|correct| correct.resolve(self.out_dir))
+            .collect();
+        if corrects.is_empty() {
+            // checker does not use correct answer in this case
+            let path = work_dir.join("empty.txt");
+            tokio::fs::write(&path, "").await?;
+            corrects.push(path);
+        }
+        let mut cmd = solution.clone();
+        self.configure_command(&mut cmd);
+        let stdin = ChildInput::File(tokio::fs::File::open(&input_path).await?.into_std().await);
+        let stdout = tokio::fs::File::create(&output_path)
+            .await?
+            .into_std()
+            .await;
+        // wall-clock limit is applied here, so that it is reported as verdict
+        let limits = Limits {
+            time: None,
+            ..test.limits
+        };
+        let run = cmd.run_with_limits_redirected(&limits, stdin, Some(stdout));
+        let output = match test.limits.time {
+            Some(ms) => match tokio::time::timeout(std::time::Duration::from_millis(ms), run).await
+            {
+                Ok(output) => output?,
+                Err(_) => return Ok(Verdict::TimeLimitExceeded),
+            },
+            None => run.await?,
+        };
+        if !output.status.success() {
+            use std::os::unix::process::ExitStatusExt;
+            if output.status.signal() == Some(libc::SIGXCPU) {
+                return Ok(Verdict::TimeLimitExceeded);
+            }
+            return Ok(Verdict::RuntimeError);
+        }
+        if let Some(comparison) = builtin_comparison {
+            let output = tokio::fs::read(&output_path).await?;
+            for correct_path in &corrects {
+                let answer = tokio::fs::read(correct_path).await?;
+                if comparison.accepts(&output, &answer) {
+                    return Ok(Verdict::Ok);
+                }
+            }
+            return Ok(Verdict::WrongAnswer);
+        }
+        let res = checker::run_checker_any(checker, &input_path, &corrects, &output_path, work_dir)
+            .await
+            .context("failed to run checker")?;
+        Verdict::from_outcome(&res.outcome)
+            .with_context(|| format!("checker returned unknown outcome {}", res.outcome))
+    }
+
+    /// Runs solutions on tests, for which they have expected verdicts,
+    /// and checks that they get these verdicts
+    async fn check_expected_verdicts(
+        &mut self,
+        tests: &[pom::Test],
+        solutions: &BTreeMap<String, Command>,
+        checker_ref: &FileRef,
+        checker_cmd: &[String],
+        builtin_comparison: Option<pom::BuiltinComparison>,
+    ) -> anyhow::Result<()> {
+        let cfg = self.cfg;
+        if cfg.expected_verdicts.is_empty() {
+            return Ok(());
+        }
+        let cmd = self.checker_command(checker_ref, checker_cmd);
+        let limits = self.checker_limits();
+        let checker = checker::CheckerRun {
+            cmd: &cmd,
+            limits: &limits,
+            exit_codes: &cfg.checker_exit_codes,
+        };
+        let work_dir = self.out_dir.join("expected-verdicts");
+        tokio::fs::create_dir_all(&work_dir).await?;
+        for expected in &cfg.expected_verdicts {
+            let test = expected
+                .test
+                .checked_sub(1)
+                .and_then(|i| tests.get(i))
+                .with_context(|| {
+                    format!(
+                        "test {} with expected verdict of solution {} does not exist",
+                        expected.test, expected.solution
+                    )
+                })?;
+            let solution = match solutions.get(&expected.solution) {
+                Some(solution) => solution,
+                // solution is excluded by filter
+                None if self.solutions_filter.is_some() => continue,
+                None => anyhow::bail!("unknown solution {}", expected.solution),
+            };
+            self.pw
+                .send(CompileUpdate::CheckExpectedVerdict {
+                    solution: expected.solution.clone(),
+                    test_id: expected.test,
+                })
+                .await;
+            let verdict = self
+                .judge_solution(solution, test, &checker, builtin_comparison, &work_dir)
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to judge solution {} on test {}",
+                        expected.solution, expected.test
+                    )
+                })?;
+            if verdict != expected.verdict {
+                anyhow::bail!(
+                    "solution {} got {:?} on test {}, but {:?} is expected",
+                    expected.solution,
+                    verdict,
+                    expected.test,
+                    expected.verdict
+                );
+            }
+        }
+        tokio::fs::remove_dir_all(&work_dir).await?;
+        Ok(())
+    }
+
+    /// Runs checker on first test with correct answer: it must accept
+    /// this answer and reject its corrupted version. If test has alternate
+    /// answers, output is accepted if it is accepted against any of them.
+    async fn checker_self_test(
+        &mut self,
+        tests: &[pom::Test],
+        checker_ref: &FileRef,
+        checker_cmd: &[String],
+    ) -> anyhow::Result<()> {
+        let (test_id, test, correct) = match tests
+            .iter()
+            .enumerate()
+            .find_map(|(i, t)| t.correct.as_ref().map(|c| (i + 1, t, c)))
+        {
+            Some(t) => t,
+            None => {
+                self.pw
+                    .send(CompileUpdate::Warnings(vec![
+                        "checker self-test skipped: no test has correct answer".to_string(),
+                    ]))
+                    .await;
+                return Ok(());
+            }
+        };
+        self.pw
+            .send(CompileUpdate::CheckerSelfTest { test_id })
+            .await;
+        let cmd = self.checker_command(checker_ref, checker_cmd);
+        let limits = self.checker_limits();
+        let checker = checker::CheckerRun {
+            cmd: &cmd,
+            limits: &limits,
+            exit_codes: &self.cfg.checker_exit_codes,
+        };
+        let work_dir = self.out_dir.join("checker-self-test");
+        tokio::fs::create_dir_all(&work_dir).await?;
+        let input_path = test.path.resolve(self.out_dir);
+        let correct_path = correct.resolve(self.out_dir);
+        let corrects: Vec<PathBuf> = test
+            .correct_answers()
+            .map(|correct| correct.resolve(self.out_dir))
+            .collect();
+
+        let res =
+            checker::run_checker_any(&checker, &input_path, &corrects, &correct_path, &work_dir)
+                .await
+                .context("failed to run checker on correct answer")?;
+        if res.outcome != checker::OUTCOME_OK {
+            anyhow::bail!(
+                "checker self-test failed: correct answer of test {} got {} ({})",
+                test_id,
+                res.outcome,
+                res.comment.trim()
+            );
+        }
+
+        let perturbed_path = work_dir.join("perturbed.txt");
+        let answer = tokio::fs::read(&correct_path).await?;
+        tokio::fs::write(&perturbed_path, checker::perturb_answer(&answer)).await?;
+        let res =
+            checker::run_checker_any(&checker, &input_path, &corrects, &perturbed_path, &work_dir)
+                .await
+                .context("failed to run checker on corrupted answer")?;
+        if res.outcome == checker::OUTCOME_OK {
+            anyhow::bail!(
+                "checker self-test failed: corrupted answer of test {} was accepted",
+                test_id
+            );
+        }
+        tokio::fs::remove_dir_all(&work_dir).await?;
+        Ok(())
+    }
+
+    /// Removes generated correct answers from the package
+    /// Renames files of shuffled tests after their new ids, so that file
+    /// names still start with test id. `test_order[i]` is old id of test
+    /// `i + 1`.
+    async fn rename_shuffled_tests(
+        &self,
+        tests: &mut [pom::Test],
+        test_order: &[usize],
+    ) -> anyhow::Result<()> {
+        // new name of one file can be old name of another, so files are
+        // moved to temporary names first
+        let mut renames = Vec::new();
+        for (i, test) in tests.iter_mut().enumerate() {
+            let (old_id, new_id) = (test_order[i], i + 1);
+            if old_id == new_id {
+                continue;
+            }
+            let files = std::iter::once(&mut test.path)
+                .chain(test.correct.iter_mut())
+                .chain(test.alternate_correct.iter_mut());
+            for file in files {
+                if !matches!(file.root, FileRefRoot::Problem) {
+                    continue;
+                }
+                let src = file.resolve(self.out_dir);
+                file.path = pom::renumber_path(&file.path, old_id, new_id).with_context(|| {
+                    format!(
+                        "file {} of test {} is not named after it",
+                        file.path, old_id
+                    )
+                })?;
+                let dest = file.resolve(self.out_dir);
+                let mut tmp = dest.clone().into_os_string();
+                tmp.push(".shuffled");
+                let tmp = PathBuf::from(tmp);
+                tokio::fs::rename(&src, &tmp)
+                    .await
+                    .with_context(|| format!("failed to move {}", src.display()))?;
+                renames.push((tmp, dest));
+            }
+        }
+        for (tmp, dest) in renames {
+            tokio::fs::rename(&tmp, &dest)
+                .await
+                .with_context(|| format!("failed to move {}", tmp.display()))?;
+        }
+        Ok(())
+    }
+
+    async fn strip_answers(&self, tests: &mut [pom::Test]) -> anyhow::Result<()> {
+        for test in tests {
+            let answers = test.correct.take().into_iter();
+            for correct in answers.chain(std::mem::take(&mut test.alternate_correct)) {
+                let path = correct.resolve(self.out_dir);
+                tokio::fs::remove_file(&path)
+                    .await
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns solutions, which generate correct answers.
+    /// Returns empty list if answers should not be generated.
+    async fn answer_solutions<'s>(
+        &mut self,
+        solutions: &'s BTreeMap<String, Command>,
+    ) -> anyhow::Result<Vec<(&'a str, &'s Command)>> {
+        let cfg = self.cfg;
+        if let Some(name) = &cfg.primary_solution {
+            find_solution(solutions, name)?;
+        }
+        let answer_solution_names = if cfg.answer_solutions.is_empty() {
+            cfg.primary_solution.iter().collect::<Vec<_>>()
+        } else {
+            cfg.answer_solutions.iter().collect()
+        };
+        let mut answer_solutions = Vec::new();
+        for name in answer_solution_names {
+            answer_solutions.push((name.as_str(), find_solution(solutions, name)?));
+        }
+        let gen_answers = match &cfg.check {
+            crate::manifest::Check::Custom(cs) => cs.pass_correct,
+            crate::manifest::Check::Builtin(_) => true,
+            crate::manifest::Check::Prebuilt(pc) => pc.pass_correct,
+            crate::manifest::Check::Tokens => true,
+        };
+        if gen_answers {
+            if answer_solutions.is_empty() {
+                return Err(BuildError::PrimarySolutionMissing.into());
+            }
+        } else if !answer_solutions.is_empty() {
+            self.pw
+                .send(CompileUpdate::Warnings(vec![format!(
+                    "solutions [{}] are unused, because correct answers are not generated",
+                    answer_solutions
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )]))
+                .await;
+            answer_solutions.clear();
+        }
+        Ok(answer_solutions)
+    }
+
+    /// Returns solutions, which generate alternate answers. Returns empty
+    /// list if answers should not be generated, i.e. `answer_solutions`
+    /// is empty.
+    fn alternate_answer_solutions<'s>(
+        &self,
+        solutions: &'s BTreeMap<String, Command>,
+        answer_solutions: &[(&str, &Command)],
+    ) -> anyhow::Result<Vec<(&'a str, &'s Command)>> {
+        if answer_solutions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let cfg = self.cfg;
+        cfg.alternate_answer_solutions
+            .iter()
+            .map(|name| find_solution(solutions, name).map(|cmd| (name.as_str(), cmd)))
+            .collect()
+    }
+
+    /// Main method, which actually builds the problem into
+    /// redistributable package.
+    pub async fn build(&mut self) -> Result<(), BuildError> {
+        let marker = self.out_dir.join(INCOMPLETE_MARKER);
+        if self.incremental && marker.exists() {
+            self.pw
+                .send(CompileUpdate::Warnings(vec![
+                    "previous build did not finish, so everything is rebuilt".to_string(),
+                ]))
+                .await;
+            self.incremental = false;
+        }
+        tokio::fs::write(&marker, "")
+            .await
+            .with_context(|| format!("failed to create {}", marker.display()))?;
+        self.build_package()
+            .await
+            .map_err(BuildError::from_anyhow)?;
+        tokio::fs::remove_file(&marker)
+            .await
+            .with_context(|| format!("failed to remove {}", marker.display()))?;
+        Ok(())
+    }
+
+    /// Reports unused generators and solutions. They are warnings,
+    /// unless build is strict.
+    async fn lint(&mut self) -> anyhow::Result<()> {
+        let lints = crate::validate::find_unused(self.problem_dir, self.cfg);
+        if lints.is_empty() {
+            return Ok(());
+        }
+        let messages: Vec<String> = lints.iter().map(ToString::to_string).collect();
+        if self.strict_build {
+            anyhow::bail!("strict build: {}", messages.join("; "));
+        }
+        self.pw.send(CompileUpdate::Warnings(messages)).await;
+        Ok(())
+    }
+
+    /// Marks `units` of planned work as done and reports overall progress.
+    /// Does nothing if work was not planned (i.e. build is not full).
+    async fn advance_progress(&mut self, units: usize) {
+        if self.progress.total == 0 {
+            return;
+        }
+        self.progress.done += units;
+        let progress = self.progress.fraction();
+        self.pw.send(CompileUpdate::Progress { progress }).await;
+    }
+
+    async fn build_package(&mut self) -> anyhow::Result<()> {
+        self.preflight()?;
+        let test_count = self.test_specs().await?.len();
+        // such package can not be scored, so fail before anything is built
+        if !self.cfg.allow_empty_tests && test_count == 0 {
+            return Err(BuildError::NoTests.into());
+        }
+        self.lint().await?;
+        let modules_count = self.glob("modules/*").await?.len();
+        let solutions_count = self.glob("solutions/*").await?.len();
+        let testgens_count = self.glob("generators/*").await?.len();
+        // checker and output validator are counted as programs
+        let validators_count = usize::from(self.cfg.output_validator.is_some());
+        let programs_count =
+            modules_count + solutions_count + testgens_count + 1 + validators_count;
+        self.progress = ProgressEstimate {
+            total: programs_count * PROGRAM_PROGRESS_WEIGHT + test_count + FINISH_PROGRESS_WEIGHT,
+            done: 0,
+        };
+
+        self.build_modules().await?;
+        self.advance_progress(modules_count * PROGRAM_PROGRESS_WEIGHT)
+            .await;
+        // attachments are copied first, because generators can read them
+        let attachments = self.copy_attachments().await?;
+        let statements = self.copy_statements().await?;
+        let solutions = self.build_solutions().await?;
+        self.advance_progress(solutions_count * PROGRAM_PROGRESS_WEIGHT)
+            .await;
+        let testgen_launch_info = self.build_testgens().await?;
+        self.advance_progress(testgens_count * PROGRAM_PROGRESS_WEIGHT)
+            .await;
+
+        let (checker_ref, checker_build_commands) = self
+            .build_checkers()
+            .await
+            .map_err(|err| BuildError::CheckerBuildFailed(err.into()))?;
+        self.advance_progress(PROGRAM_PROGRESS_WEIGHT).await;
+        let output_validator_exe = self.build_output_validator().await?;
+        self.advance_progress(validators_count * PROGRAM_PROGRESS_WEIGHT)
+            .await;
+
+        let checker_cmd = self.cfg.check_options.args.clone();
+        let builtin_comparison = match self.cfg.check {
+            crate::manifest::Check::Tokens => Some(pom::BuiltinComparison::Tokens),
+            _ => None,
+        };
+
+        let mut tests = {
+            let answer_solutions = self.answer_solutions(&solutions).await?;
+            let alternate_solutions =
+                self.alternate_answer_solutions(&solutions, &answer_solutions)?;
+            let ctx = TestContext::new(&testgen_launch_info, answer_solutions, alternate_solutions)
+                .await?;
+            self.build_tests(&ctx).await?
+        };
+        let bad_groups = non_contiguous_groups(tests.iter().map(|test| test.group.as_str()));
+        if !bad_groups.is_empty() {
+            self.pw
+                .send(CompileUpdate::Warnings(vec![format!(
+                    "tests of groups [{}] do not form contiguous ranges (set reorder-groups to fix)",
+                    bad_groups.join(", ")
+                )]))
+                .await;
+        }
+        // token comparison always accepts correct answers and rejects
+        // corrupted ones, so there is nothing to check
+        if self.cfg.verify_answers && builtin_comparison.is_none() {
+            self.verify_answers(&tests, &checker_ref, &checker_cmd)
+                .await?;
+        }
+        if self.cfg.checker_self_test && builtin_comparison.is_none() {
+            self.checker_self_test(&tests, &checker_ref, &checker_cmd)
+                .await?;
+        }
+        if let Some(validator_ref) = &output_validator_exe {
+            self.validate_answers(&tests, validator_ref).await?;
+        }
+        self.check_expected_verdicts(
+            &tests,
+            &solutions,
+            &checker_ref,
+            &checker_cmd,
+            builtin_comparison,
+        )
+        .await?;
+        if self.cfg.strip_answers {
+            self.strip_answers(&mut tests).await?;
+        }
+        let test_order = self
+            .cfg
+            .shuffle_tests
+            .map(|seed| shuffle_within_groups(&mut tests, seed));
+        if let Some(test_order) = &test_order {
+            self.rename_shuffled_tests(&mut tests, test_order).await?;
+        }
+        self.copy_raw().await?;
+
+        let valuer_exe = FileRef {
+            root: FileRefRoot::Problem,
+            path: "valuer".to_string(),
+        };
+        let valuer_path = valuer_exe.resolve(self.out_dir);
+        tokio::fs::copy(self.valuer_exe_path()?, &valuer_path)
+            .await
+            .context("failed to copy valuer binary")?;
+        let valuer_version = match query_valuer_version(&valuer_path).await {
+            Ok(version) => Some(version),
+            Err(err) => {
+                self.pw
+                    .send(CompileUpdate::Warnings(vec![format!(
+                        "valuer version is unknown: {:#}",
+                        err
+                    )]))
+                    .await;
+                None
+            }
+        };
+
         let valuer_cfg = FileRef {
             root: FileRefRoot::Problem,
             path: "valuer-cfg".to_string(),
         };
 
+        let mut groups = Vec::new();
+        for test in &tests {
+            let info = match self.cfg.groups.get(&test.group) {
+                Some(info) => info,
+                None => continue,
+            };
+            if groups.iter().any(|g: &pom::GroupInfo| g.id == test.group) {
+                continue;
+            }
+            groups.push(pom::GroupInfo {
+                id: test.group.clone(),
+                name: info.name.clone(),
+                description: info.description.clone(),
+                points: info.points,
+            });
+        }
         let problem = pom::Problem {
             title: self.cfg.title.clone(),
             name: self.cfg.name.clone(),
@@ -468,10 +2370,305 @@ impl<'a> ProblemBuilder<'a> {
             valuer_exe,
             tests,
             valuer_cfg,
+            valuer_version,
+            attachments,
+            time_scale: self.time_scale,
+            groups,
+            checker_build_commands,
+            builtin_comparison,
+            lang_time_scale: self.cfg.lang_time_scale.clone(),
+            checker_limits: self.checker_limits(),
+            test_order,
+            checker_exit_codes: self.cfg.checker_exit_codes.clone(),
+            output_validator_exe,
+            statements,
         };
-        let manifest_path = format!("{}/manifest.json", self.out_dir.display());
+        BuiltCommands {
+            solutions,
+            testgens: testgen_launch_info,
+        }
+        .store(self.out_dir)
+        .await?;
+        self.write_manifest(&problem).await?;
+        self.run_post_build().await?;
+        self.advance_progress(FINISH_PROGRESS_WEIGHT).await;
+        Ok(())
+    }
+
+    /// Runs `post_build` hook, if it is specified
+    async fn run_post_build(&mut self) -> anyhow::Result<()> {
+        let argv = match &self.cfg.post_build {
+            Some(argv) => argv,
+            None => return Ok(()),
+        };
+        self.pw.send(CompileUpdate::RunPostBuild).await;
+        let mut cmd = Command::new(&argv[0]);
+        for arg in &argv[1..] {
+            cmd.arg(arg);
+        }
+        self.configure_command(&mut cmd);
+        cmd.run_quiet().await.context("post-build hook failed")?;
+        Ok(())
+    }
+
+    /// Writes package manifest and checksums
+    async fn write_manifest(&self, problem: &pom::Problem) -> anyhow::Result<()> {
+        let manifest_path = self.out_dir.join("manifest.json");
         let manifest_data =
-            serde_json::to_string(&problem).context("couldn't serialize manifest")?;
-        std::fs::write(manifest_path, manifest_data).context("couldn't emit manifest")
+            serde_json::to_string(problem).context("couldn't serialize manifest")?;
+        tokio::fs::write(&manifest_path, manifest_data)
+            .await
+            .with_context(|| format!("couldn't emit manifest {}", manifest_path.display()))?;
+        crate::checksums::write_checksums(self.out_dir).await
+    }
+
+    /// Loads manifest of previously built package, together with
+    /// commands of built programs and test specs
+    async fn load_previous_build(
+        &self,
+    ) -> anyhow::Result<(
+        pom::Problem,
+        BuiltCommands,
+        Vec<crate::manifest::ExpandedTestSpec>,
+    )> {
+        if self.cfg.dedup {
+            anyhow::bail!("tests can not be rebuilt separately when dedup is enabled");
+        }
+        if self.cfg.shuffle_tests.is_some() {
+            anyhow::bail!("tests can not be rebuilt separately when shuffle-tests is enabled");
+        }
+        if self.out_dir.join(INCOMPLETE_MARKER).exists() {
+            anyhow::bail!("previous build did not finish, full rebuild is required");
+        }
+        let built = BuiltCommands::load(self.out_dir).await?;
+        let manifest_path = self.out_dir.join("manifest.json");
+        let manifest = tokio::fs::read(&manifest_path)
+            .await
+            .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+        let problem: pom::Problem =
+            serde_json::from_slice(&manifest).context("manifest is invalid")?;
+        let test_specs = self.test_specs().await?;
+        if test_specs.len() != problem.tests.len() {
+            anyhow::bail!(
+                "test count changed since previous build ({} -> {}), full rebuild is required",
+                problem.tests.len(),
+                test_specs.len()
+            );
+        }
+        Ok((problem, built, test_specs))
+    }
+
+    /// Regenerates input and answer of single test of previously built
+    /// package, reusing built generators and solutions.
+    pub async fn build_single_test(&mut self, test_id: usize) -> anyhow::Result<()> {
+        let (mut problem, built, test_specs) = self.load_previous_build().await?;
+        let test_spec = test_id
+            .checked_sub(1)
+            .and_then(|i| test_specs.get(i))
+            .with_context(|| format!("test {} does not exist", test_id))?;
+        if let crate::manifest::ExpandedGenSpec::Generate { testgen, .. } = &test_spec.gen {
+            if !built.testgens.contains_key(testgen) {
+                anyhow::bail!("test generator {} was not built", testgen);
+            }
+        }
+        let answer_solutions = self.answer_solutions(&built.solutions).await?;
+        let alternate_solutions =
+            self.alternate_answer_solutions(&built.solutions, &answer_solutions)?;
+        let ctx = TestContext::new(&built.testgens, answer_solutions, alternate_solutions).await?;
+        let (test, fingerprint, pending_answer) =
+            self.build_test(&ctx, test_id, test_spec, None).await?;
+        if let Some(pending) = &pending_answer {
+            self.generate_pending_answer(&ctx, pending, &test, None)
+                .await?;
+        }
+        problem.tests[test_id - 1] = test;
+        if self.cfg.strip_answers {
+            self.strip_answers(&mut problem.tests[test_id - 1..test_id])
+                .await?;
+        }
+        let mut fingerprints = Fingerprints::load(self.out_dir).await;
+        fingerprints.tests.insert(test_id, fingerprint);
+        fingerprints.store(self.out_dir).await?;
+        self.write_manifest(&problem).await
+    }
+
+    /// Rebuilds solutions, used for answer generation, and updates their
+    /// commands in `built`. Other solutions are not rebuilt.
+    async fn rebuild_answer_solutions(&mut self, built: &mut BuiltCommands) -> anyhow::Result<()> {
+        let cfg = self.cfg;
+        let names: std::collections::BTreeSet<&str> = cfg
+            .primary_solution
+            .iter()
+            .chain(cfg.answer_solutions.iter())
+            .chain(cfg.alternate_answer_solutions.iter())
+            .map(String::as_str)
+            .collect();
+        for solution_path in self.glob("solutions/*").await? {
+            let is_answer_solution = solution_path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map_or(false, |name| names.contains(name));
+            if !is_answer_solution {
+                continue;
+            }
+            let (sol_id, cmd) = self.build_solution(solution_path).await?;
+            built.solutions.insert(sol_id, cmd);
+        }
+        built.store(self.out_dir).await
+    }
+
+    /// Regenerates correct answers of given tests (or of all tests, if
+    /// `test_ids` is empty) of previously built package. Answer solutions
+    /// are rebuilt, test inputs are reused as is. This is useful when
+    /// primary solution was fixed.
+    pub async fn regenerate_answers(&mut self, test_ids: &[usize]) -> anyhow::Result<()> {
+        let (mut problem, mut built, test_specs) = self.load_previous_build().await?;
+        let test_ids: Vec<usize> = if test_ids.is_empty() {
+            (1..=test_specs.len()).collect()
+        } else {
+            test_ids.to_vec()
+        };
+        for &tid in &test_ids {
+            let test = tid
+                .checked_sub(1)
+                .and_then(|i| problem.tests.get(i))
+                .with_context(|| format!("test {} does not exist", tid))?;
+            if !test.path.resolve(self.out_dir).is_file() {
+                anyhow::bail!("input of test {} is missing", tid);
+            }
+        }
+        self.rebuild_answer_solutions(&mut built).await?;
+        let answer_solutions = self.answer_solutions(&built.solutions).await?;
+        if answer_solutions.is_empty() {
+            anyhow::bail!("correct answers are not generated for this problem");
+        }
+        let alternate_solutions =
+            self.alternate_answer_solutions(&built.solutions, &answer_solutions)?;
+        let ctx = TestContext::new(&built.testgens, answer_solutions, alternate_solutions).await?;
+        let mut fingerprints = Fingerprints::load(self.out_dir).await;
+        for tid in test_ids {
+            let test_spec = &test_specs[tid - 1];
+            let test = &mut problem.tests[tid - 1];
+            let input_path = test.path.resolve(self.out_dir);
+            self.pw
+                .send(CompileUpdate::GenerateAnswer { test_id: tid })
+                .await;
+            let work_dir = self.test_work_dir(test_spec).await?;
+            self.generate_answers(&ctx, tid, &input_path, work_dir.as_deref(), &test.limits)
+                .await?;
+            test.correct = Some(self.answer_ref(tid));
+            test.alternate_correct = ctx
+                .alternate_solutions
+                .iter()
+                .map(|(name, _)| self.alternate_answer_ref(tid, name))
+                .collect();
+            if let Some(fingerprint) = fingerprints.tests.get_mut(&tid) {
+                fingerprint.answer = Some(ctx.answer_fingerprint(&fingerprint.input, test_spec));
+            }
+            if self.cfg.strip_answers {
+                self.strip_answers(std::slice::from_mut(test)).await?;
+            }
+        }
+        fingerprints.store(self.out_dir).await?;
+        self.write_manifest(&problem).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::LimitPatch;
+
+    #[test]
+    fn merge_limits_precedence() {
+        let problem = LimitsPatch {
+            time: LimitPatch::Set(2000),
+            memory: LimitPatch::Set(512),
+            process_count: LimitPatch::Set(4),
+            ..LimitsPatch::default()
+        };
+        let test_spec = LimitsPatch {
+            time: LimitPatch::Set(5000),
+            memory: LimitPatch::Unlimited,
+            ..LimitsPatch::default()
+        };
+        let limits = merge_limits(&[problem, test_spec]);
+        assert_eq!(limits.time, Some(5000));
+        assert_eq!(limits.memory, None);
+        assert_eq!(limits.process_count, Some(4));
+        let defaults = Limits::default();
+        assert_eq!(limits.work_dir_size, defaults.work_dir_size);
+        assert_eq!(limits.cpu_time, defaults.cpu_time);
+    }
+
+    #[test]
+    fn shuffle_preserves_groups() {
+        let groups = ["a", "a", "a", "b", "b", "c", "a", "b"];
+        let mut tests: Vec<pom::Test> = groups
+            .iter()
+            .enumerate()
+            .map(|(i, group)| pom::Test {
+                path: FileRef {
+                    root: FileRefRoot::Problem,
+                    path: format!("tests/{}-in.txt", i + 1),
+                },
+                correct: None,
+                alternate_correct: Vec::new(),
+                limits: Limits::default(),
+                group: group.to_string(),
+                provenance: None,
+                raw_time: None,
+            })
+            .collect();
+        let order = shuffle_within_groups(&mut tests, 42);
+        let mut sorted_order = order.clone();
+        sorted_order.sort_unstable();
+        assert_eq!(sorted_order, (1..=groups.len()).collect::<Vec<_>>());
+        for (i, test) in tests.iter().enumerate() {
+            assert_eq!(test.group, groups[i]);
+            assert_eq!(test.path.path, format!("tests/{}-in.txt", order[i]));
+        }
+        assert_ne!(order, sorted_order, "seed 42 must move some tests");
+    }
+
+    #[test]
+    fn normalize_line_endings_strips_bom_and_cr() {
+        assert_eq!(
+            normalize_line_endings(b"\xEF\xBB\xBF1 2\r\n3\r\n".to_vec()),
+            b"1 2\n3\n"
+        );
+        // lone CR and BOM in the middle of data are kept
+        assert_eq!(
+            normalize_line_endings(b"a\rb\xEF\xBB\xBF\n".to_vec()),
+            b"a\rb\xEF\xBB\xBF\n"
+        );
+        assert_eq!(normalize_line_endings(b"\r".to_vec()), b"\r");
+        assert_eq!(normalize_line_endings(Vec::new()), b"");
+    }
+
+    #[test]
+    fn non_contiguous_groups_reported_once() {
+        let groups = ["a", "a", "b", "a", "c", "b", "a"];
+        assert_eq!(
+            non_contiguous_groups(groups.iter().copied()),
+            vec!["a", "b"]
+        );
+        let contiguous = ["a", "a", "b", "c", "c"];
+        assert!(non_contiguous_groups(contiguous.iter().copied()).is_empty());
+        assert!(non_contiguous_groups(std::iter::empty()).is_empty());
+    }
+
+    #[test]
+    fn module_output_name_escapes_path() {
+        assert_eq!(
+            module_output_name(Path::new("foo/main.cpp")).unwrap(),
+            "module-foo-main.cpp"
+        );
+        assert_eq!(
+            module_output_name(Path::new("my lib/a+b_c-d.rs")).unwrap(),
+            "module-my_lib-a_b_c-d.rs"
+        );
+        assert!(module_output_name(Path::new("../main.cpp")).is_err());
+        assert!(module_output_name(Path::new("/main.cpp")).is_err());
     }
 }
@@ -0,0 +1,180 @@
+//! Utilities for running built checker during build.
+//!
+//! Checker gets file descriptors of test input, correct answer and
+//! solution answer in `JJS_TEST`, `JJS_CORR` and `JJS_SOL` environment
+//! variables, and writes outcome (`outcome=Ok`) to `JJS_CHECKER_OUT`.
+use crate::command::Command;
+use anyhow::Context as _;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+/// Outcome, reported by checker for accepted answer
+pub(crate) const OUTCOME_OK: &str = "Ok";
+
+/// How checker is run
+pub(crate) struct CheckerRun<'a> {
+    pub(crate) cmd: &'a Command,
+    pub(crate) limits: &'a pom::Limits,
+    /// Meaning of checker exit codes
+    pub(crate) exit_codes: &'a BTreeMap<i32, pom::CheckerVerdict>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CheckerOutput {
+    /// Outcome name, e.g. `Ok` or `WrongAnswer`
+    pub(crate) outcome: String,
+    /// Comment written by checker
+    pub(crate) comment: String,
+}
+
+/// Runs checker on given files. `work_dir` is used for checker output files.
+///
+/// If exit code means that answer is rejected, outcome is determined by
+/// exit code. Otherwise it is read from checker output, defaulting to `Ok`
+/// (so checkers, which only use exit codes, are supported).
+pub(crate) async fn run_checker(
+    checker: &CheckerRun<'_>,
+    test: &Path,
+    correct: &Path,
+    solution: &Path,
+    work_dir: &Path,
+) -> anyhow::Result<CheckerOutput> {
+    let out_path = work_dir.join("checker-out.txt");
+    let comment_path = work_dir.join("checker-comment.txt");
+    let open = |path: &Path| {
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))
+    };
+    let create = |path: &Path| {
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))
+    };
+    let files = vec![
+        ("JJS_TEST", open(test)?),
+        ("JJS_CORR", open(correct)?),
+        ("JJS_SOL", open(solution)?),
+        ("JJS_CHECKER_OUT", create(&out_path)?),
+        ("JJS_CHECKER_COMMENT", create(&comment_path)?),
+    ];
+    let limits = checker.limits;
+    let mut cmd = checker.cmd.clone();
+    for (var, file) in &files {
+        cmd.env(var, file.as_raw_fd().to_string());
+    }
+    let mut cmd = cmd.to_tokio_command();
+    crate::command::set_rlimits(&mut cmd, limits);
+    let fds: Vec<_> = files.iter().map(|(_, file)| file.as_raw_fd()).collect();
+    unsafe {
+        cmd.pre_exec(move || {
+            // files are opened with O_CLOEXEC, but checker must inherit them
+            for &fd in &fds {
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let output = match limits.time {
+        Some(ms) => match tokio::time::timeout(Duration::from_millis(ms), cmd.output()).await {
+            Ok(output) => output,
+            Err(_) => anyhow::bail!("checker exceeded time limit of {} ms", ms),
+        },
+        None => cmd.output().await,
+    }
+    .context("failed to launch checker")?;
+    drop(files);
+    let verdict = output
+        .status
+        .code()
+        .and_then(|code| checker.exit_codes.get(&code).copied());
+    let verdict = match verdict {
+        Some(pom::CheckerVerdict::CheckerLogicError) | None => anyhow::bail!(
+            "checker failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Some(verdict) => verdict,
+    };
+    let outcome = if verdict == pom::CheckerVerdict::Ok {
+        let out = tokio::fs::read_to_string(&out_path)
+            .await
+            .context("failed to read checker output")?;
+        out.lines()
+            .find_map(|line| line.trim().strip_prefix("outcome="))
+            .unwrap_or(OUTCOME_OK)
+            .to_string()
+    } else {
+        verdict.as_str().to_string()
+    };
+    let comment = tokio::fs::read_to_string(&comment_path)
+        .await
+        .unwrap_or_default();
+    Ok(CheckerOutput { outcome, comment })
+}
+
+/// Runs checker with each of `corrects` as correct answer. Outcome is `Ok`
+/// if solution output is accepted against any of them, otherwise last
+/// rejection is returned.
+pub(crate) async fn run_checker_any(
+    checker: &CheckerRun<'_>,
+    test: &Path,
+    corrects: &[PathBuf],
+    solution: &Path,
+    work_dir: &Path,
+) -> anyhow::Result<CheckerOutput> {
+    let mut rejection = None;
+    for correct in corrects {
+        let res = run_checker(checker, test, correct, solution, work_dir).await?;
+        if res.outcome == OUTCOME_OK {
+            return Ok(res);
+        }
+        rejection = Some(res);
+    }
+    rejection.context("test has no correct answers")
+}
+
+/// Returns slightly modified answer, which checker must not accept
+pub(crate) fn perturb_answer(answer: &[u8]) -> Vec<u8> {
+    let mut out = answer.to_vec();
+    match out.iter().rposition(|b| !b.is_ascii_whitespace()) {
+        Some(pos) => {
+            out[pos] = match out[pos] {
+                b'0'..=b'8' => out[pos] + 1,
+                b'9' => b'0',
+                b'a'..=b'y' | b'A'..=b'Y' => out[pos] + 1,
+                b'z' | b'Z' => b'a',
+                _ => b'x',
+            };
+        }
+        None => out.extend_from_slice(b"0\n"),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perturb_answer_changes_last_token() {
+        assert_eq!(perturb_answer(b"1 2 3\n"), b"1 2 4\n");
+        assert_eq!(perturb_answer(b"19\n\n"), b"10\n\n");
+        assert_eq!(perturb_answer(b"YES"), b"YET");
+        assert_eq!(perturb_answer(b"fizz\r\n"), b"fiza\r\n");
+        assert_eq!(perturb_answer(b"-1 -\n"), b"-1 x\n");
+        assert_eq!(perturb_answer(b""), b"0\n");
+        assert_eq!(perturb_answer(b" \n"), b" \n0\n");
+        for answer in [&b"x"[..], b"Z", b"9", b"?", b"\t"].iter() {
+            assert_ne!(perturb_answer(answer), *answer);
+        }
+    }
+}
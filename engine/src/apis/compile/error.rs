@@ -0,0 +1,44 @@
+use thiserror::Error;
+
+type Source = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Error, returned when problem build fails.
+/// `CompileUpdate` operation reports it as `anyhow::Error`, so use
+/// `downcast_ref::<BuildError>()` to inspect failure kind.
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("failed to build checker")]
+    CheckerBuildFailed(#[source] Source),
+    #[error("test {test_id} generation (generator {name}) failed after {attempts} attempts")]
+    GeneratorFailed {
+        /// Test generator name
+        name: String,
+        test_id: usize,
+        attempts: u32,
+        #[source]
+        source: Source,
+    },
+    #[error("problem defines no tests")]
+    NoTests,
+    #[error("primary-solution must be specified in order to generate tests correct answers")]
+    PrimarySolutionMissing,
+    #[error("Error while generating correct answer for test {test_id}")]
+    AnswerGenerationFailed {
+        test_id: usize,
+        #[source]
+        source: Source,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl BuildError {
+    /// Extracts `BuildError` from error, returned by build step.
+    /// Other errors are wrapped into `BuildError::Other`.
+    pub(crate) fn from_anyhow(err: anyhow::Error) -> BuildError {
+        match err.downcast::<BuildError>() {
+            Ok(err) => err,
+            Err(err) => BuildError::Other(err),
+        }
+    }
+}
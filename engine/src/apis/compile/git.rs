@@ -0,0 +1,89 @@
+//! Fetching problem sources from git repositories
+use super::builder::BuildDirGuard;
+use crate::command::Command;
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Git repository, containing problem sources
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GitSource {
+    /// Repository URL, as accepted by `git clone`
+    pub url: String,
+    /// Branch, tag or commit to check out. If not set, default branch
+    /// is used.
+    #[serde(default)]
+    pub git_ref: Option<String>,
+}
+
+/// Shallow clone of repository in temporary directory, which is removed
+/// when checkout is dropped
+pub(crate) struct Checkout {
+    guard: BuildDirGuard,
+}
+
+impl Checkout {
+    /// Returns repository root
+    pub(crate) fn path(&self) -> &Path {
+        &self.guard.0
+    }
+}
+
+fn git(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(dir);
+    // prompt would hang non-interactive builds
+    cmd.env("GIT_TERMINAL_PROMPT", "0");
+    cmd
+}
+
+/// Clones `source` into new temporary directory. Only requested revision
+/// is fetched.
+pub(crate) async fn clone(source: &GitSource) -> anyhow::Result<Checkout> {
+    let mut suffix = [0; 8];
+    super::builder::get_entropy_hex(&mut suffix);
+    let dir: PathBuf =
+        std::env::temp_dir().join(format!("pps-git-{}", String::from_utf8_lossy(&suffix)));
+    tokio::fs::create_dir(&dir)
+        .await
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+    let checkout = Checkout {
+        guard: BuildDirGuard(dir),
+    };
+    let dir = checkout.path();
+    match &source.git_ref {
+        // `git clone --branch` does not accept commit hashes, so
+        // revision is fetched explicitly
+        Some(git_ref) => {
+            git(dir).arg("init").arg("--quiet").run_quiet().await?;
+            git(dir)
+                .arg("fetch")
+                .arg("--quiet")
+                .arg("--depth=1")
+                .arg(&source.url)
+                .arg(git_ref)
+                .run_quiet()
+                .await
+                .with_context(|| format!("failed to fetch {} from {}", git_ref, source.url))?;
+            git(dir)
+                .arg("checkout")
+                .arg("--quiet")
+                .arg("FETCH_HEAD")
+                .run_quiet()
+                .await
+                .context("failed to check out fetched revision")?;
+        }
+        None => {
+            git(dir)
+                .arg("clone")
+                .arg("--quiet")
+                .arg("--depth=1")
+                .arg(&source.url)
+                .arg(".")
+                .run_quiet()
+                .await
+                .with_context(|| format!("failed to clone {}", source.url))?;
+        }
+    }
+    Ok(checkout)
+}
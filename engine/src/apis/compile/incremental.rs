@@ -0,0 +1,299 @@
+//! Incremental builds support.
+//!
+//! After tests are built, their fingerprints are saved into output
+//! directory. Next incremental build reuses test input (and answer),
+//! if its fingerprint did not change.
+//!
+//! Launch commands of built solutions and test generators are saved too,
+//! so that separate tests can be regenerated without full rebuild.
+//!
+//! Test generators are cached separately: generator is not rebuilt if
+//! its sources and build backend did not change.
+use crate::command::Command;
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const FINGERPRINTS_FILE_NAME: &str = "fingerprints.json";
+const BUILT_COMMANDS_FILE_NAME: &str = "build-commands.json";
+const TESTGEN_CACHE_FILE_NAME: &str = "testgen-cache.json";
+
+/// Launch commands of programs, built during previous build
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct BuiltCommands {
+    pub(crate) solutions: BTreeMap<String, Command>,
+    pub(crate) testgens: BTreeMap<String, Command>,
+}
+
+impl BuiltCommands {
+    /// Loads commands saved by previous build.
+    /// Unlike fingerprints, they are required, so errors are reported.
+    pub(crate) async fn load(out_dir: &Path) -> anyhow::Result<BuiltCommands> {
+        let path = out_dir.join(BUILT_COMMANDS_FILE_NAME);
+        let data = tokio::fs::read(&path).await.with_context(|| {
+            format!(
+                "failed to read {} (was problem built before?)",
+                path.display()
+            )
+        })?;
+        serde_json::from_slice(&data).with_context(|| format!("{} is invalid", path.display()))
+    }
+
+    pub(crate) async fn store(&self, out_dir: &Path) -> anyhow::Result<()> {
+        let path = out_dir.join(BUILT_COMMANDS_FILE_NAME);
+        let data = serde_json::to_vec_pretty(self).expect("serialization never fails");
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Test generators, built during previous builds
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct TestgenCache {
+    pub(crate) testgens: BTreeMap<String, TestgenCacheEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct TestgenCacheEntry {
+    /// Fingerprint of generator sources and build backend
+    pub(crate) source: String,
+    /// Launch command of the built generator
+    pub(crate) command: Command,
+}
+
+impl TestgenCache {
+    /// Loads cache saved by previous build.
+    /// Returns empty cache if it is missing or corrupted.
+    pub(crate) async fn load(out_dir: &Path) -> TestgenCache {
+        let data = match tokio::fs::read(out_dir.join(TESTGEN_CACHE_FILE_NAME)).await {
+            Ok(data) => data,
+            Err(_) => return TestgenCache::default(),
+        };
+        match serde_json::from_slice(&data) {
+            Ok(cache) => cache,
+            Err(err) => {
+                tracing::warn!(error = %err, "ignoring invalid testgen cache file");
+                TestgenCache::default()
+            }
+        }
+    }
+
+    pub(crate) async fn store(&self, out_dir: &Path) -> anyhow::Result<()> {
+        let path = out_dir.join(TESTGEN_CACHE_FILE_NAME);
+        let data = serde_json::to_vec_pretty(self).expect("serialization never fails");
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Returns launch command of the cached generator, if its sources
+    /// fingerprint is `source` and the built binary still exists
+    pub(crate) fn lookup(&self, name: &str, source: &str) -> Option<&Command> {
+        let entry = self.testgens.get(name)?;
+        if entry.source != source || !Path::new(entry.command.get_exe()).exists() {
+            return None;
+        }
+        Some(&entry.command)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct Fingerprints {
+    pub(crate) tests: BTreeMap<usize, TestFingerprint>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct TestFingerprint {
+    /// Fingerprint of test input
+    pub(crate) input: String,
+    /// Fingerprint of correct answer, if it was generated
+    pub(crate) answer: Option<String>,
+    /// Provenance of generated test
+    pub(crate) provenance: Option<pom::TestProvenance>,
+}
+
+impl Fingerprints {
+    /// Loads fingerprints saved by previous build.
+    /// Returns empty set if they are missing or corrupted.
+    pub(crate) async fn load(out_dir: &Path) -> Fingerprints {
+        let data = match tokio::fs::read(out_dir.join(FINGERPRINTS_FILE_NAME)).await {
+            Ok(data) => data,
+            Err(_) => return Fingerprints::default(),
+        };
+        match serde_json::from_slice(&data) {
+            Ok(fingerprints) => fingerprints,
+            Err(err) => {
+                tracing::warn!(error = %err, "ignoring invalid fingerprints file");
+                Fingerprints::default()
+            }
+        }
+    }
+
+    pub(crate) async fn store(&self, out_dir: &Path) -> anyhow::Result<()> {
+        let path = out_dir.join(FINGERPRINTS_FILE_NAME);
+        let data = serde_json::to_vec_pretty(self).expect("serialization never fails");
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Value, which can be added to fingerprint.
+///
+/// `std::hash::Hash` is not used, because its encoding is not guaranteed
+/// to be stable across Rust versions. Instead, values are written as
+/// explicit bytes: variable-length values are prefixed with their
+/// length, and all integers are little-endian `u64`. This way fingerprints,
+/// saved by previous build, stay valid after toolchain upgrade.
+pub(crate) trait FingerprintInput {
+    fn feed(&self, hasher: &mut Sha256);
+}
+
+fn feed_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.input(&(bytes.len() as u64).to_le_bytes());
+    hasher.input(bytes);
+}
+
+impl<T: FingerprintInput + ?Sized> FingerprintInput for &T {
+    fn feed(&self, hasher: &mut Sha256) {
+        (**self).feed(hasher)
+    }
+}
+
+impl FingerprintInput for str {
+    fn feed(&self, hasher: &mut Sha256) {
+        feed_bytes(hasher, self.as_bytes());
+    }
+}
+
+impl FingerprintInput for String {
+    fn feed(&self, hasher: &mut Sha256) {
+        self.as_str().feed(hasher);
+    }
+}
+
+impl FingerprintInput for Path {
+    fn feed(&self, hasher: &mut Sha256) {
+        use std::os::unix::ffi::OsStrExt;
+        feed_bytes(hasher, self.as_os_str().as_bytes());
+    }
+}
+
+impl FingerprintInput for bool {
+    fn feed(&self, hasher: &mut Sha256) {
+        hasher.input(&[*self as u8]);
+    }
+}
+
+impl<T: FingerprintInput> FingerprintInput for Option<T> {
+    fn feed(&self, hasher: &mut Sha256) {
+        match self {
+            None => hasher.input(&[0u8]),
+            Some(value) => {
+                hasher.input(&[1u8]);
+                value.feed(hasher);
+            }
+        }
+    }
+}
+
+impl<T: FingerprintInput> FingerprintInput for [T] {
+    fn feed(&self, hasher: &mut Sha256) {
+        hasher.input(&(self.len() as u64).to_le_bytes());
+        for item in self {
+            item.feed(hasher);
+        }
+    }
+}
+
+impl<T: FingerprintInput> FingerprintInput for Vec<T> {
+    fn feed(&self, hasher: &mut Sha256) {
+        self.as_slice().feed(hasher);
+    }
+}
+
+impl FingerprintInput for crate::manifest::TestgenStdin {
+    fn feed(&self, hasher: &mut Sha256) {
+        match self {
+            crate::manifest::TestgenStdin::Data(data) => {
+                hasher.input(&[0u8]);
+                data.feed(hasher);
+            }
+            crate::manifest::TestgenStdin::File(path) => {
+                hasher.input(&[1u8]);
+                path.feed(hasher);
+            }
+        }
+    }
+}
+
+/// Computes SHA-256 fingerprint of several values
+pub(crate) struct Fingerprinter(Sha256);
+
+impl Fingerprinter {
+    pub(crate) fn new() -> Fingerprinter {
+        Fingerprinter(Sha256::new())
+    }
+
+    pub(crate) fn add(&mut self, value: impl FingerprintInput) -> &mut Self {
+        value.feed(&mut self.0);
+        self
+    }
+
+    /// Adds file contents to the fingerprint
+    pub(crate) async fn add_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        feed_bytes(&mut self.0, &data);
+        Ok(())
+    }
+
+    /// Adds contents of file or of all files in directory, including
+    /// their relative paths
+    pub(crate) async fn add_tree(&mut self, root: &Path) -> anyhow::Result<()> {
+        let mut stack = vec![root.to_path_buf()];
+        while let Some(path) = stack.pop() {
+            self.add(path.strip_prefix(root).unwrap_or(&path));
+            if !path.is_dir() {
+                self.add_file(&path).await?;
+                continue;
+            }
+            let mut entries = tokio::fs::read_dir(&path)
+                .await
+                .with_context(|| format!("failed to read dir {}", path.display()))?;
+            let mut children = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                children.push(entry.path());
+            }
+            // reverse order, so that files are fingerprinted sorted
+            children.sort_unstable_by(|a, b| b.cmp(a));
+            stack.extend(children);
+        }
+        Ok(())
+    }
+
+    /// Adds command line, as well as contents of all files mentioned in it
+    /// (e.g. executable or script).
+    pub(crate) async fn add_command(&mut self, cmd: &Command) -> anyhow::Result<()> {
+        self.add(cmd.to_string_pretty());
+        let paths: Vec<PathBuf> = std::iter::once(cmd.get_exe())
+            .chain(cmd.get_argv().iter().map(|arg| arg.as_os_str()))
+            .map(PathBuf::from)
+            .filter(|path| path.is_file())
+            .collect();
+        for path in paths {
+            self.add_file(&path).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(&self) -> String {
+        hex::encode(self.0.clone().result())
+    }
+}
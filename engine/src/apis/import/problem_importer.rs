@@ -268,7 +268,7 @@ impl<'a> Importer<'a> {
                 map: cnt.to_string(),
                 testgen: None,
                 files: None,
-                limits: self.limits,
+                limits: self.limits.into(),
                 group: format!(
                     "g{}",
                     test_node
@@ -276,6 +276,9 @@ impl<'a> Importer<'a> {
                         .unwrap_or("default")
                         .to_string()
                 ),
+                retries: None,
+                work_dir: None,
+                args_file: None,
             };
             let is_generated = test_node.attribute("method").unwrap() == "generated";
             if is_generated {
@@ -49,6 +49,10 @@ impl<'a> Visitor<'a> {
             run_to_first_failure: false,
             score: 0,
             deps: vec![],
+            scoring: svaluer::cfg::GroupScoring::AllOrNothing,
+            open: true,
+            max_score: None,
+            stop_on_fail: false,
         };
         for group_option in iter {
             self.visit_group_option(group_option, &mut group_cfg);
@@ -131,7 +135,14 @@ pub(crate) async fn import(
 ) -> Result<(svaluer::Config, Vec<String>), ImportValuerCfgError> {
     let input = tokio::fs::read_to_string(path).await?;
     let mut ast = P::parse(Rule::config, &input)?;
-    let mut config = svaluer::Config { groups: Vec::new() };
+    let mut config = svaluer::Config {
+        groups: Vec::new(),
+        live_score: svaluer::cfg::LiveScoreMode::Guaranteed,
+        score_format: svaluer::cfg::ScoreFormat::default(),
+        trace: false,
+        total_score: None,
+        session_timeout: None,
+    };
     let mut visitor = Visitor {
         config: &mut config,
         tests_info: std::collections::HashMap::new(),
@@ -0,0 +1,139 @@
+//! Package integrity checking.
+//!
+//! Compiled package contains `checksums.json`, which maps path of every
+//! package file (relative to package root) to its SHA-256 hash.
+use anyhow::Context as _;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const CHECKSUMS_FILE_NAME: &str = "checksums.json";
+
+/// Files of package which are covered by checksums.
+/// Directories are processed recursively.
+const CHECKED_ENTRIES: &[&str] = &["assets", "manifest.json"];
+
+/// Result of package verification
+#[derive(Debug, Default)]
+pub struct PackageVerification {
+    /// Files that are listed in checksums, but do not exist
+    pub missing: Vec<String>,
+    /// Files which content does not match checksum
+    pub corrupted: Vec<String>,
+}
+
+impl PackageVerification {
+    /// Returns true if no problems were found
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+pub(crate) async fn file_checksum(path: &Path) -> anyhow::Result<String> {
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(hex::encode(Sha256::digest(&data)))
+}
+
+/// Returns all files under `CHECKED_ENTRIES`, relative to `package_dir`
+async fn list_files(package_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut queue: Vec<PathBuf> = CHECKED_ENTRIES.iter().map(PathBuf::from).collect();
+    while let Some(item) = queue.pop() {
+        let path = package_dir.join(&item);
+        let meta = match tokio::fs::metadata(&path).await {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to stat {}", path.display()))
+            }
+        };
+        if meta.is_dir() {
+            let mut entries = tokio::fs::read_dir(&path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                queue.push(item.join(entry.file_name()));
+            }
+        } else {
+            files.push(item);
+        }
+    }
+    Ok(files)
+}
+
+/// Returns all files of package (including checksums), relative to
+/// `package_dir`, sorted
+pub(crate) async fn package_files(package_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = list_files(package_dir).await?;
+    if package_dir.join(CHECKSUMS_FILE_NAME).is_file() {
+        files.push(PathBuf::from(CHECKSUMS_FILE_NAME));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Computes checksums of all package files and writes them into package
+pub(crate) async fn write_checksums(package_dir: &Path) -> anyhow::Result<()> {
+    let mut checksums = BTreeMap::new();
+    for file in list_files(package_dir).await? {
+        let checksum = file_checksum(&package_dir.join(&file)).await?;
+        checksums.insert(file.to_string_lossy().into_owned(), checksum);
+    }
+    let data = serde_json::to_vec_pretty(&checksums).expect("serialization never fails");
+    tokio::fs::write(package_dir.join(CHECKSUMS_FILE_NAME), data)
+        .await
+        .context("failed to write checksums")
+}
+
+/// Recomputes checksums of compiled package files and compares them
+/// with ones recorded during build.
+pub async fn verify_package(package_dir: &Path) -> anyhow::Result<PackageVerification> {
+    let checksums_path = package_dir.join(CHECKSUMS_FILE_NAME);
+    let checksums = tokio::fs::read(&checksums_path)
+        .await
+        .with_context(|| format!("failed to read {}", checksums_path.display()))?;
+    let checksums: BTreeMap<String, String> =
+        serde_json::from_slice(&checksums).context("checksums file is invalid")?;
+    let mut report = PackageVerification::default();
+    for (file, expected) in checksums {
+        let path = package_dir.join(&file);
+        if !path.is_file() {
+            report.missing.push(file);
+            continue;
+        }
+        if file_checksum(&path).await? != expected {
+            report.corrupted.push(file);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verify_package_reports_missing_and_corrupted() {
+        let dir = std::env::temp_dir().join(format!("pps-checksums-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("assets/tests")).unwrap();
+        std::fs::write(dir.join("manifest.json"), b"{}").unwrap();
+        std::fs::write(dir.join("assets/tests/1-in.txt"), b"1 2\n").unwrap();
+        std::fs::write(dir.join("assets/tests/2-in.txt"), b"3 4\n").unwrap();
+        write_checksums(&dir).await.unwrap();
+        let clean = verify_package(&dir).await.unwrap();
+
+        std::fs::remove_file(dir.join("assets/tests/1-in.txt")).unwrap();
+        std::fs::write(dir.join("assets/tests/2-in.txt"), b"3 5\n").unwrap();
+        // files, not listed in checksums, are ignored
+        std::fs::write(dir.join("assets/extra.txt"), b"").unwrap();
+        let broken = verify_package(&dir).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(clean.is_ok());
+        assert_eq!(broken.missing, vec!["assets/tests/1-in.txt".to_string()]);
+        assert_eq!(broken.corrupted, vec!["assets/tests/2-in.txt".to_string()]);
+        assert!(!broken.is_ok());
+    }
+}
@@ -1,7 +1,21 @@
 // TODO: split all this stuff to separate library
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::ffi::{OsStr, OsString};
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    path::Path,
+    process::Stdio,
+};
+use tokio::io::AsyncWriteExt as _;
+
+/// Source of child process stdin
+pub enum ChildInput<'a> {
+    Null,
+    File(std::fs::File),
+    /// Data is written to child stdin through pipe
+    Data(&'a [u8]),
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Command {
@@ -9,6 +23,9 @@ pub struct Command {
     exe: OsString,
     cwd: Option<OsString>,
     env: Vec<(OsString, OsString)>,
+    /// If set, parent environment is not inherited
+    #[serde(default)]
+    clear_env: bool,
 }
 
 impl Command {
@@ -18,16 +35,24 @@ impl Command {
         if let Some(cwd) = &self.cwd {
             cmd.current_dir(cwd);
         }
+        if self.clear_env {
+            cmd.env_clear();
+        }
         cmd.envs(self.env.iter().cloned());
         cmd
     }
 
+    /// Returns command line without quoting. It is used in fingerprints,
+    /// so its format must not change; use `to_shell_string` for display.
     pub fn to_string_pretty(&self) -> String {
         use std::fmt::Write;
         let mut out = String::new();
         if let Some(cwd) = &self.cwd {
             write!(out, "cd {} && ", cwd.to_string_lossy()).unwrap();
         }
+        if self.clear_env {
+            out.push_str("env -i ");
+        }
         for (k, v) in &self.env {
             write!(out, "{}={} ", k.to_string_lossy(), v.to_string_lossy()).unwrap();
         }
@@ -38,33 +63,192 @@ impl Command {
         out
     }
 
+    /// Returns command line, which can be pasted into shell to run
+    /// this command
+    pub fn to_shell_string(&self) -> String {
+        shell_command_line(
+            self.cwd.as_deref().map(Path::new),
+            self.clear_env,
+            self.env.iter().map(|(k, v)| (k.as_os_str(), v.as_os_str())),
+            std::iter::once(self.exe.as_os_str()).chain(self.argv.iter().map(OsString::as_os_str)),
+        )
+    }
+
     pub async fn run_quiet(&mut self) -> anyhow::Result<std::process::Output> {
-        use std::os::unix::process::ExitStatusExt;
         let mut s = self.to_tokio_command();
         let out = s.output().await.context("couldn't spawn")?;
+        self.check_success(out)
+    }
+
+    /// Returns error, containing child output, if child process failed
+    pub fn check_success(&self, out: std::process::Output) -> anyhow::Result<std::process::Output> {
         let status = out.status;
         if status.success() {
             return Ok(out);
         }
 
-        let exit_code = if status.code().is_some() {
-            format!("normal: {}", status.code().unwrap())
-        } else {
-            format!("signaled: {}", status.signal().unwrap())
-        };
         anyhow::bail!(
-            "child process did not finished successfully (exit code {})\ncommand: `{}`\nchild stdout:\n{}\nchild stderr:\n{}",
-            exit_code,
+            "child process failed ({})\ncommand: `{}`\nchild stdout:\n{}\nchild stderr:\n{}",
+            describe_exit_status(status),
             self,
             String::from_utf8_lossy(&out.stdout),
             String::from_utf8_lossy(&out.stderr)
         )
     }
+
+    /// Runs command under given limits, capturing its stdout and stderr.
+    /// Unlike `run_quiet`, unsuccessful exit status is not an error.
+    pub async fn run_with_limits(
+        &self,
+        limits: &pom::Limits,
+    ) -> anyhow::Result<std::process::Output> {
+        self.run_with_limits_redirected(limits, ChildInput::Null, None)
+            .await
+    }
+
+    /// Same as `run_with_limits`, but allows to redirect stdin and stdout.
+    /// Child is killed when wall-clock time limit is exceeded; CPU time,
+    /// memory and process count are limited with rlimits. Note that
+    /// `RLIMIT_NPROC` counts all processes of the user.
+    pub async fn run_with_limits_redirected(
+        &self,
+        limits: &pom::Limits,
+        stdin: ChildInput<'_>,
+        stdout: Option<std::fs::File>,
+    ) -> anyhow::Result<std::process::Output> {
+        let mut cmd = self.to_tokio_command();
+        set_rlimits(&mut cmd, limits);
+        let data = match stdin {
+            ChildInput::Null => {
+                cmd.stdin(Stdio::null());
+                None
+            }
+            ChildInput::File(file) => {
+                cmd.stdin(Stdio::from(file));
+                None
+            }
+            ChildInput::Data(data) => {
+                cmd.stdin(Stdio::piped());
+                Some(data)
+            }
+        };
+        cmd.stdout(stdout.map_or_else(Stdio::piped, Stdio::from))
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("couldn't spawn `{}`", self))?;
+        let child_stdin = child.stdin.take();
+        let feed_stdin = async move {
+            if let (Some(data), Some(mut child_stdin)) = (data, child_stdin) {
+                match child_stdin.write_all(data).await {
+                    // child is not required to read whole input
+                    Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+                    res => res.context("failed to write child stdin")?,
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        let run = async {
+            let (feed_res, output) = futures::join!(feed_stdin, child.wait_with_output());
+            feed_res?;
+            output.context("failed to wait for child")
+        };
+        match limits.time {
+            Some(ms) => match tokio::time::timeout(std::time::Duration::from_millis(ms), run).await
+            {
+                Ok(output) => output,
+                Err(_) => anyhow::bail!("time limit of {} ms exceeded", ms),
+            },
+            None => run.await,
+        }
+    }
+}
+
+/// Limits CPU time, memory and process count of child with rlimits.
+/// Note that `RLIMIT_NPROC` counts all processes of the user.
+pub(crate) fn set_rlimits(cmd: &mut tokio::process::Command, limits: &pom::Limits) {
+    let rlimits = [
+        // RLIMIT_CPU has seconds granularity
+        (
+            libc::RLIMIT_CPU,
+            limits.cpu_time.map(|ms| (ms + 999) / 1000),
+        ),
+        (libc::RLIMIT_AS, limits.memory),
+        (libc::RLIMIT_NPROC, limits.process_count),
+    ];
+    unsafe {
+        cmd.pre_exec(move || {
+            for &(resource, value) in &rlimits {
+                if let Some(value) = value {
+                    // when soft CPU limit equals hard one, child is killed
+                    // with SIGKILL, so SIGXCPU (sent on soft limit) would
+                    // not be distinguishable from other kills
+                    let hard = if resource == libc::RLIMIT_CPU {
+                        value + 1
+                    } else {
+                        value
+                    };
+                    let rlim = libc::rlimit {
+                        rlim_cur: value as libc::rlim_t,
+                        rlim_max: hard as libc::rlim_t,
+                    };
+                    if libc::setrlimit(resource, &rlim) == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Quotes `arg` for POSIX shell, if it contains special characters
+fn shell_quote(arg: &OsStr) -> Cow<'_, str> {
+    let arg = arg.to_string_lossy();
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg;
+    }
+    Cow::Owned(format!("'{}'", arg.replace('\'', "'\\''")))
+}
+
+/// Formats command line with quoted arguments, so that it can be pasted
+/// into shell
+pub(crate) fn shell_command_line<'a>(
+    cwd: Option<&Path>,
+    clear_env: bool,
+    env: impl IntoIterator<Item = (&'a OsStr, &'a OsStr)>,
+    argv: impl IntoIterator<Item = &'a OsStr>,
+) -> String {
+    let mut parts = Vec::new();
+    if let Some(cwd) = cwd {
+        parts.push("cd".to_string());
+        parts.push(shell_quote(cwd.as_os_str()).into_owned());
+        parts.push("&&".to_string());
+    }
+    if clear_env {
+        parts.push("env -i".to_string());
+    }
+    for (k, v) in env {
+        parts.push(format!("{}={}", k.to_string_lossy(), shell_quote(v)));
+    }
+    parts.extend(argv.into_iter().map(|arg| shell_quote(arg).into_owned()));
+    parts.join(" ")
+}
+
+/// Returns human-readable description of process termination reason, e.g. `exit 1`
+fn describe_exit_status(status: std::process::ExitStatus) -> String {
+    use std::os::unix::process::ExitStatusExt;
+    match status.code() {
+        Some(code) => format!("exit {}", code),
+        None => format!("killed by signal {}", status.signal().unwrap_or(0)),
+    }
 }
 
 impl std::fmt::Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_str(&self.to_string_pretty())
+        f.write_str(&self.to_shell_string())
     }
 }
 
@@ -75,9 +259,18 @@ impl Command {
             argv: Vec::new(),
             cwd: None,
             env: Vec::new(),
+            clear_env: false,
         }
     }
 
+    /// Same as `new`, but child does not inherit parent environment
+    /// (see `env_clear`)
+    pub fn with_clean_env(s: impl AsRef<OsStr>) -> Command {
+        let mut cmd = Command::new(s);
+        cmd.env_clear();
+        cmd
+    }
+
     pub fn arg(&mut self, a: impl AsRef<OsStr>) -> &mut Self {
         self.argv.push(a.as_ref().to_os_string());
         self
@@ -90,8 +283,57 @@ impl Command {
         self
     }
 
+    /// Makes child process start with empty environment, so that
+    /// only variables added with `env` are visible
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.clear_env = true;
+        self
+    }
+
     pub fn current_dir(&mut self, cwd: impl AsRef<OsStr>) -> &mut Self {
         self.cwd.replace(cwd.as_ref().to_os_string());
         self
     }
+
+    pub fn get_current_dir(&self) -> Option<&OsStr> {
+        self.cwd.as_deref()
+    }
+
+    pub fn get_exe(&self) -> &OsStr {
+        &self.exe
+    }
+
+    pub fn get_argv(&self) -> &[OsString] {
+        &self.argv
+    }
+
+    /// Returns variables, explicitly added with `env`
+    pub fn get_env(&self) -> &[(OsString, OsString)] {
+        &self.env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cpu_time_limit_sends_sigxcpu() {
+        use std::os::unix::process::ExitStatusExt;
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("while :; do :; done");
+        let limits = pom::Limits {
+            // only protects test from hanging
+            time: Some(10_000),
+            cpu_time: Some(1000),
+            memory: None,
+            process_count: None,
+            work_dir_size: None,
+        };
+        let output = cmd
+            .run_with_limits_redirected(&limits, ChildInput::Null, None)
+            .await
+            .unwrap();
+        assert_eq!(output.status.signal(), Some(libc::SIGXCPU));
+    }
 }
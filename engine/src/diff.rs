@@ -0,0 +1,171 @@
+//! Comparison of two compiled packages, e.g. built before and after
+//! change of problem sources.
+//!
+//! Tests are matched by id. Files are compared by content hash, so
+//! packages may be located anywhere.
+use anyhow::Context as _;
+use std::{collections::BTreeMap, path::Path};
+
+/// Value, which differs between old and new package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+fn change<T: PartialEq + Clone>(old: &T, new: &T) -> Option<Change<T>> {
+    if old == new {
+        return None;
+    }
+    Some(Change {
+        old: old.clone(),
+        new: new.clone(),
+    })
+}
+
+/// Differences of the test, present in both packages
+#[derive(Debug)]
+pub struct TestDiff {
+    pub test_id: usize,
+    /// Content of test input differs
+    pub input_changed: bool,
+    /// Content or count of correct answers differs
+    pub answers_changed: bool,
+    pub limits: Option<Change<pom::Limits>>,
+    pub group: Option<Change<String>>,
+}
+
+/// Result of package comparison
+#[derive(Debug, Default)]
+pub struct PackageDiff {
+    /// Ids of tests, which are only present in new package
+    pub added_tests: Vec<usize>,
+    /// Ids of tests, which are only present in old package
+    pub removed_tests: Vec<usize>,
+    /// Tests, present in both packages, which differ
+    pub changed_tests: Vec<TestDiff>,
+    /// Checker binary, arguments, limits, exit codes or builtin
+    /// comparison differ
+    pub checker_changed: bool,
+    /// Valuer binary or config differs
+    pub valuer_changed: bool,
+    /// Group descriptions differ
+    pub groups_changed: bool,
+}
+
+impl PackageDiff {
+    /// Returns true if no differences were found
+    pub fn is_empty(&self) -> bool {
+        self.added_tests.is_empty()
+            && self.removed_tests.is_empty()
+            && self.changed_tests.is_empty()
+            && !self.checker_changed
+            && !self.valuer_changed
+            && !self.groups_changed
+    }
+}
+
+async fn load_manifest(package_dir: &Path) -> anyhow::Result<pom::Problem> {
+    let manifest_path = package_dir.join("manifest.json");
+    let manifest = tokio::fs::read(&manifest_path)
+        .await
+        .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+    serde_json::from_slice(&manifest)
+        .with_context(|| format!("manifest {} is invalid", manifest_path.display()))
+}
+
+/// Returns hash of referenced file, or `None` if it does not exist
+async fn file_hash(package_dir: &Path, file: &pom::FileRef) -> anyhow::Result<Option<String>> {
+    let path = file.resolve(package_dir);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    crate::checksums::file_checksum(&path).await.map(Some)
+}
+
+/// Everything, that determines how solution output is checked
+#[derive(PartialEq)]
+struct CheckerIdentity {
+    exe_hash: Option<String>,
+    cmd: Vec<String>,
+    limits: pom::Limits,
+    exit_codes: BTreeMap<i32, pom::CheckerVerdict>,
+    builtin_comparison: Option<pom::BuiltinComparison>,
+}
+
+impl CheckerIdentity {
+    async fn new(package_dir: &Path, problem: &pom::Problem) -> anyhow::Result<CheckerIdentity> {
+        Ok(CheckerIdentity {
+            exe_hash: file_hash(package_dir, &problem.checker_exe).await?,
+            cmd: problem.checker_cmd.clone(),
+            limits: problem.checker_limits,
+            exit_codes: problem.checker_exit_codes.clone(),
+            builtin_comparison: problem.builtin_comparison,
+        })
+    }
+}
+
+async fn valuer_hashes(
+    package_dir: &Path,
+    problem: &pom::Problem,
+) -> anyhow::Result<[Option<String>; 2]> {
+    Ok([
+        file_hash(package_dir, &problem.valuer_exe).await?,
+        file_hash(package_dir, &problem.valuer_cfg).await?,
+    ])
+}
+
+async fn answer_hashes(
+    package_dir: &Path,
+    test: &pom::Test,
+) -> anyhow::Result<Vec<Option<String>>> {
+    let mut hashes = Vec::new();
+    for answer in test.correct_answers() {
+        hashes.push(file_hash(package_dir, answer).await?);
+    }
+    Ok(hashes)
+}
+
+async fn diff_test(
+    old_dir: &Path,
+    old: &pom::Test,
+    new_dir: &Path,
+    new: &pom::Test,
+    test_id: usize,
+) -> anyhow::Result<TestDiff> {
+    Ok(TestDiff {
+        test_id,
+        input_changed: file_hash(old_dir, &old.path).await?
+            != file_hash(new_dir, &new.path).await?,
+        answers_changed: answer_hashes(old_dir, old).await? != answer_hashes(new_dir, new).await?,
+        limits: change(&old.limits, &new.limits),
+        group: change(&old.group, &new.group),
+    })
+}
+
+/// Compares compiled packages `a` (old) and `b` (new)
+pub async fn diff_packages(a: &Path, b: &Path) -> anyhow::Result<PackageDiff> {
+    let old = load_manifest(a).await?;
+    let new = load_manifest(b).await?;
+    let mut diff = PackageDiff::default();
+    let common = old.tests.len().min(new.tests.len());
+    diff.removed_tests = (common + 1..=old.tests.len()).collect();
+    diff.added_tests = (common + 1..=new.tests.len()).collect();
+    for (i, (old_test, new_test)) in old.tests.iter().zip(&new.tests).enumerate() {
+        let test_diff = diff_test(a, old_test, b, new_test, i + 1)
+            .await
+            .with_context(|| format!("failed to compare test {}", i + 1))?;
+        let changed = test_diff.input_changed
+            || test_diff.answers_changed
+            || test_diff.limits.is_some()
+            || test_diff.group.is_some();
+        if changed {
+            diff.changed_tests.push(test_diff);
+        }
+    }
+    diff.checker_changed =
+        CheckerIdentity::new(a, &old).await? != CheckerIdentity::new(b, &new).await?;
+    diff.valuer_changed = valuer_hashes(a, &old).await? != valuer_hashes(b, &new).await?;
+    diff.groups_changed = old.groups != new.groups;
+    Ok(diff)
+}
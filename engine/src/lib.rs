@@ -1,7 +1,15 @@
 pub mod apis;
+mod checksums;
 mod command;
+mod diff;
 mod manifest;
 pub mod operation;
+mod validate;
+mod zip;
+
+pub use checksums::{verify_package, PackageVerification};
+pub use diff::{diff_packages, Change, PackageDiff, TestDiff};
+pub use validate::{validate_manifest, ManifestError};
 
 use std::path::Path;
 
@@ -1,10 +1,97 @@
 use anyhow::{bail, Context};
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 /// Length of random seed
 /// We need this because mt19937_64 has fixed-length seed
 pub const RANDOM_SEED_LENGTH: usize = 16;
 
+/// Variables, inherited by build commands when `clean-env` is set and
+/// `env-allowlist` is not specified
+pub const DEFAULT_ENV_ALLOWLIST: &[&str] = &["PATH", "LANG", "LC_ALL", "TMPDIR"];
+
+/// Default time limit of answer generation, in milliseconds
+pub const DEFAULT_ANSWER_GEN_TIME_LIMIT: u64 = 60_000;
+
+/// Serialization format of problem manifest
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ManifestFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// Supported manifest file names, together with their formats
+const MANIFEST_FILES: &[(&str, ManifestFormat)] = &[
+    ("problem.toml", ManifestFormat::Toml),
+    ("problem.yaml", ManifestFormat::Yaml),
+    ("problem.yml", ManifestFormat::Yaml),
+    ("problem.json", ManifestFormat::Json),
+];
+
+impl ManifestFormat {
+    /// Parses manifest in this format
+    pub fn parse(self, data: &str) -> anyhow::Result<RawProblem> {
+        match self {
+            ManifestFormat::Toml => toml::from_str(data).map_err(Into::into),
+            ManifestFormat::Yaml => serde_yaml::from_str(data).map_err(Into::into),
+            ManifestFormat::Json => serde_json::from_str(data).map_err(Into::into),
+        }
+    }
+}
+
+/// Finds problem manifest in `problem_dir` and detects its format
+/// by file extension. It is an error if several manifests are present.
+pub fn find_manifest(problem_dir: &Path) -> anyhow::Result<(PathBuf, ManifestFormat)> {
+    let mut found = MANIFEST_FILES
+        .iter()
+        .map(|(name, format)| (problem_dir.join(name), *format))
+        .filter(|(path, _)| path.is_file());
+    let manifest = match found.next() {
+        Some(manifest) => manifest,
+        None => bail!(
+            "problem manifest not found in {} (expected problem.toml, problem.yaml or problem.json)",
+            problem_dir.display()
+        ),
+    };
+    if let Some((other, _)) = found.next() {
+        bail!(
+            "ambiguous problem manifest: both {} and {} exist",
+            manifest.0.display(),
+            other.display()
+        );
+    }
+    Ok(manifest)
+}
+
+/// Checks that `name` of solution, generator etc. can be used as single
+/// component of output path, so that it can not escape package directory
+pub(crate) fn check_name(what: &str, name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(&['/', '\\'][..]) {
+        bail!("{} name {:?} is not a valid file name", what, name);
+    }
+    Ok(())
+}
+
+/// Checks that relative `path` stays inside directory it is resolved
+/// against, i.e. it is not absolute and has no `..` components
+pub(crate) fn check_relative_path(what: &str, path: &str) -> anyhow::Result<()> {
+    use std::path::Component;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => bail!("{} path {} must not contain '..'", what, path),
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("{} path {} must be relative", what, path)
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct CustomCheck {
     #[serde(rename = "pass-correct")]
@@ -17,19 +104,361 @@ pub struct BuiltinCheck {
     pub name: String,
 }
 
+/// Checker, which is already built and is just copied into package
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PrebuiltCheck {
+    /// Path to checker executable, relative to problem dir
+    pub path: String,
+    #[serde(rename = "pass-correct")]
+    pub pass_correct: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct CheckOptions {
     pub args: Vec<String>,
 }
 
+/// Single limit in manifest.
+/// In `problem.toml` it is either number or string `"unlimited"`.
+/// Sizes and times can also be strings with units (e.g. `"256MiB"` or
+/// `"2s"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPatch {
+    /// Value from less specific level is used
+    #[default]
+    Inherit,
+    /// Value from less specific level is cleared
+    Unlimited,
+    /// Value from less specific level is replaced
+    Set(u64),
+}
+
+const UNLIMITED: &str = "unlimited";
+
+/// Size units, with their values in bytes
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("B", 1),
+    ("KB", 1000),
+    ("MB", 1000 * 1000),
+    ("GB", 1000 * 1000 * 1000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+];
+
+/// Time units, with their values in milliseconds
+const TIME_UNITS: &[(&str, u64)] = &[("ms", 1), ("s", 1000), ("min", 60 * 1000)];
+
+/// Parses `value` with unit suffix (e.g. `1.5s`) into count of base units
+fn parse_with_units(value: &str, units: &[(&str, u64)]) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or_else(|| value.len());
+    let (number, unit) = value.split_at(split);
+    let unit = unit.trim();
+    let factor = match units.iter().find(|(name, _)| *name == unit) {
+        Some((_, factor)) => *factor,
+        None => {
+            let names: Vec<&str> = units.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "unknown unit \"{}\" (expected one of: {})",
+                unit,
+                names.join(", ")
+            ));
+        }
+    };
+    let too_large = || format!("\"{}\" is too large", value);
+    if let Ok(number) = number.parse::<u64>() {
+        return number.checked_mul(factor).ok_or_else(too_large);
+    }
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a number", number))?;
+    let result = (number * factor as f64).round();
+    // `u64::MAX as f64` is 2^64, which itself does not fit
+    if result >= u64::MAX as f64 {
+        return Err(too_large());
+    }
+    Ok(result as u64)
+}
+
+impl LimitPatch {
+    fn is_inherit(&self) -> bool {
+        *self == LimitPatch::Inherit
+    }
+
+    /// Deserializes limit, which can also be specified as string with one
+    /// of `units` (if they are not empty). `field` is used in errors.
+    fn deserialize_with_units<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+        field: &str,
+        units: &[(&str, u64)],
+    ) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Value(u64),
+            Keyword(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Value(value) => Ok(LimitPatch::Set(value)),
+            Repr::Keyword(kw) if kw == UNLIMITED => Ok(LimitPatch::Unlimited),
+            Repr::Keyword(kw) if units.is_empty() => Err(serde::de::Error::custom(format!(
+                "{}: expected number or \"{}\", got \"{}\"",
+                field, UNLIMITED, kw
+            ))),
+            Repr::Keyword(kw) => match parse_with_units(&kw, units) {
+                Ok(value) => Ok(LimitPatch::Set(value)),
+                Err(err) => Err(serde::de::Error::custom(format!(
+                    "invalid {} limit \"{}\": {}",
+                    field, kw, err
+                ))),
+            },
+        }
+    }
+
+    fn deserialize_memory<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_units(d, "memory", SIZE_UNITS)
+    }
+
+    fn deserialize_time<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_units(d, "time", TIME_UNITS)
+    }
+
+    fn deserialize_cpu_time<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_units(d, "cpu_time", TIME_UNITS)
+    }
+
+    fn deserialize_process_count<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_units(d, "process_count", &[])
+    }
+
+    fn deserialize_work_dir_size<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_units(d, "work_dir_size", SIZE_UNITS)
+    }
+
+    /// Applies this patch to a `place`
+    pub fn apply(self, place: &mut Option<u64>) {
+        match self {
+            LimitPatch::Inherit => (),
+            LimitPatch::Unlimited => *place = None,
+            LimitPatch::Set(value) => *place = Some(value),
+        }
+    }
+}
+
+impl From<Option<u64>> for LimitPatch {
+    fn from(value: Option<u64>) -> LimitPatch {
+        match value {
+            Some(value) => LimitPatch::Set(value),
+            None => LimitPatch::Inherit,
+        }
+    }
+}
+
+impl Serialize for LimitPatch {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            LimitPatch::Inherit => serializer.serialize_none(),
+            LimitPatch::Unlimited => serializer.serialize_str(UNLIMITED),
+            LimitPatch::Set(value) => serializer.serialize_u64(*value),
+        }
+    }
+}
+
+/// Limits, as specified on some level of manifest.
+/// See `LimitPatch` for details. Sizes are in bytes, times are in
+/// milliseconds, unless units are specified.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct LimitsPatch {
+    #[serde(
+        default,
+        skip_serializing_if = "LimitPatch::is_inherit",
+        deserialize_with = "LimitPatch::deserialize_memory"
+    )]
+    pub memory: LimitPatch,
+    #[serde(
+        default,
+        skip_serializing_if = "LimitPatch::is_inherit",
+        deserialize_with = "LimitPatch::deserialize_time"
+    )]
+    pub time: LimitPatch,
+    #[serde(
+        default,
+        skip_serializing_if = "LimitPatch::is_inherit",
+        deserialize_with = "LimitPatch::deserialize_cpu_time"
+    )]
+    pub cpu_time: LimitPatch,
+    #[serde(
+        default,
+        skip_serializing_if = "LimitPatch::is_inherit",
+        deserialize_with = "LimitPatch::deserialize_process_count"
+    )]
+    pub process_count: LimitPatch,
+    #[serde(
+        default,
+        skip_serializing_if = "LimitPatch::is_inherit",
+        deserialize_with = "LimitPatch::deserialize_work_dir_size"
+    )]
+    pub work_dir_size: LimitPatch,
+}
+
+impl LimitsPatch {
+    /// Applies this patch to `limits`
+    pub fn apply(&self, limits: &mut pom::Limits) {
+        self.memory.apply(&mut limits.memory);
+        self.time.apply(&mut limits.time);
+        self.cpu_time.apply(&mut limits.cpu_time);
+        self.process_count.apply(&mut limits.process_count);
+        self.work_dir_size.apply(&mut limits.work_dir_size);
+    }
+}
+
+impl From<pom::Limits> for LimitsPatch {
+    fn from(limits: pom::Limits) -> LimitsPatch {
+        LimitsPatch {
+            memory: limits.memory.into(),
+            time: limits.time.into(),
+            cpu_time: limits.cpu_time.into(),
+            process_count: limits.process_count.into(),
+            work_dir_size: limits.work_dir_size.into(),
+        }
+    }
+}
+
+/// Tests, imported from directory tree: each subdirectory of `dir` is
+/// a group named after it, and each file in subdirectory is test input
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TestsTree {
+    /// Directory, relative to `tests` dir of the problem
+    #[serde(default)]
+    pub dir: String,
+    /// Limits of groups, by group name
+    #[serde(default)]
+    pub limits: BTreeMap<String, LimitsPatch>,
+}
+
+/// Naming scheme of test files in package
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestNaming {
+    /// `{id}-in.txt` and `{id}-out.txt`
+    Txt,
+    /// `{id}.in` and `{id}.ans`
+    Ans,
+}
+
+impl Default for TestNaming {
+    fn default() -> TestNaming {
+        TestNaming::Txt
+    }
+}
+
+impl TestNaming {
+    /// File name of test input
+    pub fn input_name(self, test_id: usize) -> String {
+        match self {
+            TestNaming::Txt => format!("{}-in.txt", test_id),
+            TestNaming::Ans => format!("{}.in", test_id),
+        }
+    }
+
+    /// File name of test correct answer
+    pub fn answer_name(self, test_id: usize) -> String {
+        match self {
+            TestNaming::Txt => format!("{}-out.txt", test_id),
+            TestNaming::Ans => format!("{}.ans", test_id),
+        }
+    }
+
+    /// File name of test alternate answer, generated by `solution`
+    pub fn alternate_answer_name(self, test_id: usize, solution: &str) -> String {
+        match self {
+            TestNaming::Txt => format!("{}-alt-{}.txt", test_id, solution),
+            TestNaming::Ans => format!("{}.alt-{}.ans", test_id, solution),
+        }
+    }
+}
+
+/// Human-facing description of test group
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GroupInfo {
+    /// Display name, e.g. `n ≤ 1000`
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Points, declared for this group
+    pub points: Option<u32>,
+}
+
+/// Verdict, which solution gets on single test
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Ok,
+    WrongAnswer,
+    PresentationError,
+    TimeLimitExceeded,
+    RuntimeError,
+}
+
+impl Verdict {
+    /// Converts checker outcome into verdict
+    pub fn from_outcome(outcome: &str) -> Option<Verdict> {
+        match outcome {
+            "Ok" => Some(Verdict::Ok),
+            "WrongAnswer" => Some(Verdict::WrongAnswer),
+            "PresentationError" => Some(Verdict::PresentationError),
+            _ => None,
+        }
+    }
+}
+
+/// Asserts that solution gets `verdict` on test `test`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExpectedVerdict {
+    pub solution: String,
+    pub test: usize,
+    pub verdict: Verdict,
+}
+
+/// Problem statement file in some language
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Statement {
+    /// Language code, e.g. `en`
+    pub lang: String,
+    /// Path to statement file (e.g. PDF), relative to problem dir
+    pub path: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct RawTestsSpec {
     pub map: String,
     pub testgen: Option<Vec<String>>,
     pub files: Option<String>,
     #[serde(default)]
-    pub limits: pom::Limits,
+    pub limits: LimitsPatch,
     pub group: String,
+    /// How many times failed generator should be rerun
+    pub retries: Option<u32>,
+    /// Working directory for commands, relative to problem dir
+    #[serde(rename = "work-dir")]
+    pub work_dir: Option<String>,
+    /// File with generator arguments, one test per line.
+    /// Requires `testgen` and single test in `map`.
+    /// Arguments in a line are separated by whitespace or commas, so
+    /// single argument can not contain them.
+    #[serde(rename = "args-file")]
+    pub args_file: Option<String>,
+    /// Data, written to generator stdin. Requires `testgen`.
+    pub stdin: Option<String>,
+    /// File (relative to problem dir), written to generator stdin.
+    /// Requires `testgen`.
+    #[serde(rename = "stdin-file")]
+    pub stdin_file: Option<String>,
+    /// Generator name and arguments of program, which decides whether
+    /// generated test is accepted. Requires `testgen`.
+    pub filter: Option<Vec<String>>,
 }
 
 impl RawTestsSpec {
@@ -112,10 +541,47 @@ impl RawTestsSpec {
                 }
             }
         }
+        if self.args_file.is_some() && self.testgen.is_none() {
+            bail!("'args-file' requires 'testgen' to be specified");
+        }
+        let stdin_data = match (&self.stdin, &self.stdin_file) {
+            (Some(_), Some(_)) => bail!("at most one of 'stdin' and 'stdin-file' can be specified"),
+            (Some(data), None) => Some(TestgenStdin::Data(data.clone())),
+            (None, Some(path)) => Some(TestgenStdin::File(path.clone())),
+            (None, None) => None,
+        };
+        if stdin_data.is_some() && self.testgen.is_none() {
+            bail!("'stdin' and 'stdin-file' require 'testgen' to be specified");
+        }
+        match &self.filter {
+            Some(filter) if filter.is_empty() => bail!("'filter' command is empty"),
+            Some(_) if self.testgen.is_none() => {
+                bail!("'filter' requires 'testgen' to be specified")
+            }
+            _ => {}
+        }
         if let Some(testgen_cmd) = &self.testgen {
-            let spec = TestGenSpec::Generate {
-                testgen: testgen_cmd[0].clone(),
-                args: testgen_cmd[1..].to_vec(),
+            let spec = match &self.args_file {
+                Some(args_file) => {
+                    if idxs.len() != 1 {
+                        bail!("when 'args-file' is used, 'map' must contain exactly one test");
+                    }
+                    TestGenSpec::GenerateFromFile {
+                        testgen: testgen_cmd[0].clone(),
+                        args: testgen_cmd[1..].to_vec(),
+                        args_file: args_file.clone(),
+                        retries: self.retries,
+                        stdin_data: stdin_data.clone(),
+                        filter: self.filter.clone(),
+                    }
+                }
+                None => TestGenSpec::Generate {
+                    testgen: testgen_cmd[0].clone(),
+                    args: testgen_cmd[1..].to_vec(),
+                    retries: self.retries,
+                    stdin_data: stdin_data.clone(),
+                    filter: self.filter.clone(),
+                },
             };
 
             for &id in &idxs {
@@ -131,6 +597,7 @@ impl RawTestsSpec {
                         gen: test_gen_spec,
                         limits: self.limits,
                         group: self.group.clone(),
+                        work_dir: self.work_dir.clone(),
                     },
                 )
             })
@@ -140,17 +607,110 @@ impl RawTestsSpec {
     }
 }
 
+/// Data, written to test generator stdin
+#[derive(Clone, Debug)]
+pub enum TestgenStdin {
+    /// Inline data
+    Data(String),
+    /// File path, relative to problem dir
+    File(String),
+}
+
 #[derive(Clone, Debug)]
 pub enum TestGenSpec {
-    Generate { testgen: String, args: Vec<String> },
-    File { path: String },
+    Generate {
+        testgen: String,
+        args: Vec<String>,
+        /// Overrides problem-wide `testgen_retries`
+        retries: Option<u32>,
+        stdin_data: Option<TestgenStdin>,
+        /// Generator name and arguments of filter. It gets generated test
+        /// on stdin, and exits with code 0 to accept it or 1 to reject.
+        /// Rejected tests are regenerated, like failed ones.
+        filter: Option<Vec<String>>,
+    },
+    File {
+        path: String,
+    },
+    /// Expands to one `Generate` test per line of `args_file`.
+    /// Line arguments are appended to `args`.
+    GenerateFromFile {
+        testgen: String,
+        args: Vec<String>,
+        args_file: String,
+        retries: Option<u32>,
+        stdin_data: Option<TestgenStdin>,
+        filter: Option<Vec<String>>,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TestSpec {
     pub gen: TestGenSpec,
-    pub limits: pom::Limits,
+    pub limits: LimitsPatch,
     pub group: String,
+    /// Overrides working directory of generator and solution commands
+    pub work_dir: Option<String>,
+}
+
+impl TestSpec {
+    /// Creates expanded spec with same limits, group and working
+    /// directory, but generated by `gen`
+    pub fn with_gen(&self, gen: ExpandedGenSpec) -> ExpandedTestSpec {
+        ExpandedTestSpec {
+            gen,
+            limits: self.limits,
+            group: self.group.clone(),
+            work_dir: self.work_dir.clone(),
+        }
+    }
+
+    /// Converts spec, that does not need expansion, into expanded one.
+    /// Returns `None` for `GenerateFromFile` specs.
+    pub fn expanded(&self) -> Option<ExpandedTestSpec> {
+        let gen = match &self.gen {
+            TestGenSpec::Generate {
+                testgen,
+                args,
+                retries,
+                stdin_data,
+                filter,
+            } => ExpandedGenSpec::Generate {
+                testgen: testgen.clone(),
+                args: args.clone(),
+                retries: *retries,
+                stdin_data: stdin_data.clone(),
+                filter: filter.clone(),
+            },
+            TestGenSpec::File { path } => ExpandedGenSpec::File { path: path.clone() },
+            TestGenSpec::GenerateFromFile { .. } => return None,
+        };
+        Some(self.with_gen(gen))
+    }
+}
+
+/// Same as `TestGenSpec`, but `GenerateFromFile` is already expanded
+#[derive(Clone, Debug)]
+pub enum ExpandedGenSpec {
+    Generate {
+        testgen: String,
+        args: Vec<String>,
+        retries: Option<u32>,
+        stdin_data: Option<TestgenStdin>,
+        filter: Option<Vec<String>>,
+    },
+    File {
+        path: String,
+    },
+}
+
+/// Spec of single test, as used by builder
+#[derive(Debug, Clone)]
+pub struct ExpandedTestSpec {
+    pub gen: ExpandedGenSpec,
+    pub limits: LimitsPatch,
+    pub group: String,
+    pub work_dir: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -176,6 +736,10 @@ pub struct RawProblem {
     #[serde(rename = "builtin-check")]
     pub builtin_check: Option<BuiltinCheck>,
 
+    #[serde(rename = "prebuilt-check")]
+    pub prebuilt_check: Option<PrebuiltCheck>,
+
+    #[serde(default)]
     pub tests: Vec<RawTestsSpec>,
 
     #[serde(rename = "check-options")]
@@ -185,7 +749,103 @@ pub struct RawProblem {
     pub valuer_cfg: Option<String>,
 
     #[serde(default)]
-    pub limits: pom::Limits,
+    pub limits: LimitsPatch,
+
+    #[serde(rename = "testgen-retries")]
+    pub testgen_retries: Option<u32>,
+
+    #[serde(rename = "valuer-exe-override")]
+    pub valuer_exe_override: Option<String>,
+
+    #[serde(rename = "builtin-checker-dir-override")]
+    pub builtin_checker_dir_override: Option<String>,
+
+    #[serde(rename = "normalize-line-endings", default)]
+    pub normalize_line_endings: bool,
+
+    #[serde(rename = "answer-solutions", default)]
+    pub answer_solutions: Vec<String>,
+
+    #[serde(rename = "checker-self-test", default)]
+    pub checker_self_test: bool,
+
+    #[serde(rename = "verify-answers", default)]
+    pub verify_answers: bool,
+
+    #[serde(rename = "strip-answers", default)]
+    pub strip_answers: bool,
+
+    #[serde(rename = "clean-env", default)]
+    pub clean_env: bool,
+
+    #[serde(rename = "env-allowlist")]
+    pub env_allowlist: Option<Vec<String>>,
+
+    #[serde(rename = "reorder-groups", default)]
+    pub reorder_groups: bool,
+
+    #[serde(default)]
+    pub attachments: Vec<String>,
+
+    #[serde(rename = "strict-build", default)]
+    pub strict_build: bool,
+
+    #[serde(rename = "strict-build-exempt", default)]
+    pub strict_build_exempt: Vec<String>,
+
+    #[serde(rename = "post-build")]
+    pub post_build: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub groups: BTreeMap<String, GroupInfo>,
+
+    #[serde(rename = "max-package-bytes")]
+    pub max_package_bytes: Option<u64>,
+
+    #[serde(rename = "time-scale")]
+    pub time_scale: Option<f64>,
+
+    #[serde(default)]
+    pub dedup: bool,
+
+    #[serde(rename = "allow-empty-tests", default)]
+    pub allow_empty_tests: bool,
+
+    #[serde(rename = "test-naming", default)]
+    pub test_naming: TestNaming,
+
+    #[serde(rename = "tests-tree")]
+    pub tests_tree: Option<TestsTree>,
+
+    #[serde(rename = "lang-time-scale", default)]
+    pub lang_time_scale: BTreeMap<String, f64>,
+
+    #[serde(rename = "checker-limits", default)]
+    pub checker_limits: LimitsPatch,
+
+    #[serde(rename = "shuffle-tests")]
+    pub shuffle_tests: Option<u64>,
+
+    #[serde(rename = "checker-exit-codes")]
+    pub checker_exit_codes: Option<BTreeMap<String, pom::CheckerVerdict>>,
+
+    #[serde(rename = "answer-gen-time-limit")]
+    pub answer_gen_time_limit: Option<u64>,
+
+    #[serde(rename = "testlib-generators", default)]
+    pub testlib_generators: Vec<String>,
+
+    #[serde(rename = "output-validator")]
+    pub output_validator: Option<String>,
+
+    #[serde(default)]
+    pub statements: Vec<Statement>,
+
+    #[serde(rename = "expected-verdicts", default)]
+    pub expected_verdicts: Vec<ExpectedVerdict>,
+
+    #[serde(rename = "alternate-answer-solutions", default)]
+    pub alternate_answer_solutions: Vec<String>,
 }
 
 impl RawProblem {
@@ -200,7 +860,7 @@ impl RawProblem {
         }
         tests.sort_by_key(|item| item.0);
         let test_ids: Vec<_> = tests.iter().map(|item| item.0).collect();
-        if test_ids.is_empty() {
+        if test_ids.is_empty() && self.tests_tree.is_none() && !self.allow_empty_tests {
             bail!("No tests specified");
         }
 
@@ -217,9 +877,115 @@ impl RawProblem {
         Ok(tests.into_iter().map(|item| item.1).collect())
     }
 
+    /// If groups are declared, checks that declared groups are exactly
+    /// groups used by tests
+    fn check_groups(&self, tests: &[TestSpec]) -> anyhow::Result<()> {
+        if self.groups.is_empty() {
+            return Ok(());
+        }
+        for (i, test) in tests.iter().enumerate() {
+            if !self.groups.contains_key(&test.group) {
+                bail!(
+                    "test {} belongs to group {}, which is not declared in [groups]",
+                    i + 1,
+                    test.group
+                );
+            }
+        }
+        for group in self.groups.keys() {
+            if !tests.iter().any(|test| &test.group == group) {
+                bail!("group {} is declared, but has no tests", group);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that names and paths, coming from manifest, can not refer
+    /// to files outside of problem and package directories
+    fn check_paths(&self, tests: &[TestSpec]) -> anyhow::Result<()> {
+        let answer_solutions = self
+            .primary_solution
+            .iter()
+            .chain(&self.answer_solutions)
+            .chain(&self.alternate_answer_solutions);
+        for name in answer_solutions {
+            check_name("solution", name)?;
+        }
+        for expected in &self.expected_verdicts {
+            check_name("solution", &expected.solution)?;
+        }
+        for test in tests {
+            check_name("group", &test.group)?;
+            if let Some(work_dir) = &test.work_dir {
+                check_relative_path("work dir", work_dir)?;
+            }
+            match &test.gen {
+                TestGenSpec::File { path } => check_relative_path("test", path)?,
+                TestGenSpec::Generate {
+                    testgen,
+                    stdin_data,
+                    filter,
+                    ..
+                } => {
+                    check_name("generator", testgen)?;
+                    if let Some(filter) = filter {
+                        check_name("filter", &filter[0])?;
+                    }
+                    if let Some(TestgenStdin::File(path)) = stdin_data {
+                        check_relative_path("stdin file", path)?;
+                    }
+                }
+                TestGenSpec::GenerateFromFile {
+                    testgen,
+                    args_file,
+                    stdin_data,
+                    filter,
+                    ..
+                } => {
+                    check_name("generator", testgen)?;
+                    if let Some(filter) = filter {
+                        check_name("filter", &filter[0])?;
+                    }
+                    check_relative_path("arguments file", args_file)?;
+                    if let Some(TestgenStdin::File(path)) = stdin_data {
+                        check_relative_path("stdin file", path)?;
+                    }
+                }
+            }
+        }
+        for name in &self.testlib_generators {
+            check_name("generator", name)?;
+        }
+        if let Some(bc) = &self.builtin_check {
+            check_name("builtin checker", &bc.name)?;
+        }
+        if let Some(pc) = &self.prebuilt_check {
+            check_relative_path("prebuilt checker", pc.path.trim_start_matches('/'))?;
+        }
+        if let Some(valuer_cfg) = &self.valuer_cfg {
+            check_relative_path("valuer config", valuer_cfg.trim_start_matches('/'))?;
+        }
+        if let Some(validator) = &self.output_validator {
+            check_relative_path("output validator", validator.trim_start_matches('/'))?;
+        }
+        if let Some(tree) = &self.tests_tree {
+            check_relative_path("tests tree", &tree.dir)?;
+        }
+        for pattern in &self.attachments {
+            check_relative_path("attachment", pattern)?;
+        }
+        for statement in &self.statements {
+            check_name("statement language", &statement.lang)?;
+            check_relative_path("statement", &statement.path)?;
+        }
+        Ok(())
+    }
+
     pub fn postprocess(mut self) -> anyhow::Result<(Problem, /* warnings */ Vec<String>)> {
         let mut warnings = Vec::new();
         let tests = self.process_tests()?;
+        self.check_groups(&tests)?;
+        self.check_paths(&tests)?;
 
         let random_seed = match self.random_seed.take() {
             Some(s) => {
@@ -238,6 +1004,26 @@ impl RawProblem {
             }
         };
 
+        for (lang, scale) in &self.lang_time_scale {
+            if !(scale.is_finite() && *scale > 0.0) {
+                bail!("lang-time-scale for {} must be positive", lang);
+            }
+        }
+
+        let checker_exit_codes = match &self.checker_exit_codes {
+            Some(codes) => {
+                let mut parsed = BTreeMap::new();
+                for (code, verdict) in codes {
+                    let code = code
+                        .parse()
+                        .with_context(|| format!("checker exit code {} is not a number", code))?;
+                    parsed.insert(code, *verdict);
+                }
+                parsed
+            }
+            None => pom::CheckerVerdict::testlib_exit_codes(),
+        };
+
         let out = Problem {
             title: self.title,
             primary_solution: self.primary_solution,
@@ -260,6 +1046,16 @@ impl RawProblem {
                     };
                     Check::Builtin(builtin_check)
                 }
+                "prebuilt" => {
+                    let prebuilt_check = match self.prebuilt_check {
+                        Some(pc) => pc,
+                        None => {
+                            bail!("check-type=prebuilt requires [prebuilt-check] section");
+                        }
+                    };
+                    Check::Prebuilt(prebuilt_check)
+                }
+                "tokens" => Check::Tokens,
                 other => {
                     bail!("unknown check type: {}", other);
                 }
@@ -273,6 +1069,54 @@ impl RawProblem {
             valuer: self.valuer,
             valuer_cfg: self.valuer_cfg,
             limits: self.limits,
+            testgen_retries: self.testgen_retries.unwrap_or(0),
+            valuer_exe_override: self.valuer_exe_override,
+            builtin_checker_dir_override: self.builtin_checker_dir_override,
+            normalize_line_endings: self.normalize_line_endings,
+            answer_solutions: self.answer_solutions,
+            checker_self_test: self.checker_self_test,
+            verify_answers: self.verify_answers,
+            strip_answers: self.strip_answers,
+            clean_env: self.clean_env,
+            env_allowlist: self.env_allowlist.unwrap_or_else(|| {
+                DEFAULT_ENV_ALLOWLIST
+                    .iter()
+                    .map(|var| var.to_string())
+                    .collect()
+            }),
+            reorder_groups: self.reorder_groups,
+            attachments: self.attachments,
+            strict_build: self.strict_build,
+            strict_build_exempt: self.strict_build_exempt,
+            post_build: match self.post_build {
+                Some(argv) if argv.is_empty() => bail!("post-build command is empty"),
+                other => other,
+            },
+            groups: self.groups,
+            max_package_bytes: self.max_package_bytes,
+            time_scale: match self.time_scale {
+                Some(scale) if !(scale.is_finite() && scale > 0.0) => {
+                    bail!("time-scale must be positive")
+                }
+                Some(scale) => scale,
+                None => 1.0,
+            },
+            dedup: self.dedup,
+            allow_empty_tests: self.allow_empty_tests,
+            test_naming: self.test_naming,
+            tests_tree: self.tests_tree,
+            lang_time_scale: self.lang_time_scale,
+            checker_limits: self.checker_limits,
+            shuffle_tests: self.shuffle_tests,
+            checker_exit_codes,
+            answer_gen_time_limit: self
+                .answer_gen_time_limit
+                .unwrap_or(DEFAULT_ANSWER_GEN_TIME_LIMIT),
+            testlib_generators: self.testlib_generators,
+            output_validator: self.output_validator,
+            statements: self.statements,
+            expected_verdicts: self.expected_verdicts,
+            alternate_answer_solutions: self.alternate_answer_solutions,
         };
 
         Ok((out, warnings))
@@ -283,6 +1127,9 @@ impl RawProblem {
 pub enum Check {
     Custom(CustomCheck),
     Builtin(BuiltinCheck),
+    Prebuilt(PrebuiltCheck),
+    /// Token-based comparison, performed by invoker without checker binary
+    Tokens,
 }
 
 #[derive(Debug)]
@@ -296,5 +1143,160 @@ pub struct Problem {
     pub check_options: CheckOptions,
     pub valuer: String,
     pub valuer_cfg: Option<String>,
-    pub limits: pom::Limits,
+    pub limits: LimitsPatch,
+    /// How many times failed generator should be rerun by default
+    pub testgen_retries: u32,
+    /// If set, this valuer binary is used instead of one from build environment
+    pub valuer_exe_override: Option<String>,
+    /// If set, builtin checkers are searched in this directory instead of build environment
+    pub builtin_checker_dir_override: Option<String>,
+    /// Strip BOM and convert CRLF to LF in test inputs
+    pub normalize_line_endings: bool,
+    /// Solutions used to generate correct answers. All of them must produce
+    /// same answer. If empty, `primary_solution` is used.
+    pub answer_solutions: Vec<String>,
+    /// Check that checker accepts correct answer and rejects corrupted one
+    pub checker_self_test: bool,
+    /// Check that checker accepts correct answers of all tests
+    pub verify_answers: bool,
+    /// Correct answers are still generated (e.g. to check that answer
+    /// solutions agree), but are not included in the package
+    pub strip_answers: bool,
+    /// Run generators and solutions with environment, containing only
+    /// variables from `env_allowlist` (and ones set by pps itself)
+    pub clean_env: bool,
+    /// Variables, inherited from parent environment if `clean_env` is set
+    pub env_allowlist: Vec<String>,
+    /// Reorder tests so that tests of each group have contiguous ids
+    pub reorder_groups: bool,
+    /// Glob patterns (relative to problem dir) of data files, copied
+    /// into package. Each pattern must match at least one file.
+    pub attachments: Vec<String>,
+    /// Compiler warnings and unused generators or solutions fail the build
+    pub strict_build: bool,
+    /// Sources (relative to problem dir), which are allowed to have
+    /// warnings in strict build
+    pub strict_build_exempt: Vec<String>,
+    /// Command (program and its arguments), executed after package is built
+    pub post_build: Option<Vec<String>>,
+    /// Declared groups by id. If not empty, contains all groups of tests.
+    pub groups: BTreeMap<String, GroupInfo>,
+    /// Build fails if package becomes larger
+    pub max_package_bytes: Option<u64>,
+    /// Time limits are multiplied by this factor.
+    /// Can be overriden with `JJS_TIME_SCALE` environment variable.
+    pub time_scale: f64,
+    /// Generated tests, which have same input as previous test of the
+    /// same group, are removed. Tests copied from files are never removed.
+    pub dedup: bool,
+    /// Allow building problem without tests
+    pub allow_empty_tests: bool,
+    /// Naming scheme of test inputs and answers in package
+    pub test_naming: TestNaming,
+    /// Tests, imported from directory tree. They are placed after `tests`.
+    pub tests_tree: Option<TestsTree>,
+    /// Time limit factors by submission language, applied by invoker
+    pub lang_time_scale: BTreeMap<String, f64>,
+    /// Checker limits. They patch `pom::Limits::checker_default`,
+    /// not solution limits.
+    pub checker_limits: LimitsPatch,
+    /// If set, tests of each group are shuffled (with this seed) after
+    /// generation. Groups keep their positions, and test files are renamed
+    /// after new test ids. Disables reuse of previously built tests.
+    pub shuffle_tests: Option<u64>,
+    /// Meaning of checker exit codes (testlib convention by default)
+    pub checker_exit_codes: BTreeMap<i32, pom::CheckerVerdict>,
+    /// Wall-clock time limit (in milliseconds) of solutions, generating
+    /// correct answers. Test time limits do not apply to them.
+    pub answer_gen_time_limit: u64,
+    /// Generators, which use testlib `registerGen`. Random seed is passed
+    /// to them as last argument, because testlib seeds its generator
+    /// from command line.
+    pub testlib_generators: Vec<String>,
+    /// Source of program, which checks that solution output is well-formed
+    /// before it is compared with correct answer. It is built like custom
+    /// checker and uses checker protocol.
+    pub output_validator: Option<String>,
+    /// Statements, copied into package
+    pub statements: Vec<Statement>,
+    /// Verdicts, which solutions must get on given tests.
+    /// They are checked after tests are built.
+    pub expected_verdicts: Vec<ExpectedVerdict>,
+    /// Solutions, whose outputs are stored as alternate correct answers.
+    /// Unlike `answer_solutions`, they may disagree with primary answer.
+    pub alternate_answer_solutions: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_with_units_values() {
+        assert_eq!(parse_with_units("1.5s", TIME_UNITS), Ok(1500));
+        assert_eq!(parse_with_units(" 250 ms ", TIME_UNITS), Ok(250));
+        assert_eq!(parse_with_units("2min", TIME_UNITS), Ok(120_000));
+        assert_eq!(parse_with_units("256MiB", SIZE_UNITS), Ok(256 << 20));
+        assert_eq!(parse_with_units("0.5KB", SIZE_UNITS), Ok(500));
+        // integers are not rounded through f64
+        assert_eq!(
+            parse_with_units("9007199254740993B", SIZE_UNITS),
+            Ok(9_007_199_254_740_993)
+        );
+    }
+
+    #[test]
+    fn parse_with_units_errors() {
+        assert!(parse_with_units("1.5", TIME_UNITS).is_err());
+        assert!(parse_with_units("1h", TIME_UNITS).is_err());
+        assert!(parse_with_units("s", TIME_UNITS).is_err());
+        assert!(parse_with_units("1.2.3s", TIME_UNITS).is_err());
+        assert!(parse_with_units("20000000000GiB", SIZE_UNITS).is_err());
+        assert!(parse_with_units("18446744073709551616.0B", SIZE_UNITS).is_err());
+    }
+
+    #[test]
+    fn test_naming_file_names() {
+        assert_eq!(TestNaming::default(), TestNaming::Txt);
+        assert_eq!(TestNaming::Txt.input_name(3), "3-in.txt");
+        assert_eq!(TestNaming::Txt.answer_name(3), "3-out.txt");
+        assert_eq!(
+            TestNaming::Txt.alternate_answer_name(3, "slow"),
+            "3-alt-slow.txt"
+        );
+        assert_eq!(TestNaming::Ans.input_name(12), "12.in");
+        assert_eq!(TestNaming::Ans.answer_name(12), "12.ans");
+        assert_eq!(
+            TestNaming::Ans.alternate_answer_name(12, "slow"),
+            "12.alt-slow.ans"
+        );
+    }
+
+    #[test]
+    fn test_naming_names_can_be_renumbered() {
+        for naming in [TestNaming::Txt, TestNaming::Ans].iter().copied() {
+            let names = [
+                naming.input_name(1),
+                naming.answer_name(1),
+                naming.alternate_answer_name(1, "slow"),
+            ];
+            for name in &names {
+                let renumbered = pom::renumber_path(name, 1, 10).unwrap();
+                assert_eq!(pom::renumber_path(&renumbered, 10, 1).as_ref(), Some(name));
+            }
+            assert_eq!(
+                pom::renumber_path(&naming.input_name(1), 1, 10),
+                Some(naming.input_name(10))
+            );
+        }
+    }
+
+    #[test]
+    fn test_naming_deserialize() {
+        let naming: TestNaming = serde_json::from_str("\"ans\"").unwrap();
+        assert_eq!(naming, TestNaming::Ans);
+        let naming: TestNaming = serde_json::from_str("\"txt\"").unwrap();
+        assert_eq!(naming, TestNaming::Txt);
+        assert!(serde_json::from_str::<TestNaming>("\"in\"").is_err());
+    }
 }
@@ -71,6 +71,14 @@ impl<Update> ProgressWriter<Update> {
         };
         self.tx.send(ChannelMessage::Done(out)).await.ok();
     }
+
+    /// Reports that operation was cancelled
+    pub async fn cancel(self) {
+        self.tx
+            .send(ChannelMessage::Done(Outcome::Cancelled))
+            .await
+            .ok();
+    }
 }
 
 pub(crate) fn start<U>() -> (Operation<U>, ProgressWriter<U>) {
@@ -81,3 +89,23 @@ pub(crate) fn start<U>() -> (Operation<U>, ProgressWriter<U>) {
 
     (op, pw)
 }
+
+/// Writes operation updates as JSON Lines, i.e. one JSON object per line,
+/// so that they can be consumed by other programs
+pub struct JsonLinesWriter<W> {
+    writer: W,
+}
+
+impl<W: std::io::Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesWriter { writer }
+    }
+
+    /// Writes single update and flushes underlying writer
+    pub fn write<U: serde::Serialize>(&mut self, update: &U) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, update)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
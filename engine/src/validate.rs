@@ -0,0 +1,211 @@
+//! Manifest validation, which does not require build environment
+use crate::manifest::{Check, Problem, TestGenSpec, TestgenStdin};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("failed to read manifest: {0:#}")]
+    Read(anyhow::Error),
+    #[error("manifest parse error: {0:#}")]
+    Parse(anyhow::Error),
+    #[error("invalid manifest: {0:#}")]
+    Invalid(anyhow::Error),
+    #[error("{what} {} does not exist", path.display())]
+    MissingFile { what: &'static str, path: PathBuf },
+    #[error("unknown solution {0}")]
+    UnknownSolution(String),
+    #[error("unknown test generator {name} (used by test {test_id})")]
+    UnknownTestgen { name: String, test_id: usize },
+    #[error("unknown test generator {0} in testlib-generators")]
+    UnknownTestlibGenerator(String),
+    #[error("{what} {name} is never used")]
+    Unused { what: &'static str, name: String },
+}
+
+/// Returns file stems of all entries in `dir`, i.e. names under which
+/// solutions or generators are available
+fn list_names(dir: &Path) -> BTreeSet<String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return BTreeSet::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| {
+            entry
+                .path()
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(ToString::to_string)
+        })
+        .collect()
+}
+
+/// Returns generators, which are not used by any test, and solutions,
+/// which neither generate answers, nor are primary, nor have expected
+/// verdicts
+pub(crate) fn find_unused(problem_dir: &Path, problem: &Problem) -> Vec<ManifestError> {
+    let mut unused = Vec::new();
+    let mut used_testgens = BTreeSet::new();
+    for test in &problem.tests {
+        let (testgen, filter) = match &test.gen {
+            TestGenSpec::Generate {
+                testgen, filter, ..
+            } => (testgen, filter),
+            TestGenSpec::GenerateFromFile {
+                testgen, filter, ..
+            } => (testgen, filter),
+            TestGenSpec::File { .. } => continue,
+        };
+        used_testgens.insert(testgen.as_str());
+        if let Some(filter) = filter {
+            used_testgens.insert(filter[0].as_str());
+        }
+    }
+    for name in list_names(&problem_dir.join("generators")) {
+        if !used_testgens.contains(name.as_str()) {
+            unused.push(ManifestError::Unused {
+                what: "generator",
+                name,
+            });
+        }
+    }
+    for name in list_names(&problem_dir.join("solutions")) {
+        let used = problem.primary_solution.as_ref() == Some(&name)
+            || problem.answer_solutions.contains(&name)
+            || problem.alternate_answer_solutions.contains(&name)
+            || problem
+                .expected_verdicts
+                .iter()
+                .any(|expected| expected.solution == name);
+        if !used {
+            unused.push(ManifestError::Unused {
+                what: "solution",
+                name,
+            });
+        }
+    }
+    unused
+}
+
+/// Parses problem manifest and checks that all files it refers to exist.
+/// Nothing is built, so this check is fast and does not need build
+/// environment. All found problems are reported at once.
+pub fn validate_manifest(problem_dir: &Path) -> Result<(), Vec<ManifestError>> {
+    let (manifest_path, format) = crate::manifest::find_manifest(problem_dir)
+        .map_err(|err| vec![ManifestError::Read(err)])?;
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .map_err(|err| vec![ManifestError::Read(err.into())])?;
+    let raw = format
+        .parse(&manifest)
+        .map_err(|err| vec![ManifestError::Parse(err)])?;
+    let (problem, _warnings) = raw
+        .postprocess()
+        .map_err(|err| vec![ManifestError::Invalid(err)])?;
+
+    let mut errors = Vec::new();
+    let mut check_file = |what, path: PathBuf| {
+        if !path.exists() {
+            errors.push(ManifestError::MissingFile { what, path });
+        }
+    };
+    match &problem.check {
+        Check::Custom(_) => {
+            check_file("checker source", problem_dir.join("checkers/main.cpp"));
+        }
+        Check::Prebuilt(pc) => {
+            check_file(
+                "prebuilt checker",
+                problem_dir.join(pc.path.trim_start_matches('/')),
+            );
+        }
+        Check::Builtin(_) | Check::Tokens => {}
+    }
+    if let Some(valuer_cfg) = &problem.valuer_cfg {
+        check_file(
+            "valuer config",
+            problem_dir.join(valuer_cfg.trim_start_matches('/')),
+        );
+    }
+    if let Some(validator) = &problem.output_validator {
+        check_file(
+            "output validator source",
+            problem_dir.join(validator.trim_start_matches('/')),
+        );
+    }
+    for statement in &problem.statements {
+        check_file("statement", problem_dir.join(&statement.path));
+    }
+    if let Some(tree) = &problem.tests_tree {
+        check_file("tests tree", problem_dir.join("tests").join(&tree.dir));
+    }
+    for test in &problem.tests {
+        let stdin_data = match &test.gen {
+            TestGenSpec::Generate { stdin_data, .. } => stdin_data,
+            TestGenSpec::GenerateFromFile { stdin_data, .. } => stdin_data,
+            TestGenSpec::File { .. } => &None,
+        };
+        if let Some(TestgenStdin::File(path)) = stdin_data {
+            check_file("generator stdin file", problem_dir.join(path));
+        }
+        match &test.gen {
+            TestGenSpec::File { path } => {
+                check_file("test file", problem_dir.join("tests").join(path));
+            }
+            TestGenSpec::GenerateFromFile { args_file, .. } => {
+                check_file("arguments file", problem_dir.join(args_file));
+            }
+            TestGenSpec::Generate { .. } => {}
+        }
+    }
+
+    let solutions = list_names(&problem_dir.join("solutions"));
+    for name in problem
+        .primary_solution
+        .iter()
+        .chain(problem.answer_solutions.iter())
+        .chain(problem.alternate_answer_solutions.iter())
+        .chain(problem.expected_verdicts.iter().map(|e| &e.solution))
+    {
+        if !solutions.contains(name) {
+            errors.push(ManifestError::UnknownSolution(name.clone()));
+        }
+    }
+    let testgens = list_names(&problem_dir.join("generators"));
+    for (i, test) in problem.tests.iter().enumerate() {
+        let (testgen, filter) = match &test.gen {
+            TestGenSpec::Generate {
+                testgen, filter, ..
+            } => (testgen, filter),
+            TestGenSpec::GenerateFromFile {
+                testgen, filter, ..
+            } => (testgen, filter),
+            TestGenSpec::File { .. } => continue,
+        };
+        for name in std::iter::once(testgen).chain(filter.iter().map(|filter| &filter[0])) {
+            if !testgens.contains(name) {
+                errors.push(ManifestError::UnknownTestgen {
+                    name: name.clone(),
+                    test_id: i + 1,
+                });
+            }
+        }
+    }
+
+    for name in &problem.testlib_generators {
+        if !testgens.contains(name) {
+            errors.push(ManifestError::UnknownTestlibGenerator(name.clone()));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,316 @@
+//! Minimal zip archive writer.
+//!
+//! Files are stored without compression. Each file is streamed into the
+//! archive in chunks, so large tests are never loaded into memory as a
+//! whole. ZIP64 is not supported, so archive must be smaller than 4 GiB.
+use anyhow::Context as _;
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x0605_4b50;
+/// Version 2.0: required for directories and stored files
+const VERSION_NEEDED: u16 = 20;
+/// Upper byte is host system (3 = unix), so that file modes are preserved
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_NEEDED;
+/// Entry names are encoded in UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+/// Modification date of all entries (1980-01-01), so that archive is
+/// reproducible
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// Offset of CRC-32 field in local file header
+const LOCAL_HEADER_CRC_OFFSET: u64 = 14;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// CRC-32 (IEEE 802.3), as required by zip format
+struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *entry = c;
+        }
+        Crc32 {
+            table,
+            value: 0xFFFF_FFFF,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.value = 0xFFFF_FFFF;
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            let idx = (self.value ^ u32::from(byte)) & 0xFF;
+            self.value = self.table[idx as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+/// Entry, already written into archive
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    mode: u32,
+    offset: u32,
+}
+
+fn to_u32(value: u64, what: &str) -> anyhow::Result<u32> {
+    u32::try_from(value).map_err(|_| anyhow::anyhow!("{} is too large for zip archive", what))
+}
+
+struct ZipWriter {
+    out: BufWriter<File>,
+    entries: Vec<Entry>,
+    crc: Crc32,
+    buf: Vec<u8>,
+}
+
+impl ZipWriter {
+    fn position(&mut self) -> anyhow::Result<u32> {
+        let pos = self.out.stream_position()?;
+        to_u32(pos, "archive")
+    }
+
+    /// Appends file `path` as entry `name`
+    fn add_file(&mut self, name: String, path: &Path) -> anyhow::Result<()> {
+        let mut file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let mode = file.metadata()?.permissions().mode();
+        let offset = self.position()?;
+        write_local_header(&mut self.out, &name)?;
+        self.crc.reset();
+        let mut size = 0u64;
+        loop {
+            let cnt = file
+                .read(&mut self.buf)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            if cnt == 0 {
+                break;
+            }
+            self.crc.update(&self.buf[..cnt]);
+            self.out.write_all(&self.buf[..cnt])?;
+            size += cnt as u64;
+        }
+        let size = to_u32(size, &format!("file {}", path.display()))?;
+        let crc = self.crc.finish();
+        let end = self.out.stream_position()?;
+        self.out
+            .seek(SeekFrom::Start(u64::from(offset) + LOCAL_HEADER_CRC_OFFSET))?;
+        self.out.write_all(&crc.to_le_bytes())?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.entries.push(Entry {
+            name,
+            crc,
+            size,
+            mode,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Writes central directory
+    fn finish(mut self) -> anyhow::Result<()> {
+        let entries_count =
+            u16::try_from(self.entries.len()).context("too many files for zip archive")?;
+        let central_dir_offset = self.position()?;
+        for entry in &self.entries {
+            let out = &mut self.out;
+            out.write_all(&CENTRAL_HEADER_SIGNATURE.to_le_bytes())?;
+            out.write_all(&VERSION_MADE_BY.to_le_bytes())?;
+            out.write_all(&VERSION_NEEDED.to_le_bytes())?;
+            out.write_all(&FLAG_UTF8.to_le_bytes())?;
+            // compression method (stored) and DOS time
+            out.write_all(&0u16.to_le_bytes())?;
+            out.write_all(&0u16.to_le_bytes())?;
+            out.write_all(&DOS_DATE.to_le_bytes())?;
+            out.write_all(&entry.crc.to_le_bytes())?;
+            out.write_all(&entry.size.to_le_bytes())?;
+            out.write_all(&entry.size.to_le_bytes())?;
+            out.write_all(&(entry.name.len() as u16).to_le_bytes())?;
+            // extra field length, comment length, disk number, internal attributes
+            for _ in 0..4 {
+                out.write_all(&0u16.to_le_bytes())?;
+            }
+            out.write_all(&(entry.mode << 16).to_le_bytes())?;
+            out.write_all(&entry.offset.to_le_bytes())?;
+            out.write_all(entry.name.as_bytes())?;
+        }
+        let central_dir_end = self.position()?;
+        let out = &mut self.out;
+        out.write_all(&END_OF_CENTRAL_DIR_SIGNATURE.to_le_bytes())?;
+        // disk numbers
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&entries_count.to_le_bytes())?;
+        out.write_all(&entries_count.to_le_bytes())?;
+        out.write_all(&(central_dir_end - central_dir_offset).to_le_bytes())?;
+        out.write_all(&central_dir_offset.to_le_bytes())?;
+        // comment length
+        out.write_all(&0u16.to_le_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes local file header of entry `name`. CRC and sizes are not
+/// known yet, so they are written as zeros and patched after file data.
+fn write_local_header(out: &mut impl Write, name: &str) -> anyhow::Result<()> {
+    if name.len() > usize::from(u16::MAX) {
+        anyhow::bail!("file name {} is too long", name);
+    }
+    out.write_all(&LOCAL_HEADER_SIGNATURE.to_le_bytes())?;
+    out.write_all(&VERSION_NEEDED.to_le_bytes())?;
+    out.write_all(&FLAG_UTF8.to_le_bytes())?;
+    // compression method (stored) and DOS time
+    out.write_all(&0u16.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+    out.write_all(&DOS_DATE.to_le_bytes())?;
+    // CRC, compressed and uncompressed sizes
+    for _ in 0..3 {
+        out.write_all(&0u32.to_le_bytes())?;
+    }
+    out.write_all(&(name.len() as u16).to_le_bytes())?;
+    // extra field length
+    out.write_all(&0u16.to_le_bytes())?;
+    out.write_all(name.as_bytes())?;
+    Ok(())
+}
+
+/// Writes zip archive `archive`, containing `files` (paths relative
+/// to `root`). Inside archive, files have same relative paths.
+fn write_zip(root: &Path, files: &[PathBuf], archive: &Path) -> anyhow::Result<()> {
+    let out =
+        File::create(archive).with_context(|| format!("failed to create {}", archive.display()))?;
+    let mut writer = ZipWriter {
+        out: BufWriter::new(out),
+        entries: Vec::new(),
+        crc: Crc32::new(),
+        buf: vec![0; CHUNK_SIZE],
+    };
+    for file in files {
+        let name = file
+            .to_str()
+            .with_context(|| format!("path {} is not utf8", file.display()))?
+            .to_string();
+        writer
+            .add_file(name, &root.join(file))
+            .with_context(|| format!("failed to add {} to archive", file.display()))?;
+    }
+    writer.finish()
+}
+
+/// Packs compiled package into zip archive, preserving its layout
+/// (`manifest.json`, `assets/` etc.)
+pub(crate) async fn write_package_zip(package_dir: &Path, archive: &Path) -> anyhow::Result<()> {
+    let files = crate::checksums::package_files(package_dir).await?;
+    let package_dir = package_dir.to_path_buf();
+    let archive = archive.to_path_buf();
+    tokio::task::spawn_blocking(move || write_zip(&package_dir, &files, &archive))
+        .await
+        .unwrap()
+        .context("failed to write archive")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u16(data: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes([data[pos], data[pos + 1]])
+    }
+
+    fn read_u32(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        crc.finish()
+    }
+
+    #[test]
+    fn crc32_known_vector() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let mut crc = Crc32::new();
+        crc.update(b"garbage");
+        crc.reset();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("pps-zip-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let files: &[(&str, &[u8])] = &[("a.txt", &b"hello"[..]), ("sub/b.txt", &b"123456789"[..])];
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        let paths: Vec<PathBuf> = files.iter().map(|(name, _)| PathBuf::from(name)).collect();
+        let archive = dir.join("out.zip");
+        write_zip(&dir, &paths, &archive).unwrap();
+        let data = std::fs::read(&archive).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let eocd = data.len() - 22;
+        assert_eq!(read_u32(&data, eocd), END_OF_CENTRAL_DIR_SIGNATURE);
+        assert_eq!(usize::from(read_u16(&data, eocd + 10)), files.len());
+        let central_dir_size = read_u32(&data, eocd + 12) as usize;
+        let mut pos = read_u32(&data, eocd + 16) as usize;
+        assert_eq!(pos + central_dir_size, eocd);
+
+        for (name, contents) in files {
+            assert_eq!(read_u32(&data, pos), CENTRAL_HEADER_SIGNATURE);
+            let crc = read_u32(&data, pos + 16);
+            let size = read_u32(&data, pos + 24) as usize;
+            let name_len = usize::from(read_u16(&data, pos + 28));
+            let offset = read_u32(&data, pos + 42) as usize;
+            assert_eq!(&data[pos + 46..pos + 46 + name_len], name.as_bytes());
+            assert_eq!(crc, crc32(contents));
+            assert_eq!(size, contents.len());
+            assert_eq!(read_u32(&data, pos + 20) as usize, size);
+            pos += 46 + name_len;
+
+            assert_eq!(read_u32(&data, offset), LOCAL_HEADER_SIGNATURE);
+            assert_eq!(read_u16(&data, offset + 8), 0);
+            assert_eq!(read_u32(&data, offset + 14), crc);
+            assert_eq!(read_u32(&data, offset + 18) as usize, size);
+            assert_eq!(read_u32(&data, offset + 22) as usize, size);
+            assert_eq!(usize::from(read_u16(&data, offset + 26)), name_len);
+            let data_start = offset + 30 + name_len + usize::from(read_u16(&data, offset + 28));
+            assert_eq!(&data[offset + 30..offset + 30 + name_len], name.as_bytes());
+            assert_eq!(&data[data_start..data_start + size], *contents);
+        }
+        assert_eq!(pos, eocd);
+    }
+}
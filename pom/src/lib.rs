@@ -1,11 +1,18 @@
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+/// Resource limits. Defaults are already applied when package is built
+/// (see `Limits::default`), so there `None` means that resource is not
+/// limited, as reported by `*_opt` accessors.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Limits {
     /// Memory limit in bytes
     pub memory: Option<u64>,
-    /// Time limit in milliseconds
+    /// Wall-clock time limit in milliseconds
     pub time: Option<u64>,
+    /// CPU time limit in milliseconds
+    #[serde(default)]
+    pub cpu_time: Option<u64>,
     /// Process count limit
     pub process_count: Option<u64>,
     /// Working dir size limit in bytes
@@ -29,6 +36,18 @@ impl Limits {
         16 * 1024 * 1024
     }
 
+    /// Default limits of checker. They are more generous than default
+    /// solution limits, because checker may process huge outputs.
+    pub fn checker_default() -> Limits {
+        Limits {
+            memory: Some(1024 * 1024 * 1024),
+            time: Some(10_000),
+            cpu_time: None,
+            process_count: Some(Limits::default_num_procs()),
+            work_dir_size: Some(Limits::default_work_dir_size()),
+        }
+    }
+
     pub fn time(self) -> u64 {
         self.time.unwrap_or_else(Self::default_time)
     }
@@ -45,6 +64,31 @@ impl Limits {
         self.work_dir_size
             .unwrap_or_else(Self::default_work_dir_size)
     }
+
+    /// Returns wall-clock time limit, or `None` if time is unlimited
+    pub fn time_opt(self) -> Option<u64> {
+        self.time
+    }
+
+    /// Returns CPU time limit, or `None` if CPU time is unlimited
+    pub fn cpu_time_opt(self) -> Option<u64> {
+        self.cpu_time
+    }
+
+    /// Returns memory limit, or `None` if memory is unlimited
+    pub fn memory_opt(self) -> Option<u64> {
+        self.memory
+    }
+
+    /// Returns process count limit, or `None` if it is unlimited
+    pub fn process_count_opt(self) -> Option<u64> {
+        self.process_count
+    }
+
+    /// Returns working dir size limit, or `None` if it is unlimited
+    pub fn work_dir_size_opt(self) -> Option<u64> {
+        self.work_dir_size
+    }
 }
 
 impl Default for Limits {
@@ -52,6 +96,7 @@ impl Default for Limits {
         Limits {
             memory: Some(Limits::default_memory()),
             time: Some(Limits::default_time()),
+            cpu_time: None,
             process_count: Some(Limits::default_num_procs()),
             work_dir_size: Some(Limits::default_work_dir_size()),
         }
@@ -60,8 +105,12 @@ impl Default for Limits {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FileRefRoot {
+    /// Path is relative to `assets` directory of problem package
     Problem,
+    /// Path is relative to filesystem root
     Root,
+    /// Path is relative to `assets/attachments` directory of problem package
+    Attachments,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,12 +119,54 @@ pub struct FileRef {
     pub path: String,
 }
 
+impl FileRef {
+    /// Returns absolute path of referenced file, given path to
+    /// problem package directory
+    pub fn resolve(&self, problem_root: &Path) -> PathBuf {
+        let root = match self.root {
+            FileRefRoot::Problem => problem_root.join("assets"),
+            FileRefRoot::Root => PathBuf::from("/"),
+            FileRefRoot::Attachments => problem_root.join("assets/attachments"),
+        };
+        root.join(self.path.trim_start_matches('/'))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Test {
     pub path: FileRef,
     pub correct: Option<FileRef>,
+    /// Other correct answers. Solution output is accepted if checker
+    /// accepts it against any of correct answers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternate_correct: Vec<FileRef>,
     pub limits: Limits,
     pub group: String,
+    /// Set for generated tests
+    #[serde(default)]
+    pub provenance: Option<TestProvenance>,
+    /// Time limit as specified by problem author, i.e. before it was
+    /// multiplied by `Problem::time_scale`
+    #[serde(default)]
+    pub raw_time: Option<u64>,
+}
+
+impl Test {
+    /// Returns all correct answers of the test, primary answer first
+    pub fn correct_answers(&self) -> impl Iterator<Item = &FileRef> {
+        self.correct.iter().chain(&self.alternate_correct)
+    }
+}
+
+/// Describes how test was generated, so that it can be reproduced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestProvenance {
+    /// Test generator name
+    pub generator: String,
+    /// Arguments passed to test generator
+    pub args: Vec<String>,
+    /// Value of `JJS_RANDOM_SEED` used during generation
+    pub seed: String,
 }
 
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -120,6 +211,10 @@ impl std::ops::Index<TestId> for Vec<Test> {
     }
 }
 
+fn default_time_scale() -> f64 {
+    1.0
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Problem {
     pub title: String,
@@ -129,4 +224,402 @@ pub struct Problem {
     pub checker_cmd: Vec<String>,
     pub valuer_exe: FileRef,
     pub valuer_cfg: FileRef,
+    /// Version of valuer binary, included into package
+    #[serde(default)]
+    pub valuer_version: Option<String>,
+    /// Data files, available to checker and test generators
+    #[serde(default)]
+    pub attachments: Vec<FileRef>,
+    /// Factor, applied to time limits of all tests
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64,
+    /// Descriptions of test groups, in order of their first test.
+    /// Empty if groups were not declared.
+    #[serde(default)]
+    pub groups: Vec<GroupInfo>,
+    /// Commands (program and arguments), which compiled checker from
+    /// source, in order. Empty if checker was not compiled (e.g. it is
+    /// builtin or prebuilt).
+    #[serde(default)]
+    pub checker_build_commands: Vec<Vec<String>>,
+    /// If set, invoker compares solution output with correct answer itself.
+    /// In this case `checker_exe` is only a marker and must not be run.
+    #[serde(default)]
+    pub builtin_comparison: Option<BuiltinComparison>,
+    /// Additional time limit factors by submission language (e.g. `python`).
+    /// Applied by invoker on top of test time limits.
+    #[serde(default)]
+    pub lang_time_scale: std::collections::BTreeMap<String, f64>,
+    /// Limits, which invoker should apply when running checker
+    #[serde(default = "Limits::checker_default")]
+    pub checker_limits: Limits,
+    /// If tests were shuffled, `test_order[i]` is id of test `i + 1`
+    /// in generation order
+    #[serde(default)]
+    pub test_order: Option<Vec<usize>>,
+    /// Meaning of checker exit codes. Codes, which are not listed here,
+    /// mean checker failure.
+    #[serde(default = "CheckerVerdict::testlib_exit_codes")]
+    pub checker_exit_codes: std::collections::BTreeMap<i32, CheckerVerdict>,
+    /// If set, invoker runs this program before checker, with same protocol
+    /// and limits. Checker is only run if validator accepts solution output.
+    #[serde(default)]
+    pub output_validator_exe: Option<FileRef>,
+    /// Problem statements, possibly several per language
+    #[serde(default)]
+    pub statements: Vec<Statement>,
+}
+
+/// Verdict, reported by checker exit code
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum CheckerVerdict {
+    Ok,
+    WrongAnswer,
+    PresentationError,
+    /// Checker found that test (e.g. correct answer) is invalid
+    CheckerLogicError,
+}
+
+impl CheckerVerdict {
+    /// Returns outcome name, as written by checker into `JJS_CHECKER_OUT`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckerVerdict::Ok => "Ok",
+            CheckerVerdict::WrongAnswer => "WrongAnswer",
+            CheckerVerdict::PresentationError => "PresentationError",
+            CheckerVerdict::CheckerLogicError => "CheckerLogicError",
+        }
+    }
+
+    /// Exit codes of testlib checkers
+    pub fn testlib_exit_codes() -> std::collections::BTreeMap<i32, CheckerVerdict> {
+        vec![
+            (0, CheckerVerdict::Ok),
+            (1, CheckerVerdict::WrongAnswer),
+            (2, CheckerVerdict::PresentationError),
+            (3, CheckerVerdict::CheckerLogicError),
+        ]
+        .into_iter()
+        .collect()
+    }
+}
+
+/// Output comparison, performed without checker binary
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuiltinComparison {
+    /// Output must consist of the same whitespace-separated tokens as
+    /// correct answer, so trailing whitespace and line endings are ignored
+    Tokens,
+}
+
+fn tokens(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split(|b| b.is_ascii_whitespace())
+        .filter(|token| !token.is_empty())
+}
+
+impl BuiltinComparison {
+    /// Returns true if solution output matches correct answer
+    pub fn accepts(self, output: &[u8], answer: &[u8]) -> bool {
+        match self {
+            BuiltinComparison::Tokens => tokens(output).eq(tokens(answer)),
+        }
+    }
+}
+
+/// Problem statement file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statement {
+    /// Language code, e.g. `en`
+    pub lang: String,
+    pub file: FileRef,
+}
+
+/// Human-facing description of test group
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GroupInfo {
+    /// Group id, as used in `Test::group`
+    pub id: String,
+    /// Display name
+    pub name: String,
+    pub description: String,
+    /// Points, declared for this group
+    pub points: Option<u32>,
+}
+
+/// Brief information about single test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestSummary {
+    pub test_id: TestId,
+    pub group: String,
+    /// Limits, applied by invoker (before `Problem::lang_time_scale`),
+    /// i.e. defaults, patched by problem-wide and per-test limits. Use
+    /// `Limits::*_opt` accessors to read them.
+    pub limits: Limits,
+}
+
+impl Problem {
+    /// Returns time limit factor for submissions in given language
+    pub fn lang_time_scale(&self, lang: &str) -> f64 {
+        self.lang_time_scale.get(lang).copied().unwrap_or(1.0)
+    }
+
+    /// Returns group and effective limits of each test, in test id order
+    pub fn test_summary(&self) -> Vec<TestSummary> {
+        self.tests
+            .iter()
+            .enumerate()
+            .map(|(i, test)| TestSummary {
+                test_id: TestId::make(i as u32 + 1),
+                group: test.group.clone(),
+                limits: test.limits,
+            })
+            .collect()
+    }
+
+    /// Returns ids of tests of given group, in increasing order
+    pub fn tests_in_group(&self, group: &str) -> Vec<TestId> {
+        self.tests
+            .iter()
+            .enumerate()
+            .filter(|(_, test)| test.group == group)
+            .map(|(i, _)| TestId::make(i as u32 + 1))
+            .collect()
+    }
+
+    /// Returns names of all groups, which have tests, sorted. Unlike
+    /// `groups` field, it is not empty when groups were not declared.
+    pub fn groups(&self) -> Vec<String> {
+        let groups: std::collections::BTreeSet<&str> =
+            self.tests.iter().map(|test| test.group.as_str()).collect();
+        groups.into_iter().map(ToString::to_string).collect()
+    }
+
+    /// Returns group of given test, or `None` if test does not exist
+    pub fn group_of(&self, test_id: TestId) -> Option<&str> {
+        self.tests
+            .get(test_id.to_idx())
+            .map(|test| test.group.as_str())
+    }
+
+    /// Appends `other` tests (e.g. tests of another package).
+    ///
+    /// If `renumber` is set, files of appended tests are renamed after
+    /// their new ids (e.g. `tests/3-in.txt` of test, which becomes test 7,
+    /// is renamed to `tests/7-in.txt`), and groups, which already exist in
+    /// this problem, are prefixed with `merged-`. Otherwise paths and
+    /// groups are kept, so paths must not collide with existing files.
+    ///
+    /// Files are not copied: returns `(source, destination)` pairs of
+    /// package files (rooted at `FileRefRoot::Problem`) of appended tests,
+    /// so that caller can copy them into this package.
+    ///
+    /// When renumbering, file names of `other` must start with test id
+    /// (it is not the case e.g. if `other` was shuffled). Otherwise error
+    /// is returned and this problem is not modified.
+    pub fn merge_tests(
+        &mut self,
+        other: &[Test],
+        renumber: bool,
+    ) -> Result<Vec<(FileRef, FileRef)>, RenumberError> {
+        let existing_groups: std::collections::BTreeSet<String> =
+            self.tests.iter().map(|test| test.group.clone()).collect();
+        let mut files = Vec::new();
+        let mut new_tests = Vec::new();
+        for (i, test) in other.iter().enumerate() {
+            let old_id = i + 1;
+            let new_id = self.tests.len() + old_id;
+            let mut test = test.clone();
+            let test_files = std::iter::once(&mut test.path)
+                .chain(test.correct.iter_mut())
+                .chain(test.alternate_correct.iter_mut());
+            for file in test_files {
+                if !matches!(file.root, FileRefRoot::Problem) {
+                    continue;
+                }
+                let source = file.clone();
+                if renumber {
+                    file.path =
+                        renumber_path(&file.path, old_id, new_id).ok_or_else(|| RenumberError {
+                            path: file.path.clone(),
+                            test_id: old_id,
+                        })?;
+                }
+                files.push((source, file.clone()));
+            }
+            if renumber {
+                while existing_groups.contains(&test.group) {
+                    test.group = format!("merged-{}", test.group);
+                }
+            }
+            new_tests.push(test);
+        }
+        for test in new_tests {
+            self.tests.push(test);
+            if let Some(order) = &mut self.test_order {
+                // appended tests were not shuffled
+                order.push(self.tests.len());
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Error of `Problem::merge_tests`: file of appended test can not be
+/// renumbered, because its name does not start with test id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenumberError {
+    pub path: String,
+    /// Id of the test in the appended package
+    pub test_id: usize,
+}
+
+impl std::fmt::Display for RenumberError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "can not renumber {}: file name does not start with test id {}",
+            self.path, self.test_id
+        )
+    }
+}
+
+impl std::error::Error for RenumberError {}
+
+/// Replaces test id in file name, which starts with it
+/// (e.g. `tests/3-in.txt` or `tests/3.ans`). Returns `None` if file
+/// name does not start with `old_id`.
+pub fn renumber_path(path: &str, old_id: usize, new_id: usize) -> Option<String> {
+    let (dir, file_name) = match path.rfind('/') {
+        Some(pos) => path.split_at(pos + 1),
+        None => ("", path),
+    };
+    let old_id = old_id.to_string();
+    match file_name.strip_prefix(&old_id) {
+        Some(rest) if !rest.starts_with(|c: char| c.is_ascii_digit()) => {
+            Some(format!("{}{}{}", dir, new_id, rest))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> FileRef {
+        FileRef {
+            root: FileRefRoot::Problem,
+            path: path.to_string(),
+        }
+    }
+
+    fn make_test(id: usize, group: &str) -> Test {
+        Test {
+            path: file(&format!("tests/{}-in.txt", id)),
+            correct: Some(file(&format!("tests/{}-out.txt", id))),
+            alternate_correct: Vec::new(),
+            limits: Limits::default(),
+            group: group.to_string(),
+            provenance: None,
+            raw_time: None,
+        }
+    }
+
+    fn problem(tests: Vec<Test>) -> Problem {
+        Problem {
+            title: "Test".to_string(),
+            name: "test".to_string(),
+            tests,
+            checker_exe: file("checker"),
+            checker_cmd: Vec::new(),
+            valuer_exe: file("valuer"),
+            valuer_cfg: file("valuer-cfg.yaml"),
+            valuer_version: None,
+            attachments: Vec::new(),
+            time_scale: 1.0,
+            groups: Vec::new(),
+            checker_build_commands: Vec::new(),
+            builtin_comparison: None,
+            lang_time_scale: Default::default(),
+            checker_limits: Limits::checker_default(),
+            test_order: None,
+            checker_exit_codes: CheckerVerdict::testlib_exit_codes(),
+            output_validator_exe: None,
+            statements: Vec::new(),
+        }
+    }
+
+    fn paths(files: &[(FileRef, FileRef)]) -> Vec<(&str, &str)> {
+        files
+            .iter()
+            .map(|(src, dest)| (src.path.as_str(), dest.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn renumber_path_replaces_id_prefix() {
+        assert_eq!(
+            renumber_path("tests/3-in.txt", 3, 7).as_deref(),
+            Some("tests/7-in.txt")
+        );
+        assert_eq!(renumber_path("3.ans", 3, 12).as_deref(), Some("12.ans"));
+        assert_eq!(renumber_path("tests/31-in.txt", 3, 7), None);
+        assert_eq!(renumber_path("tests/in-3.txt", 3, 7), None);
+    }
+
+    #[test]
+    fn merge_renumbered() {
+        let mut target = problem(vec![make_test(1, "main")]);
+        let files = target
+            .merge_tests(&[make_test(1, "main"), make_test(2, "extra")], true)
+            .unwrap();
+        assert_eq!(
+            paths(&files),
+            [
+                ("tests/1-in.txt", "tests/2-in.txt"),
+                ("tests/1-out.txt", "tests/2-out.txt"),
+                ("tests/2-in.txt", "tests/3-in.txt"),
+                ("tests/2-out.txt", "tests/3-out.txt"),
+            ]
+        );
+        let groups: Vec<&str> = target.tests.iter().map(|t| t.group.as_str()).collect();
+        assert_eq!(groups, ["main", "merged-main", "extra"]);
+        assert_eq!(target.tests[2].path.path, "tests/3-in.txt");
+    }
+
+    #[test]
+    fn merge_not_renumbered() {
+        let mut target = problem(vec![make_test(1, "main")]);
+        let mut other = make_test(1, "main");
+        other.path = file("merged/1-in.txt");
+        other.correct = Some(file("merged/1-out.txt"));
+        let files = target.merge_tests(&[other], false).unwrap();
+        assert_eq!(
+            paths(&files),
+            [
+                ("merged/1-in.txt", "merged/1-in.txt"),
+                ("merged/1-out.txt", "merged/1-out.txt"),
+            ]
+        );
+        assert_eq!(target.tests.len(), 2);
+        assert_eq!(target.tests[1].group, "main");
+    }
+
+    #[test]
+    fn merge_rejects_mismatched_name() {
+        let mut target = problem(vec![make_test(1, "main")]);
+        // e.g. shuffled package, where test 1 is stored in file of test 2
+        let err = target
+            .merge_tests(&[make_test(2, "main")], true)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RenumberError {
+                path: "tests/2-in.txt".to_string(),
+                test_id: 1,
+            }
+        );
+        assert_eq!(target.tests.len(), 1);
+    }
 }
@@ -0,0 +1,179 @@
+//! Tokio-based JSON driver
+use crate::AsyncValuerDriver;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Message {
+    ProblemInfo(valuer_api::ProblemInfo),
+    TestDoneNotify(valuer_api::TestDoneNotification),
+}
+
+const PROBLEM_INFO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Json-RPC driver, used in integration with JJS invoker. Unlike
+/// `JsonDriver` of svaluer binary, it runs on tokio runtime of caller
+/// (see `AsyncSimpleValuer`), and waits for notifications only when some
+/// tests are running.
+///
+/// Messages are read from `input` and responses are written to `output`,
+/// one JSON object per line.
+pub struct AsyncJsonDriver<R, W> {
+    lines: Lines<R>,
+    output: W,
+    /// Count of requested tests, which are not finished yet
+    running_tests: usize,
+    /// If set, full judge log is additionally written to this file when judging finishes
+    judge_log_out: Option<PathBuf>,
+    full_judge_log: Option<valuer_api::JudgeLog>,
+    /// Notifications are not awaited past this instant
+    deadline: Option<Instant>,
+}
+
+impl<R, W> std::fmt::Debug for AsyncJsonDriver<R, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncJsonDriver")
+            .field("running_tests", &self.running_tests)
+            .field("judge_log_out", &self.judge_log_out)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+impl AsyncJsonDriver<BufReader<tokio::io::Stdin>, tokio::io::Stdout> {
+    /// Creates driver, communicating using stdin and stdout
+    pub fn stdio() -> Self {
+        AsyncJsonDriver::new(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+    }
+}
+
+impl<R, W> AsyncJsonDriver<R, W>
+where
+    R: AsyncBufRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    pub fn new(input: R, output: W) -> Self {
+        AsyncJsonDriver {
+            lines: input.lines(),
+            output,
+            running_tests: 0,
+            judge_log_out: std::env::var_os("JJS_VALUER_JUDGE_LOG_OUT").map(PathBuf::from),
+            full_judge_log: None,
+            deadline: None,
+        }
+    }
+
+    /// Returns output, e.g. to inspect sent responses
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// Waits for next message. Malformed lines are reported and skipped.
+    async fn next_message(lines: &mut Lines<R>) -> Result<Message> {
+        loop {
+            let line = match lines.next_line().await.context("io error")? {
+                Some(line) => line,
+                None => bail!("stdin closed"),
+            };
+            match serde_json::from_str(&line) {
+                Ok(msg) => break Ok(msg),
+                Err(err) => {
+                    eprintln!(
+                        "svaluer: error: failed to deserialize invoker TestDoneNotification: {}",
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    async fn write_judge_log(&mut self) -> Result<()> {
+        let path = match &self.judge_log_out {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let judge_log = self
+            .full_judge_log
+            .take()
+            .context("full judge log was not emitted")?;
+        let data = serde_json::to_string(&judge_log).context("failed to serialize")?;
+        tokio::fs::write(path, data)
+            .await
+            .with_context(|| format!("failed to write judge log to {}", path.display()))
+    }
+}
+
+#[async_trait::async_trait]
+impl<R, W> AsyncValuerDriver for AsyncJsonDriver<R, W>
+where
+    R: AsyncBufRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    async fn problem_info(&mut self) -> Result<valuer_api::ProblemInfo> {
+        let message =
+            tokio::time::timeout(PROBLEM_INFO_TIMEOUT, Self::next_message(&mut self.lines))
+                .await
+                .map_err(|_| anyhow::anyhow!("timeout"))??;
+        match message {
+            Message::ProblemInfo(pi) => Ok(pi),
+            Message::TestDoneNotify(tdn) => bail!("got TestDoneNotification {:?} instead", tdn),
+        }
+    }
+
+    async fn send_command(&mut self, cmd: &valuer_api::ValuerResponse) -> Result<()> {
+        match cmd {
+            valuer_api::ValuerResponse::JudgeLog(judge_log)
+                if judge_log.kind == valuer_api::JudgeLogKind::Full =>
+            {
+                self.full_judge_log = Some(judge_log.clone());
+            }
+            valuer_api::ValuerResponse::Test { .. } => self.running_tests += 1,
+            valuer_api::ValuerResponse::Finish => self.write_judge_log().await?,
+            _ => (),
+        }
+        let mut line = serde_json::to_string(cmd).context("failed to serialize")?;
+        line.push('\n');
+        self.output
+            .write_all(line.as_bytes())
+            .await
+            .context("failed to write")?;
+        self.output.flush().await.context("failed to flush")?;
+        Ok(())
+    }
+
+    async fn poll_notification(&mut self) -> Result<Option<valuer_api::TestDoneNotification>> {
+        // no notification can arrive, so there is nothing to wait for
+        if self.running_tests == 0 {
+            return Ok(None);
+        }
+        let lines = &mut self.lines;
+        let message = match self.deadline {
+            Some(deadline) => {
+                let deadline = tokio::time::Instant::from_std(deadline);
+                match tokio::time::timeout_at(deadline, Self::next_message(lines)).await {
+                    Ok(message) => message?,
+                    // valuer will notice that deadline is reached
+                    Err(_) => return Ok(None),
+                }
+            }
+            None => Self::next_message(lines).await?,
+        };
+        match message {
+            Message::TestDoneNotify(tdn) => {
+                self.running_tests -= 1;
+                Ok(Some(tdn))
+            }
+            Message::ProblemInfo(pi) => bail!("got ProblemInfo {:?} instead", pi),
+        }
+    }
+
+    fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+}
@@ -17,10 +17,33 @@ pub enum GroupRef {
     ById(u32),
 }
 
+/// Determines how group score is computed from its tests scores.
+///
+/// Each test is awarded fraction of its score between 0 and 1 (see
+/// `valuer_api::Status::score_fraction`). Test that did not get full
+/// score is considered failed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupScoring {
+    /// Group score is awarded only if all tests are passed
+    #[default]
+    AllOrNothing,
+    /// Group score is multiplied by minimal test score fraction
+    Min,
+    /// Group score is multiplied by average test score fraction.
+    /// Tests that were not run are counted as zero, so this is
+    /// usually combined with `run_to_first_failure: false`.
+    Sum,
+}
+
 fn default_run_to_first_failure() -> bool {
     true
 }
 
+fn default_open() -> bool {
+    true
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Group {
     /// Group name.
@@ -38,6 +61,21 @@ pub struct Group {
     /// Required groups
     #[serde(default)]
     pub deps: Vec<GroupRef>,
+    /// Group scoring function
+    #[serde(default)]
+    pub scoring: GroupScoring,
+    /// If false, contestant does not see tests verdicts of this group
+    /// and tests are not reported live
+    #[serde(default = "default_open")]
+    pub open: bool,
+    /// Upper bound for group score, applied after aggregation
+    #[serde(default)]
+    pub max_score: Option<u32>,
+    /// Stop running group after first failed test, even if group is
+    /// scored per test. Failed group gets zero score, and remaining tests
+    /// are reported as skipped.
+    #[serde(default)]
+    pub stop_on_fail: bool,
 }
 
 impl Group {
@@ -54,10 +92,121 @@ impl Group {
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
     pub groups: Vec<Group>,
+    /// Determines how live score is computed
+    #[serde(default)]
+    pub live_score: LiveScoreMode,
+    /// Determines how scores are displayed to humans
+    #[serde(default)]
+    pub score_format: ScoreFormat,
+    /// Log every valuing decision (requested tests, received verdicts,
+    /// group results and score changes) at debug level
+    #[serde(default)]
+    pub trace: bool,
+    /// If set, sum of group scores must be equal to this value
+    #[serde(default)]
+    pub total_score: Option<u32>,
+    /// Overall valuing time budget, in seconds. When it is exceeded,
+    /// valuer finishes with score decided so far.
+    /// Can be overridden with `JJS_VALUER_SESSION_TIMEOUT` environment variable.
+    #[serde(default)]
+    pub session_timeout: Option<u64>,
+}
+
+/// Rounding mode, used when score is displayed with limited precision
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    #[default]
+    Floor,
+    Ceil,
+    /// Round half up
+    Nearest,
+}
+
+fn default_scale() -> u32 {
+    1
+}
+
+/// Score display settings.
+///
+/// Only displayed scores are affected: judge logs always contain exact
+/// scores, and solution is considered full regardless of rounding.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScoreFormat {
+    /// How many score units make one point, e.g. 1000 if group scores
+    /// are specified in thousandths of point
+    #[serde(default = "default_scale")]
+    pub scale: u32,
+    /// Decimal places in displayed score, at most `ScoreFormat::MAX_DECIMALS`
+    #[serde(default)]
+    pub decimals: u32,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+impl Default for ScoreFormat {
+    fn default() -> ScoreFormat {
+        ScoreFormat {
+            scale: default_scale(),
+            decimals: 0,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+impl ScoreFormat {
+    /// Largest supported `decimals`: any `u32` score multiplied by
+    /// `10^MAX_DECIMALS` still fits into `u64`
+    pub const MAX_DECIMALS: u32 = 9;
+
+    /// Formats score for displaying
+    ///
+    /// Panics if `decimals` exceeds `MAX_DECIMALS` (such configs are
+    /// rejected by `Config::validate`)
+    pub fn format(&self, score: u32) -> String {
+        let precision = 10u64
+            .checked_pow(self.decimals)
+            .expect(MSG_TOO_MANY_DECIMALS);
+        let num = u64::from(score)
+            .checked_mul(precision)
+            .expect(MSG_TOO_MANY_DECIMALS);
+        let den = u64::from(self.scale.max(1));
+        let (quot, rem) = (num / den, num % den);
+        let value = match self.rounding {
+            Rounding::Floor => quot,
+            Rounding::Ceil => quot + u64::from(rem != 0),
+            Rounding::Nearest => quot + u64::from(2 * rem >= den),
+        };
+        if self.decimals == 0 {
+            return value.to_string();
+        }
+        format!(
+            "{}.{:0width$}",
+            value / precision,
+            value % precision,
+            width = self.decimals as usize
+        )
+    }
+}
+
+/// Determines which score is reported as live score
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LiveScoreMode {
+    /// Only scores of finished groups are counted, so live score
+    /// is lower bound of the final score and never decreases
+    #[default]
+    Guaranteed,
+    /// Unfinished groups contribute share of their score, proportional
+    /// to count of passed tests. Such live score can decrease later.
+    Running,
 }
 
 const MSG_INVALID_GROUP_REF: &str = "GroupRef refers to nonexistent group";
 const MSG_CIRCULAR_REF: &str = "group dependencies have cycle";
+const MSG_SCORE_OVERFLOW: &str = "sum of group scores is too large";
+const MSG_TOO_MANY_DECIMALS: &str = "score-format.decimals is too large";
 
 fn dfs(graph: &[Vec<usize>], used: &mut [u8], has_cycle: &mut bool, v: usize) {
     used[v] = 1;
@@ -106,18 +255,114 @@ impl Config {
         let mut used = vec![0; self.groups.len()];
         for i in 0..self.groups.len() {
             if used[i] == 0 {
-                dfs(&group_dep_graph, &mut used, &mut has_cycle, 0);
+                dfs(&group_dep_graph, &mut used, &mut has_cycle, i);
             }
         }
         if has_cycle {
             error_sink.push(MSG_CIRCULAR_REF.to_string());
         }
+        let total = self
+            .groups
+            .iter()
+            .try_fold(0u32, |total, g| total.checked_add(g.score));
+        match (total, self.total_score) {
+            (None, _) => error_sink.push(MSG_SCORE_OVERFLOW.to_string()),
+            (Some(total), Some(expected)) if total != expected => error_sink.push(format!(
+                "sum of group scores is {}, but total-score is {}",
+                total, expected
+            )),
+            _ => (),
+        }
+        if self.score_format.decimals > ScoreFormat::MAX_DECIMALS {
+            error_sink.push(MSG_TOO_MANY_DECIMALS.to_string());
+        }
     }
+
+    /// Returns indices of groups, which can never start, because some of
+    /// their required groups are invalid, cyclic or can never start too
+    fn unreachable_groups(&self) -> Vec<usize> {
+        let mut runnable = vec![false; self.groups.len()];
+        loop {
+            let mut changed = false;
+            for (i, g) in self.groups.iter().enumerate() {
+                if runnable[i] {
+                    continue;
+                }
+                let deps_ok = g
+                    .deps
+                    .iter()
+                    .all(|dep| self.get_group(dep).map_or(false, |j| runnable[j]));
+                if deps_ok {
+                    runnable[i] = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        (0..self.groups.len()).filter(|&i| !runnable[i]).collect()
+    }
+
+    /// Checks config for mistakes. Errors (e.g. cyclic dependencies) make
+    /// config unusable, warnings point to likely mistakes. If `problem_info`
+    /// is given, groups are additionally checked against problem tests.
+    pub fn lint(&self, problem_info: Option<&valuer_api::ProblemInfo>) -> LintReport {
+        let mut report = LintReport::default();
+        self.validate(&mut report.errors);
+        for i in self.unreachable_groups() {
+            report.warnings.push(format!(
+                "group {} can never run, because its required groups can not pass",
+                self.groups[i].name
+            ));
+        }
+        if let Some(problem_info) = problem_info {
+            for g in &self.groups {
+                if !problem_info.tests.iter().any(|tag| tag == g.tests_tag()) {
+                    report.warnings.push(format!(
+                        "group {} has no tests with tag {}",
+                        g.name,
+                        g.tests_tag()
+                    ));
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Result of `Config::lint`
+#[derive(Debug, Default)]
+pub struct LintReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn score_format() {
+        let fmt = |scale, decimals, rounding, score| {
+            ScoreFormat {
+                scale,
+                decimals,
+                rounding,
+            }
+            .format(score)
+        };
+        assert_eq!(fmt(1, 0, Rounding::Floor, 100), "100");
+        assert_eq!(fmt(1, 2, Rounding::Floor, 7), "7.00");
+        assert_eq!(fmt(1000, 2, Rounding::Floor, 99_999), "99.99");
+        assert_eq!(fmt(1000, 2, Rounding::Ceil, 99_991), "100.00");
+        assert_eq!(fmt(1000, 1, Rounding::Nearest, 12_345), "12.3");
+        assert_eq!(fmt(1000, 0, Rounding::Nearest, 12_500), "13");
+        assert_eq!(
+            fmt(1, ScoreFormat::MAX_DECIMALS, Rounding::Nearest, u32::MAX),
+            "4294967295.000000000"
+        );
+    }
     mod validate {
         use super::*;
 
@@ -176,6 +421,97 @@ groups:
             );
         }
 
+        #[test]
+        fn test_score_overflow() {
+            check_errs(
+                "
+groups:
+  - name: foo
+    feedback: full
+    score: 4000000000
+  - name: bar
+    feedback: full
+    score: 4000000000
+            ",
+                &[MSG_SCORE_OVERFLOW],
+            );
+        }
+
+        #[test]
+        fn test_total_score_mismatch() {
+            check_errs(
+                "
+total-score: 100
+groups:
+  - name: foo
+    feedback: full
+    score: 30
+  - name: bar
+    feedback: full
+    score: 60
+            ",
+                &["sum of group scores is 90, but total-score is 100"],
+            );
+        }
+
+        #[test]
+        fn test_too_many_decimals() {
+            check_errs(
+                "
+score-format:
+  decimals: 10
+groups:
+  - name: foo
+    feedback: full
+    score: 100
+            ",
+                &[MSG_TOO_MANY_DECIMALS],
+            );
+        }
+
+        #[test]
+        fn lint_unreachable() {
+            let cfg: Config = serde_yaml::from_str(
+                "
+groups:
+  - name: samples
+    feedback: full
+    score: 0
+  - name: foo
+    feedback: full
+    score: 50
+    deps:
+      - bar
+  - name: bar
+    feedback: full
+    score: 30
+    deps:
+      - foo
+  - name: baz
+    feedback: full
+    score: 20
+    deps:
+      - bar
+            ",
+            )
+            .unwrap();
+            let problem_info = valuer_api::ProblemInfo {
+                tests: vec!["foo".to_string(), "bar".to_string(), "baz".to_string()],
+                valuer_version: None,
+            };
+            let report = cfg.lint(Some(&problem_info));
+            assert_eq!(report.errors, [MSG_CIRCULAR_REF]);
+            assert_eq!(
+                report.warnings,
+                [
+                    "group foo can never run, because its required groups can not pass",
+                    "group bar can never run, because its required groups can not pass",
+                    "group baz can never run, because its required groups can not pass",
+                    "group samples has no tests with tag samples",
+                ]
+            );
+        }
+
         #[test]
         fn test_circular_ref() {
             check_errs(
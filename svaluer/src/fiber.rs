@@ -1,10 +1,13 @@
 mod group;
 
-use crate::cfg::Config;
+use crate::cfg::{Config, LiveScoreMode};
 use group::Group;
 use log::{debug, info};
 use pom::TestId;
-use std::{collections::HashSet, num::NonZeroU32};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+};
 use valuer_api::{
     JudgeLog, JudgeLogKind, ProblemInfo, Status, SubtaskVisibleComponents, TestVisibleComponents,
 };
@@ -15,11 +18,20 @@ pub(crate) struct Fiber {
     kind: JudgeLogKind,
     /// If test is not in this set, it will not be included into judge log.
     visible_tests: HashSet<TestId>,
+    /// Tests from open groups
+    open_tests: HashSet<TestId>,
     // contains indices for `groups`
     active_groups: Vec<usize>,
     groups: Vec<Group>,
     finished: bool,
     last_live_score: u32,
+    live_score_mode: LiveScoreMode,
+    /// Names of `groups`, used in trace
+    group_names: Vec<String>,
+    /// See `Config::trace`
+    trace: bool,
+    /// Checker messages of finished tests
+    checker_comments: HashMap<TestId, String>,
 }
 
 // TODO: consider unifying with ValuerResponse
@@ -73,7 +85,9 @@ impl Fiber {
     pub(crate) fn new(cfg: &Config, problem_info: &ProblemInfo, kind: JudgeLogKind) -> Fiber {
         let mut groups = Vec::new();
         let mut visible_tests = HashSet::new();
+        let mut open_tests = HashSet::new();
         let mut skipped_groups = HashSet::new();
+        let mut group_names = Vec::new();
         for (i, group_cfg) in cfg.groups.iter().enumerate() {
             let vis_preset = match group_cfg.feedback {
                 crate::cfg::FeedbackKind::Brief => GroupVisPreset::Brief,
@@ -93,11 +107,20 @@ impl Fiber {
                 }
             }
             visible_tests.extend(tests.iter().map(|test_id| pom::TestId::make(*test_id)));
+            if group_cfg.open {
+                open_tests.extend(tests.iter().map(|test_id| pom::TestId::make(*test_id)));
+            }
             grp.add_tests(tests);
 
-            grp.set_tests_vis(vis_preset.test_flags_for(kind))
+            let mut test_flags = vis_preset.test_flags_for(kind);
+            if !group_cfg.open && kind == JudgeLogKind::Contestant {
+                test_flags.remove(TestVisibleComponents::STATUS);
+            }
+            grp.set_tests_vis(test_flags)
                 .set_group_vis(vis_preset.subtask_flags_for(kind));
-            grp.set_score(group_cfg.score);
+            grp.set_score(group_cfg.score)
+                .set_scoring(group_cfg.scoring)
+                .set_max_score(group_cfg.max_score);
             for dep in &group_cfg.deps {
                 let group_id = cfg.get_group(dep).expect("invalid config");
                 if skipped_groups.contains(&group_id) {
@@ -108,18 +131,40 @@ impl Fiber {
             if !group_cfg.run_to_first_failure {
                 grp.set_run_all_tests();
             }
+            if group_cfg.stop_on_fail {
+                grp.set_stop_on_fail();
+            }
 
             grp.freeze();
 
             groups.push(grp);
+            group_names.push(group_cfg.name.clone());
         }
         Fiber {
             kind,
             visible_tests,
+            open_tests,
             active_groups: (0..groups.len()).collect(),
             finished: false,
             groups,
             last_live_score: 0,
+            live_score_mode: cfg.live_score,
+            group_names,
+            trace: cfg.trace,
+            checker_comments: HashMap::new(),
+        }
+    }
+
+    /// Logs decision about group `i`, if tracing is enabled
+    fn trace_group(&self, i: usize, delta: u32, reason: &str) {
+        if self.trace {
+            tracing::debug!(
+                kind = self.kind.as_str(),
+                group = %self.group_names[i],
+                delta,
+                reason,
+                "trace: group finished"
+            );
         }
     }
 
@@ -130,6 +175,10 @@ impl Fiber {
         if self.finished {
             panic!("Fiber is finished, but got notification {:?}", notification);
         }
+        if let Some(comment) = &notification.checker_comment {
+            self.checker_comments
+                .insert(notification.test_id, comment.clone());
+        }
         self.add_test(notification.test_id, &notification.test_status);
     }
 
@@ -139,22 +188,49 @@ impl Fiber {
 
     fn emit_judgelog(&mut self) -> FiberReply {
         debug!("Emitting {:?} judge log", self.kind);
-        let is_full = self.groups.iter().all(|g| g.is_passed());
+        let is_full = self.groups.iter().all(|g| g.is_full());
         let mut judge_log = JudgeLog {
             kind: self.kind,
             tests: vec![],
             subtasks: vec![],
             is_full,
             score: 0,
+            guaranteed_score: 0,
         };
         for (i, g) in self.groups.iter().enumerate() {
             debug!("extending judge log with group {}", i);
-            g.update_judge_log(&mut judge_log);
+            g.update_judge_log(&mut judge_log, &self.checker_comments);
         }
+        judge_log.guaranteed_score = self.guaranteed_score();
 
         FiberReply::Finish(judge_log)
     }
 
+    /// Finishes fiber immediately, emitting judge log with groups decided
+    /// so far. In `LiveScoreMode::Running` unfinished groups contribute
+    /// their running score to `score`, but never to `guaranteed_score`.
+    /// Returns `None` if judge log was already emitted.
+    pub(crate) fn force_finish(&mut self) -> Option<JudgeLog> {
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        let mut judge_log = JudgeLog {
+            kind: self.kind,
+            tests: vec![],
+            subtasks: vec![],
+            is_full: false,
+            score: 0,
+            guaranteed_score: 0,
+        };
+        let partial = self.live_score_mode == LiveScoreMode::Running;
+        for g in &self.groups {
+            g.update_judge_log_unfinished(&mut judge_log, &self.checker_comments, partial);
+        }
+        judge_log.guaranteed_score = self.guaranteed_score();
+        Some(judge_log)
+    }
+
     fn poll_groups_for_tests(&mut self) -> Option<TestId> {
         for &i in &self.active_groups {
             let g = &mut self.groups[i];
@@ -168,11 +244,26 @@ impl Fiber {
     }
 
     pub(crate) fn test_is_live(&self, test: TestId) -> bool {
-        self.kind == JudgeLogKind::Contestant && self.visible_tests.contains(&test)
+        self.kind == JudgeLogKind::Contestant
+            && self.visible_tests.contains(&test)
+            && self.open_tests.contains(&test)
+    }
+
+    /// Returns total score of fully decided groups
+    fn guaranteed_score(&self) -> u32 {
+        self.groups
+            .iter()
+            .fold(0, |total: u32, g| total.saturating_add(g.score()))
     }
 
     fn current_score(&self) -> u32 {
-        self.groups.iter().map(|g| g.score()).sum()
+        match self.live_score_mode {
+            LiveScoreMode::Guaranteed => self.guaranteed_score(),
+            LiveScoreMode::Running => self
+                .groups
+                .iter()
+                .fold(0, |total: u32, g| total.saturating_add(g.running_score())),
+        }
     }
 
     fn running_tests(&self) -> u32 {
@@ -188,6 +279,15 @@ impl Fiber {
         let cur_live_score = self.current_score();
         debug!("live score: {}", cur_live_score);
         if cur_live_score != self.last_live_score {
+            if self.trace {
+                tracing::debug!(
+                    kind = self.kind.as_str(),
+                    delta = i64::from(cur_live_score) - i64::from(self.last_live_score),
+                    score = cur_live_score,
+                    reason = "live score changed",
+                    "trace: score updated"
+                );
+            }
             info!(
                 "live score updated: old={}, cur={}",
                 self.last_live_score, cur_live_score
@@ -210,10 +310,12 @@ impl Fiber {
             assert!(!(is_passed && is_failed));
             if g.is_passed() {
                 debug!("group {} is passed", i);
+                self.trace_group(i, g.score(), "all tests passed");
                 for group in &mut self.groups {
                     group.on_group_pass(i as u32);
                 }
             } else if g.is_failed() {
+                self.trace_group(i, g.score(), "some test failed");
                 let mut queue = vec![i as u32];
                 while let Some(k) = queue.pop() {
                     debug!("group {} is failed", k);
@@ -223,6 +325,15 @@ impl Fiber {
                         }
                         group.on_group_fail(k as u32);
                         if group.is_skipped() {
+                            if self.trace {
+                                tracing::debug!(
+                                    kind = self.kind.as_str(),
+                                    group = %self.group_names[j],
+                                    delta = 0,
+                                    reason = "required group failed",
+                                    "trace: group skipped"
+                                );
+                            }
                             queue.push(j as u32);
                         }
                     }
@@ -283,6 +394,7 @@ mod tests {
             &serde_yaml::from_str(cfg).unwrap(),
             &ProblemInfo {
                 tests: problem_info.iter().map(ToString::to_string).collect(),
+                valuer_version: None,
             },
             kind,
         )
@@ -365,17 +477,20 @@ groups:
                 JudgeLogSubtaskRow {
                     subtask_id: SubtaskId::make(1),
                     score: 0,
-                    components: SubtaskVisibleComponents::all()
+                    components: SubtaskVisibleComponents::all(),
+                    comment: String::new()
                 },
                 JudgeLogSubtaskRow {
                     subtask_id: SubtaskId::make(2),
                     score: 60,
-                    components: SubtaskVisibleComponents::all()
+                    components: SubtaskVisibleComponents::all(),
+                    comment: String::new()
                 },
                 JudgeLogSubtaskRow {
                     subtask_id: SubtaskId::make(3),
                     score: 0,
-                    components: SubtaskVisibleComponents::all()
+                    components: SubtaskVisibleComponents::all(),
+                    comment: "failed on test 3 (NOT_OK)".to_string()
                 }
             ]
         );
@@ -385,9 +500,51 @@ groups:
                 is_full: false,
                 kind: JudgeLogKind::Full,
                 score: 60,
+                guaranteed_score: 60,
                 tests: vec![],
                 subtasks: vec![]
             }
         );
     }
+
+    #[test]
+    fn force_finish_running() {
+        let mut f = make_fiber(
+            "
+live-score: running
+groups:
+  - name: a
+    feedback: full
+    score: 30
+  - name: b
+    feedback: full
+    score: 60
+    run_to_first_failure: false
+        ",
+            &["a", "b", "b"],
+            JudgeLogKind::Full,
+        );
+        let mut requested = Vec::new();
+        while let FiberReply::Test { test_id } = f.poll() {
+            requested.push(test_id);
+        }
+        assert_eq!(
+            requested,
+            vec![TestId::make(1), TestId::make(2), TestId::make(3)]
+        );
+        f.add_test(TestId::make(1), &crate::status_util::make_ok_status());
+        f.add_test(TestId::make(2), &crate::status_util::make_ok_status());
+        assert_eq!(f.poll(), FiberReply::LiveScore { score: 60 });
+        let judge_log = f.force_finish().unwrap();
+        assert_eq!(judge_log.score, 60);
+        assert_eq!(judge_log.guaranteed_score, 30);
+        assert_eq!(
+            judge_log
+                .subtasks
+                .iter()
+                .map(|row| row.score)
+                .collect::<Vec<_>>(),
+            vec![30, 30]
+        );
+    }
 }
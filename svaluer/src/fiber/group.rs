@@ -1,10 +1,11 @@
+use crate::cfg::GroupScoring;
 use either::{Left, Right};
 use log::debug;
 use pom::TestId;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use valuer_api::{
     JudgeLog, JudgeLogSubtaskRow, JudgeLogTestRow, Status, SubtaskId, SubtaskVisibleComponents,
-    TestVisibleComponents,
+    TestVisibleComponents, FULL_SCORE_FRACTION,
 };
 
 #[derive(Debug)]
@@ -29,7 +30,11 @@ struct SkippedState {
 struct FinishedState {
     score: u32,
     success: bool,
+    /// True if score was reduced by `max_score`
+    capped: bool,
     tests: Vec<(TestId, Status)>,
+    /// Tests, which were not run because of `stop_on_fail`
+    not_run: Vec<TestId>,
 }
 
 #[derive(Debug)]
@@ -51,6 +56,9 @@ pub(crate) struct Group {
     state: State,
     tests: Vec<TestId>,
     score: u32,
+    scoring: GroupScoring,
+    max_score: Option<u32>,
+    stop_on_fail: bool,
 }
 
 impl Group {
@@ -64,6 +72,9 @@ impl Group {
             state: State::Building,
             tests: Vec::new(),
             score: 0,
+            scoring: GroupScoring::AllOrNothing,
+            max_score: None,
+            stop_on_fail: false,
         }
     }
 
@@ -95,6 +106,18 @@ impl Group {
         self
     }
 
+    pub(crate) fn set_scoring(&mut self, scoring: GroupScoring) -> &mut Self {
+        self.check_mutable();
+        self.scoring = scoring;
+        self
+    }
+
+    pub(crate) fn set_max_score(&mut self, max_score: Option<u32>) -> &mut Self {
+        self.check_mutable();
+        self.max_score = max_score;
+        self
+    }
+
     pub(crate) fn set_tests_vis(
         &mut self,
         vis: TestVisibleComponents,
@@ -113,6 +136,12 @@ impl Group {
         self
     }
 
+    pub(crate) fn set_stop_on_fail(&mut self) -> &mut Self {
+        self.check_mutable();
+        self.stop_on_fail = true;
+        self
+    }
+
     pub(crate) fn set_run_all_tests(&mut self) -> &mut Self {
         self.check_mutable();
         self.run_all_tests = true;
@@ -139,6 +168,14 @@ impl Group {
         self.finished() == Some(true)
     }
 
+    /// Returns true if group is passed and got its full score
+    pub(crate) fn is_full(&self) -> bool {
+        match &self.state {
+            State::Finished(state) => state.success && !state.capped,
+            _ => false,
+        }
+    }
+
     pub(crate) fn is_failed(&self) -> bool {
         self.finished() == Some(false)
     }
@@ -247,12 +284,16 @@ impl Group {
     fn mark_test_fail(&mut self, test_id: TestId, status: Status) {
         let id = self.id;
         let must_run_all_tests = self.run_all_tests;
+        // partially scored test still contributes to group score,
+        // unless group is scored as a whole
+        let can_stop = self.scoring == GroupScoring::AllOrNothing || status.score_fraction() == 0;
+        let stop_on_fail = self.stop_on_fail;
         let state = self.running_state();
         if state.failed_tests.is_empty() {
             debug!("group {:?} is now failed", id);
         }
         state.failed_tests.insert((test_id, status));
-        if !must_run_all_tests {
+        if stop_on_fail || (!must_run_all_tests && can_stop) {
             // no other tests should be run
             state.queued_tests.clear();
         }
@@ -270,15 +311,71 @@ impl Group {
             let success = state.failed_tests.is_empty();
             let failed_tests = std::mem::take(&mut state.failed_tests);
             let succeeded_tests = std::mem::take(&mut state.succeeded_tests);
-            let score = if success { self.score } else { 0 };
+            let tests: Vec<_> = failed_tests.into_iter().chain(succeeded_tests).collect();
+            let not_run = if self.stop_on_fail {
+                self.tests
+                    .iter()
+                    .copied()
+                    .filter(|test_id| tests.iter().all(|(t, _)| t != test_id))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let score = if self.stop_on_fail && !success {
+                0
+            } else {
+                self.aggregate_score(success, &tests)
+            };
+            let capped = self.max_score.map_or(false, |max_score| score > max_score);
+            let score = match self.max_score {
+                Some(max_score) => score.min(max_score),
+                None => score,
+            };
             self.state = State::Finished(FinishedState {
                 score,
                 success,
-                tests: failed_tests.into_iter().chain(succeeded_tests).collect(),
+                capped,
+                tests,
+                not_run,
             })
         }
     }
 
+    /// Computes group score from finished tests, according to `self.scoring`
+    fn aggregate_score(&self, success: bool, tests: &[(TestId, Status)]) -> u32 {
+        let full = u64::from(FULL_SCORE_FRACTION);
+        let max_score = u64::from(self.score);
+        let score = match self.scoring {
+            GroupScoring::AllOrNothing => {
+                if success {
+                    max_score
+                } else {
+                    0
+                }
+            }
+            GroupScoring::Min => {
+                let min_fraction = tests
+                    .iter()
+                    .map(|(_, status)| u64::from(status.score_fraction()))
+                    .min()
+                    .unwrap_or(full);
+                max_score * min_fraction / full
+            }
+            GroupScoring::Sum => {
+                if self.tests.is_empty() {
+                    max_score
+                } else {
+                    let total_fraction: u64 = tests
+                        .iter()
+                        .map(|(_, status)| u64::from(status.score_fraction()))
+                        .sum();
+                    max_score * total_fraction / (full * self.tests.len() as u64)
+                }
+            }
+        };
+        score as u32
+    }
+
     pub(crate) fn on_test_done(&mut self, test_id: TestId, status: Status) {
         let state = match &mut self.state {
             State::Running(state) => state,
@@ -292,7 +389,7 @@ impl Group {
             test_id.get(),
             status
         );
-        if status.kind.is_success() {
+        if status.score_fraction() == FULL_SCORE_FRACTION {
             self.mark_test_ok(test_id, status);
         } else {
             self.mark_test_fail(test_id, status);
@@ -300,7 +397,13 @@ impl Group {
         self.maybe_finish();
     }
 
-    pub(crate) fn update_judge_log(&self, log: &mut JudgeLog) {
+    /// Appends results of this group to `log`.
+    /// `checker_comments` contains checker messages of finished tests.
+    pub(crate) fn update_judge_log(
+        &self,
+        log: &mut JudgeLog,
+        checker_comments: &HashMap<TestId, String>,
+    ) {
         let state = match &self.state {
             State::Finished(state) => state,
             State::Skipped(_) => {
@@ -308,17 +411,21 @@ impl Group {
                     components: self.subtask_vis_flags,
                     score: 0,
                     subtask_id: self.id,
+                    comment: "skipped because required group failed".to_string(),
                 });
                 return;
             }
             other => panic!("update_judge_log: unexpected state {:?}", other),
         };
         let self_score = self.score();
-        log.score += self_score;
+        // config validation guarantees that sum of scores fits, but
+        // judge log must not wrap even if valuer is misused
+        log.score = log.score.saturating_add(self_score);
         let subtask_entry = JudgeLogSubtaskRow {
             components: self.subtask_vis_flags,
             score: self_score,
             subtask_id: self.id,
+            comment: self.make_comment(state, checker_comments),
         };
         log.subtasks.push(subtask_entry);
         for (test, status) in &state.tests {
@@ -329,13 +436,103 @@ impl Group {
             };
             log.tests.push(row);
         }
+        for test in &state.not_run {
+            log.tests.push(JudgeLogTestRow {
+                components: self.test_vis_flags,
+                test_id: *test,
+                status: crate::status_util::make_status(valuer_api::StatusKind::Skipped),
+            });
+        }
+    }
+
+    /// Same as `update_judge_log`, but also accepts group that is not
+    /// finished yet (e.g. when valuing session timed out). Such group
+    /// is reported with its `running_score` if `partial` is set, and
+    /// with zero score otherwise.
+    pub(crate) fn update_judge_log_unfinished(
+        &self,
+        log: &mut JudgeLog,
+        checker_comments: &HashMap<TestId, String>,
+        partial: bool,
+    ) {
+        let state = match &self.state {
+            State::Finished(_) | State::Skipped(_) => {
+                return self.update_judge_log(log, checker_comments);
+            }
+            State::Running(state) => Some(state),
+            State::Building | State::Waiting(_) => None,
+        };
+        let self_score = if partial { self.running_score() } else { 0 };
+        log.score = log.score.saturating_add(self_score);
+        log.subtasks.push(JudgeLogSubtaskRow {
+            components: self.subtask_vis_flags,
+            score: self_score,
+            subtask_id: self.id,
+            comment: "not finished: valuing session timed out".to_string(),
+        });
+        if let Some(state) = state {
+            for (test, status) in state.succeeded_tests.iter().chain(&state.failed_tests) {
+                log.tests.push(JudgeLogTestRow {
+                    components: self.test_vis_flags,
+                    test_id: *test,
+                    status: status.clone(),
+                });
+            }
+        }
+    }
+
+    /// Describes first failed test (including its checker message), if any
+    fn make_comment(
+        &self,
+        state: &FinishedState,
+        checker_comments: &HashMap<TestId, String>,
+    ) -> String {
+        let first_failed = state
+            .tests
+            .iter()
+            .filter(|(_, status)| status.score_fraction() != FULL_SCORE_FRACTION)
+            .min_by_key(|(test_id, _)| *test_id);
+        match first_failed {
+            None => String::new(),
+            Some((test_id, status)) => {
+                if self.test_vis_flags.contains(TestVisibleComponents::STATUS) {
+                    match checker_comments.get(test_id) {
+                        Some(comment) => format!(
+                            "failed on test {} ({}): {}",
+                            test_id.get(),
+                            status.code,
+                            comment.trim()
+                        ),
+                        None => format!("failed on test {} ({})", test_id.get(), status.code),
+                    }
+                } else {
+                    "failed".to_string()
+                }
+            }
+        }
     }
 
     pub(crate) fn score(&self) -> u32 {
-        if self.is_passed() {
-            self.score
-        } else {
-            0
+        match &self.state {
+            State::Finished(state) => state.score,
+            _ => 0,
+        }
+    }
+
+    /// Like `score`, but unfinished group returns share of its score,
+    /// proportional to passed tests count
+    pub(crate) fn running_score(&self) -> u32 {
+        match &self.state {
+            State::Running(state) if !self.tests.is_empty() => {
+                let passed = state.succeeded_tests.len() as u64;
+                let score = u64::from(self.score) * passed / self.tests.len() as u64;
+                let score = score as u32;
+                match self.max_score {
+                    Some(max_score) => score.min(max_score),
+                    None => score,
+                }
+            }
+            _ => self.score(),
         }
     }
 }
@@ -350,6 +547,7 @@ mod tests {
         let st = || Status {
             kind: StatusKind::Accepted,
             code: "MOCK_OK".to_string(),
+            partial_score: None,
         };
         let mut g = Group::new();
         g.add_tests(1..=3);
@@ -4,17 +4,60 @@
 #[cfg(test)]
 mod tests;
 
+pub mod async_driver;
 pub mod cfg;
 mod fiber;
 
+pub use async_driver::AsyncJsonDriver;
 pub use cfg::Config;
 
 use anyhow::{Context, Result};
 use fiber::{Fiber, FiberReply};
-use valuer_api::{JudgeLogKind, ProblemInfo, TestDoneNotification, ValuerResponse};
+use valuer_api::{
+    JudgeLog, JudgeLogKind, ProblemInfo, Status, TestDoneNotification, ValuerResponse,
+};
 use log::debug;
 use pom::TestId;
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Valuer version, which is recorded into compiled problem packages
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Returns `(major, minor)` components of version string
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Checks that package, built with valuer of given version, can be
+/// valued by this valuer. Versions are compatible if major components
+/// are equal (for 0.x versions minor components must be equal too).
+pub fn check_version_compatible(version: &str) -> Result<()> {
+    let (major, minor) = match parse_version(version) {
+        Some(v) => v,
+        None => anyhow::bail!("invalid valuer version `{}`", version),
+    };
+    let (self_major, self_minor) = parse_version(VERSION).expect("VERSION is valid");
+    let compatible = if self_major == 0 {
+        major == 0 && minor == self_minor
+    } else {
+        major == self_major
+    };
+    if !compatible {
+        anyhow::bail!(
+            "problem package requires valuer {}, which is incompatible with {}",
+            version,
+            VERSION
+        );
+    }
+    Ok(())
+}
+
 /// SValuer is pure. Only `ValuerDriver` actually performs some IO, interacting with environment, such as JJS invoker.
 pub trait ValuerDriver: std::fmt::Debug {
     /// Retrieves `ProblemInfo`. Will be called once.
@@ -23,12 +66,41 @@ pub trait ValuerDriver: std::fmt::Debug {
     fn send_command(&mut self, cmd: &ValuerResponse) -> Result<()>;
     /// Polls notification about test finish
     fn poll_notification(&mut self) -> Result<Option<TestDoneNotification>>;
+    /// Informs driver that valuing will be stopped at `deadline`, so
+    /// `poll_notification` should not block past it
+    fn set_deadline(&mut self, _deadline: Instant) {}
 }
 
-/// SValuer itself
+/// Same as `ValuerDriver`, but performs IO asynchronously, so that
+/// valuer can run on runtime of async host (see `AsyncSimpleValuer`)
+#[async_trait::async_trait]
+pub trait AsyncValuerDriver: std::fmt::Debug + Send {
+    /// Retrieves `ProblemInfo`. Will be called once.
+    async fn problem_info(&mut self) -> Result<ProblemInfo>;
+    /// Sends valuer response
+    async fn send_command(&mut self, cmd: &ValuerResponse) -> Result<()>;
+    /// Waits for notification about test finish. Returns `None` if
+    /// there is nothing to wait for, or deadline is reached.
+    async fn poll_notification(&mut self) -> Result<Option<TestDoneNotification>>;
+    /// Informs driver that valuing will be stopped at `deadline`, so
+    /// `poll_notification` should not wait past it
+    fn set_deadline(&mut self, _deadline: Instant) {}
+}
+
+/// Result of polling fibers
+enum FibersPoll {
+    /// Response must be sent to driver
+    Send(ValuerResponse),
+    /// Some fiber made progress, but there is nothing to send
+    Progress,
+    /// Fibers wait for test results (or all of them are finished)
+    Idle,
+}
+
+/// Valuing state, shared by sync and async valuers. It does not perform
+/// any IO.
 #[derive(Debug)]
-pub struct SimpleValuer<'a> {
-    driver: &'a mut dyn ValuerDriver,
+struct ValuerState {
     /// Amount of tests that are currently running.
     running_tests: u32,
     /// How many fibers did not emit judge log yet
@@ -37,65 +109,76 @@ pub struct SimpleValuer<'a> {
     /// It is used for caching purposes.
     used_tests: HashSet<TestId>,
     fibers: Vec<Fiber>,
+    /// Full judge log, when it is emitted
+    full_judge_log: Option<JudgeLog>,
+    /// See `cfg::Config::trace`
+    trace: bool,
+    /// See `cfg::Config::session_timeout`
+    session_timeout: Option<Duration>,
 }
 
-impl<'a> SimpleValuer<'a> {
-    pub fn new(
-        driver: &'a mut dyn ValuerDriver,
-        cfg: &'a cfg::Config,
-    ) -> anyhow::Result<SimpleValuer<'a>> {
-        let problem_info = driver
-            .problem_info()
-            .context("failed to query problem info")?;
+impl ValuerState {
+    fn new(cfg: &cfg::Config, problem_info: &ProblemInfo) -> anyhow::Result<ValuerState> {
+        if let Some(version) = &problem_info.valuer_version {
+            check_version_compatible(version)?;
+        }
+        let mut errors = Vec::new();
+        cfg.validate(&mut errors);
+        if !errors.is_empty() {
+            anyhow::bail!("invalid valuer config: {}", errors.join("; "));
+        }
         let mut fibers = Vec::new();
 
-        fibers.push(Fiber::new(cfg, &problem_info, JudgeLogKind::Full));
-        fibers.push(Fiber::new(cfg, &problem_info, JudgeLogKind::Contestant));
+        fibers.push(Fiber::new(cfg, problem_info, JudgeLogKind::Full));
+        fibers.push(Fiber::new(cfg, problem_info, JudgeLogKind::Contestant));
 
         let fibers_cnt = fibers.len();
-        Ok(SimpleValuer {
-            driver,
+        Ok(ValuerState {
             running_tests: 0,
             used_tests: HashSet::new(),
             fibers,
             running_fibers: fibers_cnt,
+            full_judge_log: None,
+            trace: cfg.trace,
+            session_timeout: cfg.session_timeout.map(Duration::from_secs),
         })
     }
 
+    /// Returns instant, when valuing session times out
+    fn deadline(&self) -> Option<Instant> {
+        self.session_timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Returns false if valuing is finished
+    fn is_running(&self) -> bool {
+        self.running_tests != 0 || self.running_fibers != 0
+    }
+
     /// Creates ValuerResponse for executing test `test_id`.
-    /// Returns early if this test was already requested.
-    fn send_run_on_test_query(&mut self, test_id: TestId, live: bool) -> anyhow::Result<()> {
+    /// Returns `None` if this test was already requested.
+    fn run_on_test_query(&mut self, test_id: TestId, live: bool) -> Option<ValuerResponse> {
         if !self.used_tests.insert(test_id) {
-            return Ok(());
+            return None;
+        }
+        if self.trace {
+            tracing::debug!(test_id = test_id.get(), live, "trace: test requested");
         }
-        let cmd = ValuerResponse::Test { test_id, live };
         self.running_tests += 1;
-
-        self.driver
-            .send_command(&cmd)
-            .context("failed to send TEST command")?;
-        Ok(())
+        Some(ValuerResponse::Test { test_id, live })
     }
 
-    /// Executes one iteration.
-    /// Returns false when valuing finishes.
-    fn step(&mut self) -> anyhow::Result<bool> {
-        debug!("Running next step");
-
+    /// Checks, if fibers have something new
+    fn poll_fibers(&mut self) -> FibersPoll {
         debug!("Polling fibers");
-        // do we have something new from fibers?
-        for fiber in &mut self.fibers {
+        for i in 0..self.fibers.len() {
+            let fiber = &mut self.fibers[i];
             let reply = fiber.poll();
             debug!("Polling fiber {:?}: {:?}", fiber.kind(), &reply);
             match reply {
                 FiberReply::LiveScore { score } => {
                     if fiber.kind() == JudgeLogKind::Contestant {
                         debug!("Step done: sending live score");
-                        let live_score = ValuerResponse::LiveScore { score };
-                        self.driver
-                            .send_command(&live_score)
-                            .context("failed to send new live score")?;
-                        return Ok(true);
+                        return FibersPoll::Send(ValuerResponse::LiveScore { score });
                     } else {
                         debug!("Ignoring live score: kind mismatch");
                     }
@@ -106,17 +189,18 @@ impl<'a> SimpleValuer<'a> {
                         "Step done: test execution requested (test id {}, live: {})",
                         test_id, is_live
                     );
-                    self.send_run_on_test_query(test_id, is_live)?;
-                    return Ok(true);
+                    return match self.run_on_test_query(test_id, is_live) {
+                        Some(query) => FibersPoll::Send(query),
+                        None => FibersPoll::Progress,
+                    };
                 }
                 FiberReply::Finish(judge_log) => {
                     debug!("Step done: new judge log {:?} emitted", judge_log.kind);
-                    let resp = ValuerResponse::JudgeLog(judge_log);
+                    if judge_log.kind == JudgeLogKind::Full {
+                        self.full_judge_log = Some(judge_log.clone());
+                    }
                     self.running_fibers -= 1;
-                    self.driver
-                        .send_command(&resp)
-                        .context("failed to submit judge log")?;
-                    return Ok(true);
+                    return FibersPoll::Send(ValuerResponse::JudgeLog(judge_log));
                 }
                 FiberReply::None => {
                     debug!("No updates from this fiber");
@@ -124,63 +208,328 @@ impl<'a> SimpleValuer<'a> {
                 }
             }
         }
+        FibersPoll::Idle
+    }
+
+    /// Returns judge logs of all fibers which are not finished yet
+    fn finish_timed_out(&mut self) -> Vec<ValuerResponse> {
+        tracing::warn!(
+            running_tests = self.running_tests,
+            "valuing session timed out, finishing with current score"
+        );
+        let mut responses = Vec::new();
+        for fiber in &mut self.fibers {
+            if let Some(judge_log) = fiber.force_finish() {
+                if judge_log.kind == JudgeLogKind::Full {
+                    self.full_judge_log = Some(judge_log.clone());
+                }
+                self.running_fibers -= 1;
+                responses.push(ValuerResponse::JudgeLog(judge_log));
+            }
+        }
+        responses
+    }
+
+    fn process_notification(&mut self, notification: TestDoneNotification) {
+        debug!("Step done: got notification");
+        assert_ne!(self.running_tests, 0);
+        self.running_tests -= 1;
+        if self.trace {
+            tracing::debug!(
+                test_id = notification.test_id.get(),
+                status = %notification.test_status.code,
+                score_fraction = notification.test_status.score_fraction(),
+                "trace: test finished"
+            );
+        }
+        for fiber in self.fibers.iter_mut() {
+            fiber.add(&notification);
+        }
+    }
+
+    /// Returns full judge log, emitted during valuing
+    fn into_judge_log(self) -> anyhow::Result<JudgeLog> {
+        self.full_judge_log
+            .context("valuing finished, but full judge log was not emitted")
+    }
+}
+
+/// SValuer itself
+#[derive(Debug)]
+pub struct SimpleValuer<'a> {
+    driver: &'a mut dyn ValuerDriver,
+    state: ValuerState,
+}
+
+impl<'a> SimpleValuer<'a> {
+    pub fn new(
+        driver: &'a mut dyn ValuerDriver,
+        cfg: &'a cfg::Config,
+    ) -> anyhow::Result<SimpleValuer<'a>> {
+        let problem_info = driver
+            .problem_info()
+            .context("failed to query problem info")?;
+        let state = ValuerState::new(cfg, &problem_info)?;
+        Ok(SimpleValuer { driver, state })
+    }
+
+    /// Executes one iteration.
+    /// Returns false when valuing finishes.
+    fn step(&mut self) -> anyhow::Result<bool> {
+        debug!("Running next step");
+        match self.state.poll_fibers() {
+            FibersPoll::Send(response) => {
+                self.driver
+                    .send_command(&response)
+                    .context("failed to send valuer response")?;
+                return Ok(true);
+            }
+            FibersPoll::Progress => return Ok(true),
+            FibersPoll::Idle => {}
+        }
         // do we have pending notifications?
         if let Some(notification) = self
             .driver
             .poll_notification()
             .context("failed to poll for notification")?
         {
-            debug!("Step done: got notification");
-            self.process_notification(notification);
+            self.state.process_notification(notification);
             return Ok(true);
         }
+        // do we have running tests or fibers?
+        Ok(self.state.is_running())
+    }
 
-        // do we have running tests?
-        if self.running_tests != 0 {
-            debug!("Step done: waiting for running tests completion");
-            return Ok(true);
+    /// Runs to valuing completion
+    pub fn exec(self) -> anyhow::Result<()> {
+        self.exec_with_log().map(drop)
+    }
+
+    /// Runs to valuing completion and returns full judge log.
+    /// Judge logs are still sent to the driver.
+    pub fn exec_with_log(mut self) -> anyhow::Result<JudgeLog> {
+        let deadline = self.state.deadline();
+        if let Some(deadline) = deadline {
+            self.driver.set_deadline(deadline);
         }
-        if self.running_fibers != 0 {
-            debug!("Step done: waiting for running fibers completion");
-            return Ok(true);
+        loop {
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                for response in self.state.finish_timed_out() {
+                    self.driver
+                        .send_command(&response)
+                        .context("failed to submit judge log")?;
+                }
+                break;
+            }
+            let should_run = self.step()?;
+            if !should_run {
+                break;
+            }
         }
+        self.driver.send_command(&ValuerResponse::Finish)?;
+        self.state.into_judge_log()
+    }
+}
+
+/// Same as `SimpleValuer`, but drives `AsyncValuerDriver` on async
+/// runtime of caller
+#[derive(Debug)]
+pub struct AsyncSimpleValuer<'a> {
+    driver: &'a mut dyn AsyncValuerDriver,
+    state: ValuerState,
+}
+
+impl<'a> AsyncSimpleValuer<'a> {
+    pub async fn new(
+        driver: &'a mut dyn AsyncValuerDriver,
+        cfg: &'a cfg::Config,
+    ) -> anyhow::Result<AsyncSimpleValuer<'a>> {
+        let problem_info = driver
+            .problem_info()
+            .await
+            .context("failed to query problem info")?;
+        let state = ValuerState::new(cfg, &problem_info)?;
+        Ok(AsyncSimpleValuer { driver, state })
+    }
 
-        Ok(false)
+    /// Executes one iteration.
+    /// Returns false when valuing finishes.
+    async fn step(&mut self) -> anyhow::Result<bool> {
+        debug!("Running next step");
+        match self.state.poll_fibers() {
+            FibersPoll::Send(response) => {
+                self.driver
+                    .send_command(&response)
+                    .await
+                    .context("failed to send valuer response")?;
+                return Ok(true);
+            }
+            FibersPoll::Progress => return Ok(true),
+            FibersPoll::Idle => {}
+        }
+        if let Some(notification) = self
+            .driver
+            .poll_notification()
+            .await
+            .context("failed to poll for notification")?
+        {
+            self.state.process_notification(notification);
+            return Ok(true);
+        }
+        Ok(self.state.is_running())
     }
 
     /// Runs to valuing completion
-    pub fn exec(mut self) -> anyhow::Result<()> {
+    pub async fn exec(self) -> anyhow::Result<()> {
+        self.exec_with_log().await.map(drop)
+    }
+
+    /// Runs to valuing completion and returns full judge log.
+    /// Judge logs are still sent to the driver.
+    pub async fn exec_with_log(mut self) -> anyhow::Result<JudgeLog> {
+        let deadline = self.state.deadline();
+        if let Some(deadline) = deadline {
+            self.driver.set_deadline(deadline);
+        }
         loop {
-            let should_run = self.step()?;
-            if !should_run {
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                for response in self.state.finish_timed_out() {
+                    self.driver
+                        .send_command(&response)
+                        .await
+                        .context("failed to submit judge log")?;
+                }
+                break;
+            }
+            if !self.step().await? {
                 break;
             }
         }
-        self.driver.send_command(&ValuerResponse::Finish)
+        self.driver.send_command(&ValuerResponse::Finish).await?;
+        self.state.into_judge_log()
     }
+}
 
-    fn process_notification(&mut self, notification: TestDoneNotification) {
-        assert_ne!(self.running_tests, 0);
-        self.running_tests -= 1;
-        for fiber in self.fibers.iter_mut() {
-            fiber.add(&notification);
+/// Driver, which replays recorded test results. Tests without recorded
+/// result are reported with `fallback` status.
+#[derive(Debug)]
+struct ReplayDriver {
+    problem_info: Option<ProblemInfo>,
+    recorded: HashMap<TestId, TestDoneNotification>,
+    fallback: Status,
+    pending_tests: VecDeque<TestId>,
+}
+
+impl ReplayDriver {
+    fn new(
+        problem_info: ProblemInfo,
+        recorded: Vec<TestDoneNotification>,
+        fallback: Status,
+    ) -> ReplayDriver {
+        ReplayDriver {
+            problem_info: Some(problem_info),
+            recorded: recorded
+                .into_iter()
+                .map(|notification| (notification.test_id, notification))
+                .collect(),
+            fallback,
+            pending_tests: VecDeque::new(),
+        }
+    }
+}
+
+impl ValuerDriver for ReplayDriver {
+    fn problem_info(&mut self) -> Result<ProblemInfo> {
+        self.problem_info
+            .take()
+            .context("problem info requested twice")
+    }
+
+    fn send_command(&mut self, cmd: &ValuerResponse) -> Result<()> {
+        if let ValuerResponse::Test { test_id, .. } = cmd {
+            self.pending_tests.push_back(*test_id);
         }
+        Ok(())
+    }
+
+    fn poll_notification(&mut self) -> Result<Option<TestDoneNotification>> {
+        let test_id = match self.pending_tests.pop_front() {
+            Some(test_id) => test_id,
+            None => return Ok(None),
+        };
+        let notification = match self.recorded.remove(&test_id) {
+            Some(notification) => notification,
+            None => TestDoneNotification {
+                test_id,
+                test_status: self.fallback.clone(),
+                checker_comment: None,
+            },
+        };
+        Ok(Some(notification))
     }
 }
 
+/// Values run, which passes all tests, and returns its full judge log.
+/// Its score is the maximal score achievable with given config.
+pub fn simulate_max_score(cfg: &cfg::Config, problem_info: ProblemInfo) -> Result<JudgeLog> {
+    let mut driver = ReplayDriver::new(problem_info, Vec::new(), status_util::make_ok_status());
+    SimpleValuer::new(&mut driver, cfg)?.exec_with_log()
+}
+
+/// Recomputes full judge log of a run from its recorded test results,
+/// e.g. after valuer config changed. Tests that were not run originally
+/// (but are requested now) are reported as skipped.
+pub fn rescore(
+    cfg: &cfg::Config,
+    problem_info: ProblemInfo,
+    recorded: Vec<TestDoneNotification>,
+) -> Result<JudgeLog> {
+    let mut driver = ReplayDriver::new(
+        problem_info,
+        recorded,
+        status_util::make_status(valuer_api::StatusKind::Skipped),
+    );
+    SimpleValuer::new(&mut driver, cfg)?.exec_with_log()
+}
+
 pub mod status_util {
-    use valuer_api::{Status, StatusKind};
+    use valuer_api::{Status, StatusKind, FULL_SCORE_FRACTION};
     pub fn make_ok_status() -> Status {
+        make_status(StatusKind::Accepted)
+    }
+
+    pub fn make_err_status() -> Status {
+        make_status(StatusKind::Rejected)
+    }
+
+    /// Creates status of given kind.
+    /// Status code is `OK` for accepted tests, `NOT_OK` for rejected tests,
+    /// and kind name otherwise.
+    pub fn make_status(kind: StatusKind) -> Status {
+        let code = match kind {
+            StatusKind::Accepted => "OK".to_string(),
+            StatusKind::Rejected => "NOT_OK".to_string(),
+            other => other.to_string(),
+        };
         Status {
-            code: "OK".to_string(),
-            kind: StatusKind::Accepted,
+            code,
+            kind,
+            partial_score: None,
         }
     }
 
-    pub fn make_err_status() -> Status {
+    /// Creates status for a test, which was awarded `fraction` of its score
+    /// (in millionths, see `valuer_api::FULL_SCORE_FRACTION`)
+    pub fn make_partial_status(fraction: u32) -> Status {
+        let kind = if fraction >= FULL_SCORE_FRACTION {
+            StatusKind::Accepted
+        } else {
+            StatusKind::Rejected
+        };
         Status {
-            code: "NOT_OK".to_string(),
-            kind: StatusKind::Rejected,
+            code: "PARTIAL".to_string(),
+            kind,
+            partial_score: Some(fraction.min(FULL_SCORE_FRACTION)),
         }
     }
 }
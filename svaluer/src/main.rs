@@ -1,14 +1,56 @@
 //! Simple valuer
 use anyhow::Context;
+use clap::Clap;
 use log::debug;
 use pom::TestId;
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Simple valuer.
+/// When launched by JJS invoker (`JJS_VALUER` is set), communicates using JSON.
+/// Otherwise, test statuses are read interactively, which is useful for testing valuer config.
+#[derive(Clap, Debug)]
+#[clap(author, version)]
+struct Args {
+    /// Path to valuer config.
+    /// See `read_config` for other ways to provide config.
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Path to JSON file with problem info (CLI mode only).
+    /// If not specified, problem info is read interactively.
+    #[clap(long)]
+    problem_info: Option<PathBuf>,
+    /// Print maximal achievable score and its distribution across groups,
+    /// instead of valuing. Requires `--problem-info`.
+    #[clap(long)]
+    max_score: bool,
+    /// Re-score recorded runs instead of valuing. Directory must contain
+    /// `*.jsonl` files with test results of runs (one `TestDoneNotification`
+    /// per line). New judge log of each run is written next to its file as
+    /// `<run>.judge-log.json`. Requires `--problem-info`.
+    #[clap(long)]
+    rescore: Option<PathBuf>,
+    /// Check config for mistakes (e.g. cyclic group dependencies) and exit.
+    /// If `--problem-info` is specified, groups are checked against tests.
+    #[clap(long)]
+    lint: bool,
+    /// In JSON mode, communicate with invoker on tokio runtime instead of
+    /// dedicated thread (see `svaluer::AsyncJsonDriver`)
+    #[clap(long)]
+    async_driver: bool,
+}
 
 /// CLI-based driver, useful for manual testing valuer config
 #[derive(Debug)]
 struct TermDriver {
     current_tests: HashSet<TestId>,
     full_judge_log: Option<valuer_api::JudgeLog>,
+    /// If set, used instead of asking user
+    problem_info: Option<valuer_api::ProblemInfo>,
+    score_format: svaluer::cfg::ScoreFormat,
 }
 
 mod term_driver {
@@ -19,6 +61,61 @@ mod term_driver {
         io::{stdin, stdout, Write},
         str::FromStr,
     };
+    use svaluer::status_util::{make_partial_status, make_status};
+    use valuer_api::{StatusKind, FULL_SCORE_FRACTION};
+
+    /// Test verdict, entered by user.
+    /// Either status kind name (e.g. `Rejected`), boolean, or awarded
+    /// score fraction (e.g. `0.5`), optionally followed by checker message
+    /// (e.g. `false wrong answer on line 3`)
+    struct Verdict(valuer_api::Status, Option<String>);
+
+    #[derive(Debug)]
+    struct UnknownVerdictError(String);
+
+    impl std::fmt::Display for UnknownVerdictError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(
+                f,
+                "unknown verdict {}, expected true, false, score fraction or one of: \
+                 Accepted, Rejected, CompilationError, InternalError, Skipped",
+                self.0
+            )
+        }
+    }
+
+    impl std::error::Error for UnknownVerdictError {}
+
+    fn parse_status(s: &str) -> Result<valuer_api::Status, UnknownVerdictError> {
+        match s {
+            "true" => return Ok(make_status(StatusKind::Accepted)),
+            "false" => return Ok(make_status(StatusKind::Rejected)),
+            _ => (),
+        }
+        if let Ok(kind) = s.parse() {
+            return Ok(make_status(kind));
+        }
+        match s.parse::<f64>() {
+            Ok(fraction) if (0.0..=1.0).contains(&fraction) => {
+                let fraction = (fraction * f64::from(FULL_SCORE_FRACTION)).round() as u32;
+                Ok(make_partial_status(fraction))
+            }
+            _ => Err(UnknownVerdictError(s.to_string())),
+        }
+    }
+
+    impl FromStr for Verdict {
+        type Err = UnknownVerdictError;
+
+        fn from_str(s: &str) -> Result<Verdict, UnknownVerdictError> {
+            let (status, message) = match s.split_once(char::is_whitespace) {
+                Some((status, message)) => (status, Some(message.trim().to_string())),
+                None => (s, None),
+            };
+            Ok(Verdict(parse_status(status)?, message))
+        }
+    }
+
     fn read_value<T: FromStr>(what: impl AsRef<str>) -> Result<T>
     where
         <T as FromStr>::Err: std::error::Error,
@@ -28,9 +125,12 @@ mod term_driver {
             print!("{}> ", what.as_ref());
             stdout().flush()?;
             user_input.clear();
-            stdin()
+            let cnt = stdin()
                 .read_line(&mut user_input)
                 .context("failed to read line")?;
+            if cnt == 0 {
+                anyhow::bail!("unexpected end of input");
+            }
             let user_input = user_input.trim();
             match user_input.parse() {
                 // These are different Ok's: one is anyhow::Result::Ok, other is Result<.., <T as FromStr>::Err>>
@@ -45,13 +145,19 @@ mod term_driver {
 
     impl svaluer::ValuerDriver for TermDriver {
         fn problem_info(&mut self) -> Result<valuer_api::ProblemInfo> {
+            if let Some(info) = self.problem_info.take() {
+                return Ok(info);
+            }
             let test_count = read_value("test count")?;
             let mut tests = Vec::new();
             for i in 1..=test_count {
                 let group = read_value(format!("group test #{} belongs to", i))?;
                 tests.push(group);
             }
-            let info = valuer_api::ProblemInfo { tests };
+            let info = valuer_api::ProblemInfo {
+                tests,
+                valuer_version: None,
+            };
             Ok(info)
         }
 
@@ -61,7 +167,12 @@ mod term_driver {
                     let judge_log = self.full_judge_log.take().expect("full judge log missing");
 
                     println!("Judging finished");
-                    println!("Score: {}", judge_log.score);
+                    println!("Score: {}", self.score_format.format(judge_log.score));
+                    for subtask in &judge_log.subtasks {
+                        if !subtask.comment.is_empty() {
+                            println!("Group {}: {}", subtask.subtask_id.0, subtask.comment);
+                        }
+                    }
                     if judge_log.is_full {
                         println!("Full solution");
                     } else {
@@ -69,7 +180,7 @@ mod term_driver {
                     }
                 }
                 valuer_api::ValuerResponse::LiveScore { score } => {
-                    println!("Current score: {}", *score);
+                    println!("Current score: {}", self.score_format.format(*score));
                 }
                 valuer_api::ValuerResponse::Test { test_id, live } => {
                     println!("Run should be executed on test {}", test_id.get());
@@ -79,28 +190,24 @@ mod term_driver {
                     let not_dup = self.current_tests.insert(*test_id);
                     assert!(not_dup);
                 }
-                valuer_api::ValuerResponse::JudgeLog { .. } => {
+                valuer_api::ValuerResponse::JudgeLog(judge_log) => {
                     // TODO print judge log
+                    if judge_log.kind == valuer_api::JudgeLogKind::Full {
+                        self.full_judge_log = Some(judge_log.clone());
+                    }
                 }
             }
             Ok(())
         }
 
         fn poll_notification(&mut self) -> Result<Option<valuer_api::TestDoneNotification>> {
-            fn create_status(ok: bool) -> valuer_api::Status {
-                if ok {
-                    svaluer::status_util::make_ok_status()
-                } else {
-                    svaluer::status_util::make_err_status()
-                }
-            }
-
             fn read_status(tid: TestId) -> Result<valuer_api::TestDoneNotification> {
-                let outcome = read_value(format!("test {} status", tid.get()))?;
-                let test_status = create_status(outcome);
+                let Verdict(test_status, checker_comment) =
+                    read_value(format!("test {} status [message]", tid.get()))?;
                 Ok(valuer_api::TestDoneNotification {
                     test_id: tid,
                     test_status,
+                    checker_comment,
                 })
             }
             match self.current_tests.len() {
@@ -136,6 +243,7 @@ mod json_driver {
     use serde::Deserialize;
     use std::{
         io::Write,
+        path::PathBuf,
         time::{Duration, Instant},
     };
     use svaluer::ValuerDriver;
@@ -143,6 +251,9 @@ mod json_driver {
     #[derive(Debug)]
     pub struct JsonDriver {
         chan: crossbeam_channel::Receiver<Message>,
+        /// If set, full judge log is additionally written to this file when judging finishes
+        judge_log_out: Option<PathBuf>,
+        full_judge_log: Option<valuer_api::JudgeLog>,
     }
     #[derive(Deserialize)]
     #[serde(untagged)]
@@ -181,7 +292,25 @@ mod json_driver {
             std::thread::spawn(move || {
                 json_driver_thread_func(send);
             });
-            Self { chan: recv }
+            Self {
+                chan: recv,
+                judge_log_out: std::env::var_os("JJS_VALUER_JUDGE_LOG_OUT").map(PathBuf::from),
+                full_judge_log: None,
+            }
+        }
+
+        fn write_judge_log(&mut self) -> Result<()> {
+            let path = match &self.judge_log_out {
+                Some(p) => p,
+                None => return Ok(()),
+            };
+            let judge_log = self
+                .full_judge_log
+                .take()
+                .context("full judge log was not emitted")?;
+            let data = serde_json::to_string(&judge_log).context("failed to serialize")?;
+            std::fs::write(path, data)
+                .with_context(|| format!("failed to write judge log to {}", path.display()))
         }
 
         fn poll(&mut self) -> Option<Message> {
@@ -213,6 +342,15 @@ mod json_driver {
         }
 
         fn send_command(&mut self, cmd: &valuer_api::ValuerResponse) -> Result<()> {
+            match cmd {
+                valuer_api::ValuerResponse::JudgeLog(judge_log)
+                    if judge_log.kind == valuer_api::JudgeLogKind::Full =>
+                {
+                    self.full_judge_log = Some(judge_log.clone());
+                }
+                valuer_api::ValuerResponse::Finish => self.write_judge_log()?,
+                _ => (),
+            }
             let cmd = serde_json::to_string(cmd).context("failed to serialize")?;
             println!("{}", cmd);
             std::io::stdout().flush().context("failed to flush")?;
@@ -231,25 +369,194 @@ mod json_driver {
     }
 }
 
-fn parse_config() -> anyhow::Result<svaluer::cfg::Config> {
-    let path = std::path::Path::new("cfg.yaml");
-    let data = std::fs::read_to_string(path).context("failed to read cfg.yaml")?;
-    Ok(serde_yaml::from_str(&data).context("failed to parse config")?)
+const DEFAULT_CONFIG_PATH: &str = "cfg.yaml";
+
+fn read_file(path: &Path) -> anyhow::Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Reads valuer config. Sources are tried in the following order:
+/// 1. `--config` argument.
+/// 2. `JJS_VALUER_CONFIG` environment variable. If it is path to existing file,
+/// config is read from that file, otherwise variable value is config itself.
+/// 3. `cfg.yaml` in current directory.
+/// 4. stdin, until EOF. This source is not available in JSON mode, because
+/// stdin is used to communicate with invoker.
+fn read_config(args: &Args, allow_stdin: bool) -> anyhow::Result<String> {
+    if let Some(path) = &args.config {
+        return read_file(path);
+    }
+    if let Some(cfg) = std::env::var_os("JJS_VALUER_CONFIG") {
+        let path = Path::new(&cfg);
+        if path.is_file() {
+            debug!("Reading config from {}", path.display());
+            return read_file(path);
+        }
+        debug!("Using inline config from JJS_VALUER_CONFIG");
+        return cfg
+            .into_string()
+            .map_err(|_| anyhow::anyhow!("JJS_VALUER_CONFIG is not utf8"));
+    }
+    let default_path = Path::new(DEFAULT_CONFIG_PATH);
+    if default_path.exists() {
+        return read_file(default_path);
+    }
+    if !allow_stdin {
+        anyhow::bail!(
+            "valuer config not found: --config, JJS_VALUER_CONFIG and {} are missing",
+            DEFAULT_CONFIG_PATH
+        );
+    }
+    debug!("Reading config from stdin");
+    let mut data = String::new();
+    std::io::stdin()
+        .read_to_string(&mut data)
+        .context("failed to read config from stdin")?;
+    Ok(data)
+}
+
+fn parse_config(args: &Args, allow_stdin: bool) -> anyhow::Result<svaluer::cfg::Config> {
+    let data = read_config(args, allow_stdin)?;
+    let mut cfg: svaluer::cfg::Config =
+        serde_yaml::from_str(&data).context("failed to parse config")?;
+    if let Some(timeout) = std::env::var_os("JJS_VALUER_SESSION_TIMEOUT") {
+        let timeout = timeout
+            .to_str()
+            .and_then(|timeout| timeout.parse().ok())
+            .context("JJS_VALUER_SESSION_TIMEOUT must be number of seconds")?;
+        cfg.session_timeout = Some(timeout);
+    }
+    Ok(cfg)
 }
 
-fn main_cli_mode() -> anyhow::Result<()> {
+fn read_problem_info(path: &Path) -> anyhow::Result<valuer_api::ProblemInfo> {
+    let data = read_file(path)?;
+    serde_json::from_str(&data).context("failed to parse problem info")
+}
+
+fn main_cli_mode(args: &Args) -> anyhow::Result<()> {
+    let problem_info = match &args.problem_info {
+        Some(path) => Some(read_problem_info(path)?),
+        None => None,
+    };
+    let cfg = parse_config(args, true)?;
     let mut driver = TermDriver {
         current_tests: HashSet::new(),
         full_judge_log: None,
+        problem_info,
+        score_format: cfg.score_format,
     };
-    let cfg = parse_config()?;
     let valuer = svaluer::SimpleValuer::new(&mut driver, &cfg)?;
     valuer.exec()
 }
 
-fn main_json_mode() -> anyhow::Result<()> {
+fn main_max_score_mode(args: &Args) -> anyhow::Result<()> {
+    let path = args
+        .problem_info
+        .as_ref()
+        .context("--max-score requires --problem-info")?;
+    let problem_info = read_problem_info(path)?;
+    let cfg = parse_config(args, true)?;
+    let judge_log = svaluer::simulate_max_score(&cfg, problem_info)?;
+    for subtask in &judge_log.subtasks {
+        let idx = subtask.subtask_id.0.get() as usize - 1;
+        let name = cfg.groups.get(idx).map_or("?", |g| g.name.as_str());
+        println!(
+            "Group {} ({}): {}",
+            subtask.subtask_id.0,
+            name,
+            cfg.score_format.format(subtask.score)
+        );
+    }
+    println!("Max score: {}", cfg.score_format.format(judge_log.score));
+    Ok(())
+}
+
+/// Reads recorded test results of one run
+fn read_recorded_run(path: &Path) -> anyhow::Result<Vec<valuer_api::TestDoneNotification>> {
+    read_file(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}: invalid notification #{}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+fn main_rescore_mode(args: &Args, runs_dir: &Path) -> anyhow::Result<()> {
+    let path = args
+        .problem_info
+        .as_ref()
+        .context("--rescore requires --problem-info")?;
+    let problem_info = read_problem_info(path)?;
+    let cfg = parse_config(args, true)?;
+    let mut runs = Vec::new();
+    for entry in std::fs::read_dir(runs_dir)
+        .with_context(|| format!("failed to read {}", runs_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension() == Some(std::ffi::OsStr::new("jsonl")) {
+            runs.push(path);
+        }
+    }
+    runs.sort();
+    for run in runs {
+        let recorded = read_recorded_run(&run)?;
+        let judge_log = svaluer::rescore(&cfg, problem_info.clone(), recorded)
+            .with_context(|| format!("failed to re-score {}", run.display()))?;
+        let out_path = run.with_extension("judge-log.json");
+        let data =
+            serde_json::to_string_pretty(&judge_log).context("failed to serialize judge log")?;
+        std::fs::write(&out_path, data)
+            .with_context(|| format!("failed to write {}", out_path.display()))?;
+        println!(
+            "{}: {}",
+            run.file_stem().unwrap_or_default().to_string_lossy(),
+            cfg.score_format.format(judge_log.score)
+        );
+    }
+    Ok(())
+}
+
+fn main_lint_mode(args: &Args) -> anyhow::Result<()> {
+    let problem_info = match &args.problem_info {
+        Some(path) => Some(read_problem_info(path)?),
+        None => None,
+    };
+    let cfg = parse_config(args, true)?;
+    let report = cfg.lint(problem_info.as_ref());
+    for warning in &report.warnings {
+        println!("warning: {}", warning);
+    }
+    for error in &report.errors {
+        println!("error: {}", error);
+    }
+    if !report.errors.is_empty() {
+        anyhow::bail!("config has {} errors", report.errors.len());
+    }
+    Ok(())
+}
+
+fn main_json_mode(args: &Args) -> anyhow::Result<()> {
+    let cfg = parse_config(args, false)?;
+    if args.async_driver {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to create tokio runtime")?;
+        let res = runtime.block_on(async {
+            let mut driver = svaluer::AsyncJsonDriver::stdio();
+            let valuer = svaluer::AsyncSimpleValuer::new(&mut driver, &cfg).await?;
+            valuer.exec().await
+        });
+        // stdin is read on blocking thread, which may still wait for data
+        // (e.g. after session timeout), so runtime is not waited for
+        runtime.shutdown_background();
+        return res;
+    }
     let mut driver = JsonDriver::new();
-    let cfg = parse_config()?;
     let valuer = svaluer::SimpleValuer::new(&mut driver, &cfg)?;
     valuer.exec()
 }
@@ -263,13 +570,23 @@ fn main() -> anyhow::Result<()> {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
+    let args = Args::parse();
     let json_mode = std::env::var("JJS_VALUER").is_ok();
     if json_mode {
         debug!("Mode: JSON");
-        main_json_mode()?
+        main_json_mode(&args)?
+    } else if args.lint {
+        debug!("Mode: lint");
+        main_lint_mode(&args)?
+    } else if args.max_score {
+        debug!("Mode: max score simulation");
+        main_max_score_mode(&args)?
+    } else if let Some(runs_dir) = &args.rescore {
+        debug!("Mode: re-scoring");
+        main_rescore_mode(&args, runs_dir)?
     } else {
         debug!("Mode: CLI");
-        main_cli_mode()?
+        main_cli_mode(&args)?
     }
 
     Ok(())
@@ -11,6 +11,7 @@ struct TestMock {
     test_id: TestId,
     live: bool,
     status: Status,
+    checker_comment: Option<String>,
 }
 
 #[derive(Debug)]
@@ -33,19 +34,31 @@ impl MockDriver {
     }
 
     fn add_test(&mut self, test_id: u32, live: bool, ok: bool) -> &mut Self {
+        let status = if ok {
+            make_ok_status()
+        } else {
+            make_err_status()
+        };
+        self.add_test_with_status(test_id, live, status)
+    }
+
+    fn add_test_with_status(&mut self, test_id: u32, live: bool, status: Status) -> &mut Self {
         let mock = TestMock {
             test_id: TestId::make(test_id),
             live,
-            status: if ok {
-                make_ok_status()
-            } else {
-                make_err_status()
-            },
+            status,
+            checker_comment: None,
         };
         self.tests.push_back(mock);
         self
     }
 
+    fn add_test_with_comment(&mut self, test_id: u32, live: bool, comment: &str) -> &mut Self {
+        self.add_test(test_id, live, false);
+        self.tests.back_mut().unwrap().checker_comment = Some(comment.to_string());
+        self
+    }
+
     fn add_judge_log(&mut self, judge_log: JudgeLog) -> &mut Self {
         self.judge_logs.push(judge_log);
         self
@@ -101,6 +114,7 @@ impl MockDriver {
                 self.pending_notifications.push_back(TestDoneNotification {
                     test_id: mock.test_id,
                     test_status: mock.status,
+                    checker_comment: mock.checker_comment,
                 })
             }
             None => panic!(
@@ -143,12 +157,12 @@ impl MockDriver {
         assert_eq!(&expected, judge_log);
     }
 
-    fn exec(&mut self, cfg: impl AsRef<str>) {
+    fn exec(&mut self, cfg: impl AsRef<str>) -> JudgeLog {
         simple_logger::SimpleLogger::new().init().ok();
         let cfg = cfg.as_ref();
         let cfg = serde_yaml::from_str(cfg).expect("failed to parse config");
         let valuer = SimpleValuer::new(self, &cfg).unwrap();
-        valuer.exec().unwrap();
+        valuer.exec_with_log().unwrap()
     }
 }
 
@@ -196,14 +210,17 @@ mod simple {
                     subtask_id: SubtaskId::make(1),
                     score: 64,
                     components: SubtaskVisibleComponents::SCORE,
+                    comment: String::new(),
                 },
                 JudgeLogSubtaskRow {
                     subtask_id: SubtaskId::make(2),
                     score: 36,
                     components: SubtaskVisibleComponents::SCORE,
+                    comment: String::new(),
                 },
             ],
             score: 100,
+            guaranteed_score: 100,
         };
         let mut contestant_log = full_log.clone();
         contestant_log.kind = JudgeLogKind::Contestant;
@@ -212,12 +229,14 @@ mod simple {
         contestant_log.tests[0].components =
             TestVisibleComponents::STATUS | TestVisibleComponents::RESOURCE_USAGE;
         contestant_log.score = 64;
-        MockDriver::new(ProblemInfo {
+        contestant_log.guaranteed_score = 64;
+        let final_log = MockDriver::new(ProblemInfo {
             tests: vec!["online".to_string(), "offline".to_string()],
+            valuer_version: None,
         })
         .add_test(1, true, true)
         .add_test(2, false, true)
-        .add_judge_log(full_log)
+        .add_judge_log(full_log.clone())
         .add_judge_log(contestant_log)
         .add_live_score(64)
         .exec(
@@ -233,6 +252,7 @@ groups:
       - online
             ",
         );
+        assert_eq!(final_log, full_log);
     }
 
     #[test]
@@ -250,19 +270,23 @@ groups:
                     subtask_id: SubtaskId::make(1),
                     score: 0,
                     components: SubtaskVisibleComponents::all(),
+                    comment: "failed on test 1 (NOT_OK)".to_string(),
                 },
                 JudgeLogSubtaskRow {
                     subtask_id: SubtaskId::make(2),
                     score: 0,
                     components: SubtaskVisibleComponents::all(),
+                    comment: "skipped because required group failed".to_string(),
                 },
             ],
             score: 0,
+            guaranteed_score: 0,
         };
         let mut contestant_log = full_log.clone();
         contestant_log.kind = JudgeLogKind::Contestant;
         MockDriver::new(ProblemInfo {
             tests: vec!["samples".to_string(), "online".to_string()],
+            valuer_version: None,
         })
         .add_test(1, true, false)
         .add_judge_log(full_log)
@@ -282,3 +306,534 @@ groups:
         );
     }
 }
+
+mod checker_comment {
+    use super::*;
+
+    #[test]
+    fn shown_in_group_comment() {
+        let full_log = JudgeLog {
+            is_full: false,
+            kind: JudgeLogKind::Full,
+            tests: vec![
+                JudgeLogTestRow {
+                    test_id: TestId::make(2),
+                    status: make_err_status(),
+                    components: TestVisibleComponents::all(),
+                },
+                JudgeLogTestRow {
+                    test_id: TestId::make(1),
+                    status: make_ok_status(),
+                    components: TestVisibleComponents::all(),
+                },
+            ],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: 0,
+                components: SubtaskVisibleComponents::all(),
+                comment: "failed on test 2 (NOT_OK): wrong answer on line 3".to_string(),
+            }],
+            score: 0,
+            guaranteed_score: 0,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["tests".to_string(), "tests".to_string()],
+            valuer_version: None,
+        })
+        .add_test(1, true, true)
+        .add_test_with_comment(2, true, "wrong answer on line 3\n")
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .exec(
+            "
+groups:
+  - name: tests
+    score: 100
+    feedback: full
+            ",
+        );
+    }
+}
+
+mod open {
+    use super::*;
+
+    #[test]
+    fn closed_group() {
+        let full_log = JudgeLog {
+            is_full: true,
+            kind: JudgeLogKind::Full,
+            tests: vec![JudgeLogTestRow {
+                test_id: TestId::make(1),
+                status: make_ok_status(),
+                components: TestVisibleComponents::all(),
+            }],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: 100,
+                components: SubtaskVisibleComponents::SCORE,
+                comment: String::new(),
+            }],
+            score: 100,
+            guaranteed_score: 100,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        contestant_log.tests[0].components = TestVisibleComponents::RESOURCE_USAGE;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["tests".to_string()],
+            valuer_version: None,
+        })
+        .add_test(1, false, true)
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .add_live_score(100)
+        .exec(
+            "
+groups:
+  - name: tests
+    feedback: brief
+    score: 100
+    open: false
+            ",
+        );
+    }
+}
+
+mod partial {
+    use super::*;
+    use status_util::make_partial_status;
+
+    fn run_partial(scoring: &str, expected_score: u32) {
+        let statuses = vec![make_ok_status(), make_partial_status(500_000)];
+        let full_log = JudgeLog {
+            is_full: false,
+            kind: JudgeLogKind::Full,
+            tests: vec![
+                JudgeLogTestRow {
+                    test_id: TestId::make(2),
+                    status: statuses[1].clone(),
+                    components: TestVisibleComponents::all(),
+                },
+                JudgeLogTestRow {
+                    test_id: TestId::make(1),
+                    status: statuses[0].clone(),
+                    components: TestVisibleComponents::all(),
+                },
+            ],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: expected_score,
+                components: SubtaskVisibleComponents::all(),
+                comment: "failed on test 2 (PARTIAL)".to_string(),
+            }],
+            score: expected_score,
+            guaranteed_score: expected_score,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["tests".to_string(), "tests".to_string()],
+            valuer_version: None,
+        })
+        .add_test_with_status(1, true, statuses[0].clone())
+        .add_test_with_status(2, true, statuses[1].clone())
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .add_live_score(expected_score)
+        .exec(format!(
+            "
+groups:
+  - name: tests
+    feedback: full
+    score: 100
+    run_to_first_failure: false
+    scoring: {}
+            ",
+            scoring
+        ));
+    }
+
+    #[test]
+    fn sum() {
+        run_partial("sum", 75);
+    }
+
+    #[test]
+    fn min() {
+        run_partial("min", 50);
+    }
+}
+
+mod stop_on_fail {
+    use super::*;
+    use status_util::make_partial_status;
+    use valuer_api::FULL_SCORE_FRACTION;
+
+    #[test]
+    fn partial_test_stops_group() {
+        let full_log = JudgeLog {
+            is_full: false,
+            kind: JudgeLogKind::Full,
+            tests: vec![
+                JudgeLogTestRow {
+                    test_id: TestId::make(2),
+                    status: make_partial_status(FULL_SCORE_FRACTION / 2),
+                    components: TestVisibleComponents::all(),
+                },
+                JudgeLogTestRow {
+                    test_id: TestId::make(1),
+                    status: make_ok_status(),
+                    components: TestVisibleComponents::all(),
+                },
+                JudgeLogTestRow {
+                    test_id: TestId::make(3),
+                    status: status_util::make_status(valuer_api::StatusKind::Skipped),
+                    components: TestVisibleComponents::all(),
+                },
+            ],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: 0,
+                components: SubtaskVisibleComponents::all(),
+                comment: "failed on test 2 (PARTIAL)".to_string(),
+            }],
+            score: 0,
+            guaranteed_score: 0,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["tests".to_string(); 3],
+            valuer_version: None,
+        })
+        .add_test(1, true, true)
+        .add_test_with_status(2, true, make_partial_status(FULL_SCORE_FRACTION / 2))
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .exec(
+            "
+groups:
+  - name: tests
+    feedback: full
+    score: 100
+    scoring: sum
+    stop_on_fail: true
+            ",
+        );
+    }
+}
+
+mod max_score {
+    use super::*;
+
+    fn run_capped(max_score: u32, expected_score: u32, is_full: bool) {
+        let full_log = JudgeLog {
+            is_full,
+            kind: JudgeLogKind::Full,
+            tests: vec![JudgeLogTestRow {
+                test_id: TestId::make(1),
+                status: make_ok_status(),
+                components: TestVisibleComponents::all(),
+            }],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: expected_score,
+                components: SubtaskVisibleComponents::all(),
+                comment: String::new(),
+            }],
+            score: expected_score,
+            guaranteed_score: expected_score,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["tests".to_string()],
+            valuer_version: None,
+        })
+        .add_test(1, true, true)
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .add_live_score(expected_score)
+        .exec(format!(
+            "
+groups:
+  - name: tests
+    feedback: full
+    score: 100
+    max_score: {}
+            ",
+            max_score
+        ));
+    }
+
+    #[test]
+    fn reduces_score() {
+        run_capped(60, 60, false);
+    }
+
+    #[test]
+    fn does_not_reduce_score() {
+        run_capped(100, 100, true);
+    }
+}
+
+mod live_score {
+    use super::*;
+
+    #[test]
+    fn running() {
+        let full_log = JudgeLog {
+            is_full: true,
+            kind: JudgeLogKind::Full,
+            tests: vec![
+                JudgeLogTestRow {
+                    test_id: TestId::make(1),
+                    status: make_ok_status(),
+                    components: TestVisibleComponents::all(),
+                },
+                JudgeLogTestRow {
+                    test_id: TestId::make(2),
+                    status: make_ok_status(),
+                    components: TestVisibleComponents::all(),
+                },
+            ],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: 100,
+                components: SubtaskVisibleComponents::all(),
+                comment: String::new(),
+            }],
+            score: 100,
+            guaranteed_score: 100,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["tests".to_string(), "tests".to_string()],
+            valuer_version: None,
+        })
+        .add_test(1, true, true)
+        .add_test(2, true, true)
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .add_live_score(50)
+        .add_live_score(100)
+        .exec(
+            "
+live-score: running
+groups:
+  - name: tests
+    feedback: full
+    score: 100
+            ",
+        );
+    }
+}
+
+mod status {
+    use super::*;
+    use valuer_api::StatusKind;
+
+    #[test]
+    fn make_status() {
+        assert_eq!(
+            status_util::make_status(StatusKind::Accepted),
+            make_ok_status()
+        );
+        assert_eq!(
+            status_util::make_status(StatusKind::Rejected),
+            make_err_status()
+        );
+        let status = status_util::make_status(StatusKind::InternalError);
+        assert_eq!(status.kind, StatusKind::InternalError);
+        assert!(!status.kind.is_success());
+    }
+}
+
+mod simulation {
+    use super::*;
+
+    #[test]
+    fn max_score() {
+        let cfg = serde_yaml::from_str(
+            "
+groups:
+  - name: samples
+    feedback: full
+    score: 0
+  - name: main
+    feedback: brief
+    score: 70
+    max_score: 60
+    deps:
+      - samples
+  - name: extra
+    feedback: hidden
+    score: 40
+            ",
+        )
+        .unwrap();
+        let problem_info = ProblemInfo {
+            tests: vec![
+                "samples".to_string(),
+                "main".to_string(),
+                "main".to_string(),
+                "extra".to_string(),
+            ],
+            valuer_version: None,
+        };
+        let judge_log = simulate_max_score(&cfg, problem_info).unwrap();
+        assert_eq!(judge_log.kind, JudgeLogKind::Full);
+        assert_eq!(judge_log.score, 100);
+        let scores: Vec<_> = judge_log.subtasks.iter().map(|s| s.score).collect();
+        assert_eq!(scores, [0, 60, 40]);
+    }
+    #[test]
+    fn rescore() {
+        let cfg = serde_yaml::from_str(
+            "
+groups:
+  - name: main
+    feedback: full
+    score: 100
+    scoring: sum
+    run_to_first_failure: false
+  - name: extra
+    feedback: full
+    score: 50
+            ",
+        )
+        .unwrap();
+        let problem_info = ProblemInfo {
+            tests: vec!["main".to_string(), "main".to_string(), "extra".to_string()],
+            valuer_version: None,
+        };
+        let recorded = vec![
+            TestDoneNotification {
+                test_id: TestId::make(1),
+                test_status: make_ok_status(),
+                checker_comment: None,
+            },
+            TestDoneNotification {
+                test_id: TestId::make(2),
+                test_status: make_err_status(),
+                checker_comment: None,
+            },
+        ];
+        let judge_log = crate::rescore(&cfg, problem_info, recorded).unwrap();
+        assert_eq!(judge_log.score, 50);
+        let scores: Vec<_> = judge_log.subtasks.iter().map(|s| s.score).collect();
+        assert_eq!(scores, [50, 0]);
+        // test 3 was not recorded
+        let extra_status = &judge_log.tests.last().unwrap().status;
+        assert_eq!(extra_status.kind, valuer_api::StatusKind::Skipped);
+    }
+}
+
+mod version {
+    use super::*;
+
+    #[test]
+    fn compatibility() {
+        check_version_compatible(VERSION).unwrap();
+        check_version_compatible("1000.0.0").unwrap_err();
+        check_version_compatible("garbage").unwrap_err();
+    }
+}
+
+mod session_timeout {
+    use super::*;
+
+    #[test]
+    fn expired() {
+        let full_log = JudgeLog {
+            is_full: false,
+            kind: JudgeLogKind::Full,
+            tests: vec![],
+            subtasks: vec![JudgeLogSubtaskRow {
+                subtask_id: SubtaskId::make(1),
+                score: 0,
+                components: SubtaskVisibleComponents::all(),
+                comment: "not finished: valuing session timed out".to_string(),
+            }],
+            score: 0,
+            guaranteed_score: 0,
+        };
+        let mut contestant_log = full_log.clone();
+        contestant_log.kind = JudgeLogKind::Contestant;
+        MockDriver::new(ProblemInfo {
+            tests: vec!["main".to_string()],
+            valuer_version: None,
+        })
+        .add_judge_log(full_log)
+        .add_judge_log(contestant_log)
+        .exec(
+            "
+session-timeout: 0
+groups:
+  - name: main
+    score: 100
+    feedback: full
+            ",
+        );
+    }
+}
+
+mod async_valuer {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_on_caller_runtime() {
+        let problem_info = ProblemInfo {
+            tests: vec!["main".to_string()],
+            valuer_version: None,
+        };
+        let notification = TestDoneNotification {
+            test_id: TestId::make(1),
+            test_status: make_ok_status(),
+            checker_comment: None,
+        };
+        let mut input = String::new();
+        for message in &[
+            serde_json::to_string(&problem_info).unwrap(),
+            serde_json::to_string(&notification).unwrap(),
+        ] {
+            input.push_str(message);
+            input.push('\n');
+        }
+        let cfg = serde_yaml::from_str(
+            "
+groups:
+  - name: main
+    score: 100
+    feedback: full
+            ",
+        )
+        .unwrap();
+        let mut driver =
+            AsyncJsonDriver::new(tokio::io::BufReader::new(input.as_bytes()), Vec::new());
+        let judge_log = AsyncSimpleValuer::new(&mut driver, &cfg)
+            .await
+            .unwrap()
+            .exec_with_log()
+            .await
+            .unwrap();
+        assert_eq!(judge_log.score, 100);
+        let output = String::from_utf8(driver.into_output()).unwrap();
+        let responses: Vec<ValuerResponse> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            responses.first(),
+            Some(&ValuerResponse::Test {
+                test_id: TestId::make(1),
+                live: true,
+            })
+        );
+        assert_eq!(responses.last(), Some(&ValuerResponse::Finish));
+    }
+}
@@ -72,10 +72,28 @@ pub mod status_codes {
     declare_code!(ACCEPTED, PARTIAL_SOLUTION, BUILD_ERROR);
 }
 
+/// Score fraction, corresponding to full test score
+pub const FULL_SCORE_FRACTION: u32 = 1_000_000;
+
 #[derive(Clone, Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Hash)]
 pub struct Status {
     pub kind: StatusKind,
     pub code: String,
+    /// Fraction of test score awarded (e.g. by checker), in millionths.
+    /// If none, test is scored fully when accepted and is not scored otherwise.
+    #[serde(default)]
+    pub partial_score: Option<u32>,
+}
+
+impl Status {
+    /// Returns fraction of test score awarded, in range 0..=`FULL_SCORE_FRACTION`
+    pub fn score_fraction(&self) -> u32 {
+        match self.partial_score {
+            Some(fraction) => fraction.min(FULL_SCORE_FRACTION),
+            None if self.kind.is_success() => FULL_SCORE_FRACTION,
+            None => 0,
+        }
+    }
 }
 
 bitflags! {
@@ -123,6 +141,10 @@ pub struct JudgeLogSubtaskRow {
     pub subtask_id: SubtaskId,
     pub score: u32,
     pub components: SubtaskVisibleComponents,
+    /// Human-readable explanation of the score (e.g. first failed test).
+    /// Empty for fully passed subtasks.
+    #[serde(default)]
+    pub comment: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
@@ -156,6 +178,10 @@ pub struct JudgeLog {
     pub tests: Vec<JudgeLogTestRow>,
     pub subtasks: Vec<JudgeLogSubtaskRow>,
     pub score: u32,
+    /// Part of `score`, which is awarded by groups that were fully
+    /// decided (i.e. their score can not change anymore)
+    #[serde(default)]
+    pub guaranteed_score: u32,
     pub is_full: bool,
 }
 
@@ -166,6 +192,7 @@ impl Default for JudgeLog {
             tests: Vec::new(),
             subtasks: Vec::new(),
             score: 0,
+            guaranteed_score: 0,
             is_full: false,
         }
     }
@@ -174,12 +201,19 @@ impl Default for JudgeLog {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProblemInfo {
     pub tests: Vec<String>,
+    /// Valuer version recorded in the problem package, if known.
+    /// Valuer refuses to work if it is incompatible.
+    #[serde(default)]
+    pub valuer_version: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct TestDoneNotification {
     pub test_id: TestId,
     pub test_status: Status,
+    /// Message, emitted by checker (e.g. `wrong answer on line 3`)
+    #[serde(default)]
+    pub checker_comment: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]